@@ -0,0 +1,77 @@
+//! Benchmarks of the core rendering functions on a synthetic grid.
+//!
+//! The grid side defaults to 1024 cells and can be changed with `DEM_BENCH_SIZE`, e.g.
+//! `DEM_BENCH_SIZE=4096 cargo bench`. Throughput is reported in cells per second, so
+//! `Melem/s` reads as megapixels per second.
+
+use clap::Parser;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use dem::cli::Args;
+use dem::grid::Grid;
+use dem::pipeline::render;
+use dem::sink::MemorySink;
+use dem::{compute_gradients, hill_shading, progress, rgb};
+
+/// Side of the square benchmark grid when `DEM_BENCH_SIZE` is not set.
+const DEFAULT_SIZE: u32 = 1024;
+
+/// Reads the grid side from `DEM_BENCH_SIZE`.
+fn bench_size() -> u32 {
+    std::env::var("DEM_BENCH_SIZE").ok().and_then(|size| size.parse().ok()).unwrap_or(DEFAULT_SIZE)
+}
+
+/// Builds a square grid of rolling hills, large enough for the 61-cell gradient window.
+fn synthetic_grid(size: u32) -> Grid {
+    let size = size.max(64);
+    let data = (0..size * size)
+        .map(|i| {
+            let (x, y) = ((i % size) as f32, (i / size) as f32);
+            (x / 37.0).sin() * 40.0 + (y / 23.0).cos() * 25.0 + (x + y) * 0.05
+        })
+        .collect();
+    Grid { data, width: size, height: size, cellsize: 2.0, cellsize_y: 2.0, xllcorner: 0.0, yllcorner: 0.0 }
+}
+
+fn core_functions(c: &mut Criterion) {
+    progress::set_quiet(true);
+    let grid = synthetic_grid(bench_size());
+    let (width, height, cellsize) = (grid.width, grid.height, grid.cellsize);
+    let colors = rgb(grid.data.clone(), width, height, None, None).unwrap();
+
+    let mut group = c.benchmark_group(format!("{}x{}", width, height));
+    group.throughput(Throughput::Elements(width as u64 * height as u64));
+    group.sample_size(10);
+    group.bench_function("rgb", |b| {
+        b.iter_batched(|| grid.data.clone(), |data| rgb(data, width, height, None, None).unwrap(), BatchSize::LargeInput)
+    });
+    group.bench_function("hill_shading", |b| {
+        b.iter_batched(
+            || colors.clone(),
+            |colors| hill_shading(&grid.data, colors, width, height, (cellsize, cellsize), 315.0, 45.0, 0.0),
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("compute_gradients", |b| b.iter(|| compute_gradients(&grid.data, width, height, 61)));
+    group.finish();
+}
+
+fn end_to_end(c: &mut Criterion) {
+    progress::set_quiet(true);
+    let grid = synthetic_grid(bench_size());
+    let args = Args::parse_from(["dem"]);
+
+    let mut group = c.benchmark_group(format!("{}x{}", grid.width, grid.height));
+    group.throughput(Throughput::Elements(grid.width as u64 * grid.height as u64));
+    group.sample_size(10);
+    group.bench_function("pipeline", |b| {
+        b.iter(|| {
+            let mut sink = MemorySink::new();
+            render(&grid, &args, "bench", &mut sink).unwrap();
+            sink
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, core_functions, end_to_end);
+criterion_main!(benches);
@@ -0,0 +1,148 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, bail, Context, Result};
+use colorgrad::Gradient;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, Rgba, RgbaImage};
+use log::{debug, info};
+
+use crate::cli::Args;
+use crate::color::gradient_by_name;
+use crate::grid::{flip_vertical, Grid};
+use crate::pipeline;
+use crate::preprocess::mask_values;
+use crate::{elevation_range, rgb_with};
+
+/// Lowest and highest valid elevation over a series of grids.
+/// # Returns
+/// * `None` when no grid holds valid data.
+pub fn shared_range(grids: &[Grid]) -> Option<(f32, f32)> {
+    grids.iter()
+        .filter_map(|grid| elevation_range(&grid.data))
+        .reduce(|(min_a, max_a), (min_b, max_b)| (min_a.min(min_b), max_a.max(max_b)))
+}
+
+/// Colors every grid of a series with the same elevation range, so a color means the same value in every frame.
+/// # Arguments
+/// * `grids` - The grids, in frame order. They must all have the same dimensions.
+/// * `range` - The elevations mapped to the ends of the gradient, the `shared_range` of the grids when `None`.
+/// * `nodata_color` - Color of the NoData cells, transparent when `None`.
+/// * `gradient` - The gradient the frames are colored with.
+/// * `reverse` - Run the gradient from the highest elevation to the lowest.
+/// # Returns
+/// * One RGBA frame per grid.
+pub fn render_frames(grids: &[Grid], range: Option<(f32, f32)>, nodata_color: Option<Rgba<u8>>, gradient: &dyn Gradient, reverse: bool) -> Result<Vec<RgbaImage>> {
+    let Some(first) = grids.first() else {
+        bail!("No grid to animate");
+    };
+    if let Some((i, grid)) = grids.iter().enumerate().find(|(_, g)| (g.width, g.height) != (first.width, first.height)) {
+        bail!("Frame {} is {}x{}, the first one is {}x{}", i + 1, grid.width, grid.height, first.width, first.height);
+    }
+    let range = match range {
+        Some(range) => range,
+        None => shared_range(grids).ok_or_else(|| anyhow!("No frame holds valid data"))?,
+    };
+    debug!("Animation range: {} to {}", range.0, range.1);
+    grids.iter()
+        .enumerate()
+        .map(|(i, grid)| {
+            rgb_with(grid.data.clone(), grid.width, grid.height, nodata_color, Some(range), gradient, reverse)
+                .map_err(|e| anyhow!("Failed to color frame {}: {}", i + 1, e))
+        })
+        .collect()
+}
+
+/// Encodes frames as a looping animated GIF.
+/// # Arguments
+/// * `frames` - The frames, all of the same size.
+/// * `delay_ms` - How long each frame is shown, in milliseconds.
+/// * `writer` - Where the GIF is written.
+pub fn encode_gif<W: Write>(frames: Vec<RgbaImage>, delay_ms: u32, writer: W) -> Result<()> {
+    let mut encoder = GifEncoder::new(writer);
+    encoder.set_repeat(Repeat::Infinite)?;
+    let delay = Delay::from_numer_denom_ms(delay_ms, 1);
+    encoder.encode_frames(frames.into_iter().map(|frame| Frame::from_parts(frame, 0, 0, delay)))?;
+    Ok(())
+}
+
+/// Reads every file listed after `--animate` and writes them as one animated GIF.
+/// # Arguments
+/// * `args` - The parsed command-line options (`--animate`, `--frame-delay`, gradient and NoData options).
+/// * `output_dir` - The directory the GIF is written into.
+/// # Returns
+/// * The path of the written `animation_YYYYMMDD_HHMMSS.gif`.
+pub fn run_animation(args: &Args, output_dir: &Path) -> Result<PathBuf> {
+    let mut grids = Vec::with_capacity(args.animate.len());
+    for path in &args.animate {
+        debug!("Reading frame {}", path);
+        let (mut grid, _) = pipeline::read_path(path, args).with_context(|| format!("Failed to read frame {}", path))?;
+        if args.flip_y {
+            flip_vertical(&mut grid);
+        }
+        mask_values(&mut grid.data, &args.ignore_values);
+        grids.push(grid);
+    }
+    let gradient = gradient_by_name(&args.gradient).expect("--gradient is validated by the CLI parser");
+    // --normalize and --vmin/--vmax see the elevations of every frame, so an animation can share the
+    // color scale of tiles rendered with the same bounds
+    let elevations: Vec<f32> = grids.iter().flat_map(|grid| grid.data.iter().copied()).collect();
+    let range = pipeline::value_range(&elevations, args)?;
+    let frames = render_frames(&grids, range, args.nodata_color, gradient.as_ref(), args.reverse_gradient)?;
+
+    let path = output_dir.join(animation_file(args));
+    let file = File::create(&path).with_context(|| format!("Failed to create {}", path.display()))?;
+    encode_gif(frames, args.frame_delay, BufWriter::new(file)).with_context(|| format!("Failed to write {}", path.display()))?;
+    info!("Animation of {} frames saved as {}", grids.len(), path.display());
+    Ok(path)
+}
+
+/// File name of the GIF written by `run_animation`, `animation_YYYYMMDD_HHMMSS.gif`, or
+/// `<first frame>_animation.gif` with `--no-timestamp`.
+pub fn animation_file(args: &Args) -> String {
+    pipeline::OutputTag::from_args(args).file("animation", "gif")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use image::codecs::gif::GifDecoder;
+    use image::AnimationDecoder;
+
+    fn dummy_grid(ncols: u32, nrows: u32, offset: f32) -> Grid {
+        let values: Vec<String> = (0..ncols * nrows).map(|i| (i as f32 + offset).to_string()).collect();
+        let content = format!(
+            "ncols {}\nnrows {}\nxllcorner 0\nyllcorner 0\ncellsize 1\nNODATA_value -9999\n{}\n",
+            ncols, nrows, values.join(" ")
+        );
+        Grid::from_asc(content).unwrap()
+    }
+
+    #[test]
+    /// Two grids make a two-frame GIF that decodes back to frames of the grid size.
+    fn test_two_frame_gif() {
+        let grids = [dummy_grid(4, 3, 0.0), dummy_grid(4, 3, 20.0)];
+        let frames = render_frames(&grids, None, None, &colorgrad::preset::turbo(), false).unwrap();
+        // with a shared range, the same cell gets a different color in each frame
+        assert_ne!(frames[0].get_pixel(0, 0), frames[1].get_pixel(0, 0));
+        // a given range replaces the shared one
+        let fixed = render_frames(&grids, Some((0.0, 100.0)), None, &colorgrad::preset::turbo(), false).unwrap();
+        let expected = rgb_with(grids[1].data.clone(), 4, 3, None, Some((0.0, 100.0)), &colorgrad::preset::turbo(), false).unwrap();
+        assert_eq!(fixed[1], expected);
+        assert_ne!(fixed[1], frames[1]);
+        let mut gif = Vec::new();
+        encode_gif(frames, 200, &mut gif).unwrap();
+        let decoded = GifDecoder::new(Cursor::new(gif)).unwrap().into_frames().collect_frames().unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0].buffer().dimensions(), (4, 3));
+        assert_eq!(shared_range(&grids), Some((0.0, 31.0)));
+    }
+
+    #[test]
+    /// Grids of different sizes cannot share an animation.
+    fn test_frames_must_match() {
+        let grids = [dummy_grid(4, 3, 0.0), dummy_grid(3, 4, 0.0)];
+        assert!(render_frames(&grids, None, None, &colorgrad::preset::turbo(), false).is_err());
+    }
+}
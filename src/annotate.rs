@@ -0,0 +1,334 @@
+use ab_glyph::FontRef;
+use clap::ValueEnum;
+use colorgrad::Gradient;
+use image::{Rgba, RgbaImage};
+use imageproc::drawing::{draw_filled_rect_mut, draw_line_segment_mut, draw_polygon_mut, draw_text_mut, text_size};
+use imageproc::point::Point;
+use imageproc::rect::Rect;
+
+use crate::contours::format_level;
+
+/// Font used for every label drawn on the maps (DejaVu Sans Bold, see `assets/LICENSE-DejaVu.txt`).
+const FONT_BYTES: &[u8] = include_bytes!("../assets/DejaVuSans-Bold.ttf");
+
+/// Height of the label text in pixels.
+pub const LABEL_SIZE: f32 = 14.0;
+
+/// Distance between the annotations and the image border, in pixels.
+pub const MARGIN: u32 = 10;
+
+/// Thickness of the scale bar in pixels.
+pub const SCALE_BAR_HEIGHT: u32 = 6;
+
+/// Side of the square holding the north arrow, in pixels.
+pub const NORTH_ARROW_SIZE: u32 = 40;
+
+/// Length and thickness of the colorbar strip, in pixels.
+pub const COLORBAR_STRIP: (u32, u32) = (120, 16);
+
+/// Number of labeled elevation ticks along the colorbar.
+pub const COLORBAR_TICKS: u32 = 5;
+
+/// Opacity of the white box behind the colorbar.
+const COLORBAR_BOX_OPACITY: f32 = 0.7;
+
+/// Corner of the image an annotation is placed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    /// Top-left position of a `width` x `height` box placed in this corner, `MARGIN` pixels from the borders.
+    pub fn position(self, image: &RgbaImage, width: u32, height: u32) -> (u32, u32) {
+        let right = image.width().saturating_sub(width + MARGIN);
+        let bottom = image.height().saturating_sub(height + MARGIN);
+        match self {
+            Corner::TopLeft => (MARGIN, MARGIN),
+            Corner::TopRight => (right, MARGIN),
+            Corner::BottomLeft => (MARGIN, bottom),
+            Corner::BottomRight => (right, bottom),
+        }
+    }
+}
+
+/// Font size of the elevation labels drawn by `overlay_elevation_labels`.
+pub const ELEVATION_LABEL_SIZE: f32 = 11.0;
+
+const INK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+const PAPER: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+/// Returns the embedded label font.
+pub fn font() -> FontRef<'static> {
+    FontRef::try_from_slice(FONT_BYTES).expect("the embedded font is a valid TrueType file")
+}
+
+/// Rounds a length down to the closest 1, 2 or 5 times a power of ten.
+/// # Arguments
+/// * `max_length` - The longest acceptable length, must be positive.
+pub fn nice_length(max_length: f32) -> f32 {
+    let magnitude = 10f32.powf(max_length.log10().floor());
+    let step = [5.0, 2.0, 1.0].into_iter().find(|&s| s * magnitude <= max_length).unwrap_or(1.0);
+    step * magnitude
+}
+
+/// Formats a ground distance for a label, switching to kilometers from 1000 m.
+pub fn format_distance(meters: f32) -> String {
+    if meters >= 1000.0 {
+        format!("{} km", meters / 1000.0)
+    } else {
+        format!("{} m", meters)
+    }
+}
+
+/// Draws a labeled scale bar in the lower-left corner of an image.
+/// # Arguments
+/// * `image` - The north-up map to annotate.
+/// * `cellsize` - Ground size of one pixel in meters.
+/// * `length_meters` - Ground length of the bar. Pass `0.0` to pick a round length of at most
+///   a quarter of the image width.
+///
+/// The bar is `round(length_meters / cellsize)` pixels long and drawn in black with its label
+/// above it, on a white panel so it stays readable over any background.
+pub fn draw_scale_bar(image: &mut RgbaImage, cellsize: f32, length_meters: f32) {
+    let length_meters = if length_meters > 0.0 {
+        length_meters
+    } else {
+        nice_length(image.width() as f32 * cellsize / 4.0)
+    };
+    let bar_width = ((length_meters / cellsize).round() as u32).max(1);
+    let font = font();
+    let label = format_distance(length_meters);
+    let (label_width, label_height) = text_size(LABEL_SIZE, &font, &label);
+
+    let bar_x = MARGIN as i32;
+    let bar_y = image.height() as i32 - (MARGIN + SCALE_BAR_HEIGHT) as i32;
+    let label_y = bar_y - label_height as i32 - 4;
+    let panel = Rect::at(bar_x - 4, label_y - 4).of_size(bar_width.max(label_width) + 8, label_height + SCALE_BAR_HEIGHT + 12);
+    draw_filled_rect_mut(image, panel, PAPER);
+    draw_filled_rect_mut(image, Rect::at(bar_x, bar_y).of_size(bar_width, SCALE_BAR_HEIGHT), INK);
+    draw_text_mut(image, INK, bar_x, label_y, LABEL_SIZE, &font, &label);
+}
+
+/// Draws a north arrow topped by an "N" label.
+/// # Arguments
+/// * `image` - The north-up map to annotate.
+/// * `x` - Left edge of the arrow box.
+/// * `y` - Top edge of the arrow box.
+/// * `size` - Side of the square box the arrow and its label fit in.
+///
+/// The label fills the upper third of the box and the arrow points up from the bottom edge.
+pub fn draw_north_arrow(image: &mut RgbaImage, x: u32, y: u32, size: u32) {
+    let font = font();
+    let label_size = size as f32 / 3.0;
+    let (label_width, _) = text_size(label_size, &font, "N");
+    let center = x as f32 + size as f32 / 2.0;
+    draw_text_mut(image, INK, (center - label_width as f32 / 2.0) as i32, y as i32, label_size, &font, "N");
+
+    let tip = y as f32 + size as f32 * 0.4;
+    let bottom = (y + size - 1) as f32;
+    let head = size as f32 / 4.0;
+    draw_line_segment_mut(image, (center, tip + head), (center, bottom), INK);
+    let head_points = [
+        Point::new(center as i32, tip as i32),
+        Point::new((center + head / 2.0) as i32, (tip + head) as i32),
+        Point::new((center - head / 2.0) as i32, (tip + head) as i32),
+    ];
+    draw_polygon_mut(image, &head_points, INK);
+}
+
+/// Elevations of the colorbar ticks with their labels, from `min_val` to `max_val`.
+fn colorbar_ticks(min_val: f32, max_val: f32) -> Vec<(f32, String)> {
+    let step = (max_val - min_val) / (COLORBAR_TICKS - 1) as f32;
+    (0..COLORBAR_TICKS)
+        .map(|i| {
+            let value = min_val + step * i as f32;
+            (value, format_level(value, step))
+        })
+        .collect()
+}
+
+/// Size of the box `overlay_colorbar` draws for this elevation range, in pixels.
+pub fn colorbar_size(min_val: f32, max_val: f32) -> (u32, u32) {
+    let font = font();
+    let (label_width, label_height) = colorbar_ticks(min_val, max_val).iter()
+        .map(|(_, label)| text_size(LABEL_SIZE, &font, label))
+        .fold((0, 0), |(w, h), (lw, lh)| (w.max(lw), h.max(lh)));
+    let (length, thickness) = COLORBAR_STRIP;
+    // strip, tick marks and labels, with the end labels overhanging the strip by half their height
+    (thickness + label_width + 20, length + label_height + 8)
+}
+
+/// Draws a vertical colorbar with labeled elevation ticks in a corner of a map.
+/// # Arguments
+/// * `image` - The color map to annotate.
+/// * `gradient` - The gradient the map was colored with.
+/// * `min_val`, `max_val` - The elevations at the bottom and the top of the gradient.
+/// * `position` - The corner the colorbar is placed in.
+///
+/// The strip has the highest elevation at the top and `COLORBAR_TICKS` evenly spaced labels on its
+/// right, on a semi-transparent white box so the map still shows through.
+pub fn overlay_colorbar(image: &mut RgbaImage, gradient: &dyn Gradient, min_val: f32, max_val: f32, position: Corner) {
+    let (box_width, box_height) = colorbar_size(min_val, max_val);
+    let (x0, y0) = position.position(image, box_width, box_height);
+    for y in y0..(y0 + box_height).min(image.height()) {
+        for x in x0..(x0 + box_width).min(image.width()) {
+            let pixel = image.get_pixel_mut(x, y);
+            for c in 0..3 {
+                pixel[c] = (pixel[c] as f32 * (1.0 - COLORBAR_BOX_OPACITY) + PAPER[c] as f32 * COLORBAR_BOX_OPACITY).round() as u8;
+            }
+        }
+    }
+
+    let font = font();
+    let (length, thickness) = COLORBAR_STRIP;
+    let label_height = box_height - length - 8;
+    let strip_x = x0 + 4;
+    let strip_top = y0 + 4 + label_height / 2;
+    for i in 0..length {
+        let t = 1.0 - i as f32 / (length - 1) as f32;
+        let [r, g, b, _] = gradient.at(t).to_rgba8();
+        draw_filled_rect_mut(image, Rect::at(strip_x as i32, (strip_top + i) as i32).of_size(thickness, 1), Rgba([r, g, b, 255]));
+    }
+
+    let tick_x = (strip_x + thickness) as f32;
+    for (i, (_, label)) in colorbar_ticks(min_val, max_val).iter().enumerate() {
+        let tick_y = strip_top as f32 + (length - 1) as f32 * (1.0 - i as f32 / (COLORBAR_TICKS - 1) as f32);
+        draw_line_segment_mut(image, (tick_x, tick_y), (tick_x + 4.0, tick_y), INK);
+        let label_y = tick_y as i32 - label_height as i32 / 2;
+        draw_text_mut(image, INK, tick_x as i32 + 8, label_y, LABEL_SIZE, &font, label);
+    }
+}
+
+/// Lists the elevation labels of a sparse grid of sample cells.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `step` - Spacing of the sample cells in pixels, from the top-left corner. Must be positive.
+/// # Returns
+/// * The `(x, y, label)` of every sample cell at a multiple of `step` (except 0) holding valid data,
+///   the label being the elevation rounded to the nearest unit.
+pub fn elevation_labels(data: &Vec<f32>, width: u32, height: u32, step: u32) -> Vec<(u32, u32, String)> {
+    (step..height)
+        .step_by(step as usize)
+        .flat_map(|y| (step..width).step_by(step as usize).map(move |x| (x, y)))
+        .filter_map(|(x, y)| {
+            let value = data[(y * width + x) as usize];
+            (!value.is_nan()).then(|| (x, y, format!("{}", value.round() as i64)))
+        })
+        .collect()
+}
+
+/// Draws the elevation of a sparse grid of cells next to a dot marking each of them.
+/// # Arguments
+/// * `image` - The map to annotate, with one pixel per cell.
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `step` - Spacing of the labeled cells in pixels. Must be positive.
+///
+/// Each label sits below and to the right of its cell on a white panel; NaN cells are skipped.
+pub fn overlay_elevation_labels(image: &mut RgbaImage, data: &Vec<f32>, width: u32, height: u32, step: u32) {
+    let font = font();
+    for (x, y, label) in elevation_labels(data, width, height, step) {
+        let (label_width, label_height) = text_size(ELEVATION_LABEL_SIZE, &font, &label);
+        let (label_x, label_y) = (x as i32 + 2, y as i32 + 2);
+        draw_filled_rect_mut(image, Rect::at(label_x - 1, label_y - 1).of_size(label_width + 2, label_height + 2), PAPER);
+        draw_filled_rect_mut(image, Rect::at(x as i32 - 1, y as i32 - 1).of_size(3, 3), INK);
+        draw_text_mut(image, INK, label_x, label_y, ELEVATION_LABEL_SIZE, &font, &label);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// The bar covers round(length / cellsize) pixels and the label sits above it.
+    fn test_scale_bar_width() {
+        let mut image = RgbaImage::from_pixel(200, 100, Rgba([0, 128, 0, 255]));
+        draw_scale_bar(&mut image, 2.5, 250.0);
+        let bar_row = image.height() - MARGIN - SCALE_BAR_HEIGHT / 2;
+        let black = (0..image.width()).filter(|&x| image.get_pixel(x, bar_row) == &INK).count();
+        assert_eq!(black, 100);
+        assert_eq!(image.get_pixel(MARGIN, bar_row), &INK);
+        // the label is drawn somewhere above the bar
+        let label_rows = image.height() - MARGIN - SCALE_BAR_HEIGHT - 4;
+        assert!((0..label_rows).any(|y| (0..image.width()).any(|x| image.get_pixel(x, y)[0] < 100 && image.get_pixel(x, y)[1] < 100)));
+        // the top-right corner is untouched
+        assert_eq!(image.get_pixel(199, 0), &Rgba([0, 128, 0, 255]));
+    }
+
+    #[test]
+    /// The arrow and its label stay inside their box, leaving the rest of the image untouched.
+    fn test_north_arrow_bounds() {
+        let background = Rgba([30, 90, 200, 255]);
+        let mut image = RgbaImage::from_pixel(120, 100, background);
+        let (x0, y0, size) = (50, 20, 40);
+        draw_north_arrow(&mut image, x0, y0, size);
+        let inside = |x: u32, y: u32| x >= x0 && x < x0 + size && y >= y0 && y < y0 + size;
+        for (x, y, pixel) in image.enumerate_pixels() {
+            if !inside(x, y) {
+                assert_eq!(pixel, &background, "({}, {})", x, y);
+            }
+        }
+        // shaft below the head, and ink in the label area
+        assert_eq!(image.get_pixel(x0 + size / 2, y0 + size - 2), &INK);
+        assert!((y0..y0 + size / 3).any(|y| (x0..x0 + size).any(|x| image.get_pixel(x, y) != &background)));
+    }
+
+    #[test]
+    /// The colorbar stays inside its corner box, which is changed, leaving the rest of the image untouched.
+    fn test_overlay_colorbar_bounds() {
+        let background = Rgba([30, 90, 200, 255]);
+        let mut image = RgbaImage::from_pixel(300, 250, background);
+        overlay_colorbar(&mut image, &colorgrad::preset::turbo(), 120.0, 1480.0, Corner::BottomRight);
+        let (box_width, box_height) = colorbar_size(120.0, 1480.0);
+        let (x0, y0) = (300 - MARGIN - box_width, 250 - MARGIN - box_height);
+        let inside = |x: u32, y: u32| x >= x0 && x < x0 + box_width && y >= y0 && y < y0 + box_height;
+        for (x, y, pixel) in image.enumerate_pixels() {
+            if inside(x, y) {
+                assert_ne!(pixel, &background, "({}, {})", x, y);
+            } else {
+                assert_eq!(pixel, &background, "({}, {})", x, y);
+            }
+        }
+        let labels: Vec<String> = colorbar_ticks(120.0, 1480.0).into_iter().map(|(_, label)| label).collect();
+        assert_eq!(labels, ["120", "460", "800", "1140", "1480"]);
+    }
+
+    #[test]
+    /// Automatic lengths are round numbers no longer than the limit.
+    fn test_nice_length() {
+        assert_eq!(nice_length(730.0), 500.0);
+        assert_eq!(nice_length(199.0), 100.0);
+        assert_eq!(nice_length(2.0), 2.0);
+        assert_eq!(format_distance(2000.0), "2 km");
+        assert_eq!(format_distance(250.0), "250 m");
+    }
+
+    #[test]
+    /// A label is drawn at (step, step) with that cell's rounded elevation, and NaN cells get none.
+    fn test_overlay_elevation_labels() {
+        let (width, height, step) = (100, 80, 30);
+        let mut data: Vec<f32> = (0..width * height).map(|i| 100.0 + (i % width) as f32 + 0.4).collect();
+        data[(60 * width + 30) as usize] = f32::NAN;
+        let labels = elevation_labels(&data, width, height, step);
+        assert_eq!(labels[0], (step, step, "130".to_string()));
+        assert_eq!(labels.len(), 5, "{:?}", labels);
+        assert!(!labels.iter().any(|&(x, y, _)| (x, y) == (30, 60)));
+
+        let background = Rgba([30, 90, 200, 255]);
+        let mut image = RgbaImage::from_pixel(width, height, background);
+        overlay_elevation_labels(&mut image, &data, width, height, step);
+        assert_eq!(image.get_pixel(step, step), &INK);
+        let (label_width, label_height) = text_size(ELEVATION_LABEL_SIZE, &font(), "130");
+        let mut ink = (step + 2..step + 2 + label_width).flat_map(|x| (step + 2..step + 2 + label_height).map(move |y| (x, y)));
+        assert!(ink.any(|(x, y)| image.get_pixel(x, y)[0] < 100), "no label text at the cell");
+        assert_eq!(image.get_pixel(30, 60), &background);
+    }
+}
@@ -0,0 +1,73 @@
+/// Georeferencing attributes stored alongside archived layers.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Metadata {
+    pub cellsize: f32,
+    /// X coordinate of the lower-left corner of the grid.
+    pub xllcorner: f64,
+    /// Y coordinate of the lower-left corner of the grid.
+    pub yllcorner: f64,
+    /// The coordinate reference system, e.g. `EPSG:2154`.
+    pub crs: Option<String>,
+}
+
+/// Writes several raster layers into a single HDF5 file (requires the `hdf5` feature).
+/// # Arguments
+/// * `layers` - `(name, values)` pairs, each stored as a `height` x `width` float32 dataset.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `attrs` - Georeferencing written as attributes of the root group.
+/// * `path` - The `.h5` file to write.
+///
+/// Every dataset uses NaN as fill value, matching the NoData cells of the layers.
+#[cfg(feature = "hdf5")]
+pub fn save_hdf5(layers: &[(&str, &[f32])], width: u32, height: u32, attrs: &Metadata, path: &str) -> anyhow::Result<()> {
+    use anyhow::bail;
+    use hdf5::types::VarLenUnicode;
+
+    let file = hdf5::File::create(path)?;
+    for (name, values) in layers {
+        if values.len() != (width * height) as usize {
+            bail!("layer {} has {} values, expected {}", name, values.len(), width * height);
+        }
+        let dataset = file.new_dataset::<f32>()
+            .shape([height as usize, width as usize])
+            .fill_value(f32::NAN)
+            .create(*name)?;
+        dataset.write_raw(*values)?;
+    }
+    file.new_attr::<f32>().shape(()).create("cellsize")?.write_scalar(&attrs.cellsize)?;
+    file.new_attr::<f64>().shape(()).create("xllcorner")?.write_scalar(&attrs.xllcorner)?;
+    file.new_attr::<f64>().shape(()).create("yllcorner")?.write_scalar(&attrs.yllcorner)?;
+    if let Some(crs) = &attrs.crs {
+        let crs: VarLenUnicode = crs.parse()?;
+        file.new_attr::<VarLenUnicode>().shape(()).create("crs")?.write_scalar(&crs)?;
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "hdf5"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// It checks the datasets and their shapes, and that the elevation layer round-trips.
+    fn test_save_hdf5_layers() {
+        let path = std::env::temp_dir().join("dem_test_layers.h5");
+        let elevation = vec![1.0, 2.0, f32::NAN, 4.0, 5.0, 6.0];
+        let slope = vec![0.0; 6];
+        let attrs = Metadata { cellsize: 25.0, xllcorner: 925000.0, yllcorner: 6224000.0, crs: Some("EPSG:2154".to_string()) };
+        save_hdf5(&[("elevation", &elevation), ("slope", &slope)], 3, 2, &attrs, path.to_str().unwrap()).unwrap();
+
+        let file = hdf5::File::open(&path).unwrap();
+        let mut names = file.member_names().unwrap();
+        names.sort();
+        assert_eq!(names, vec!["elevation", "slope"]);
+        let dataset = file.dataset("elevation").unwrap();
+        assert_eq!(dataset.shape(), vec![2, 3]);
+        let values: Vec<f32> = dataset.read_raw().unwrap();
+        assert_eq!(values[1], 2.0);
+        assert!(values[2].is_nan());
+        assert_eq!(file.attr("cellsize").unwrap().read_scalar::<f32>().unwrap(), 25.0);
+        std::fs::remove_file(path).ok();
+    }
+}
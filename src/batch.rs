@@ -0,0 +1,181 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use log::{info, warn};
+use rayon::prelude::*;
+use walkdir::WalkDir;
+
+use crate::cli::Args;
+use crate::pipeline;
+use crate::progress;
+use crate::qa::QaReport;
+use crate::sink::FileSink;
+
+/// Lists the `.asc` files directly inside a directory, sorted by name.
+/// # Arguments
+/// * `dir` - The directory to scan.
+pub fn find_asc_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in WalkDir::new(dir).min_depth(1).max_depth(1) {
+        let entry = entry.with_context(|| format!("Failed to read directory {}", dir.display()))?;
+        let is_asc = entry.path().extension()
+            .map(|ext| ext.eq_ignore_ascii_case("asc"))
+            .unwrap_or(false);
+        if entry.file_type().is_file() && is_asc {
+            files.push(entry.into_path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Outcome of a batch run.
+#[derive(Debug)]
+pub struct BatchSummary {
+    /// Number of tiles rendered successfully.
+    pub succeeded: usize,
+    /// The tiles that failed with their error message, in file name order.
+    pub failures: Vec<(PathBuf, String)>,
+    /// The QA warnings of every successful tile, prefixed with its file name.
+    pub report: QaReport,
+}
+
+impl BatchSummary {
+    /// Formats the counts and one line per failed tile with its error, for the end of a batch.
+    pub fn table(&self) -> String {
+        let mut table = format!("Batch: {} succeeded, {} failed\n", self.succeeded, self.failures.len());
+        let width = self.failures.iter().map(|(file, _)| file.display().to_string().len()).max().unwrap_or(0);
+        for (file, error) in &self.failures {
+            table.push_str(&format!("  {:<width$}  {}\n", file.display().to_string(), error, width = width));
+        }
+        table
+    }
+}
+
+/// Runs the full pipeline on every `.asc` file of a directory in parallel.
+/// # Arguments
+/// * `args` - The command-line options applied to every tile.
+/// * `dir` - The directory containing the tiles.
+/// * `output_root` - Each tile writes its images into `output_root/<file stem>/`.
+/// * `jobs` - Number of worker threads, `0` lets rayon pick one per CPU.
+/// # Returns
+/// * The number of tiles processed successfully, the failed tiles with their error and the QA warnings.
+///
+/// A failing tile is logged as a warning and recorded, the rest of the batch keeps going.
+pub fn run_batch(args: &Args, dir: &Path, output_root: &Path, jobs: usize) -> Result<BatchSummary> {
+    let files = find_asc_files(dir)?;
+    info!("Found {} ASC files in {}", files.len(), dir.display());
+
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(jobs).build()?;
+    let bar = progress::item_progress(files.len() as u64, "batch", "tiles");
+    // Per-row bars from parallel tiles would overwrite each other
+    progress::set_quiet(true);
+
+    let results: Vec<(&PathBuf, Result<QaReport>)> = pool.install(|| {
+        files.par_iter()
+            .map(|file| {
+                let result = process_tile(args, file, output_root);
+                bar.inc(1);
+                if let Err(e) = &result {
+                    warn!("Skipping {}: {:#}", file.display(), e);
+                }
+                (file, result)
+            })
+            .collect()
+    });
+    bar.finish();
+    progress::set_quiet(args.quiet);
+
+    let mut summary = BatchSummary { succeeded: 0, failures: Vec::new(), report: QaReport::new() };
+    for (file, result) in results {
+        match result {
+            Ok(report) => {
+                summary.succeeded += 1;
+                summary.report.extend(&file.display().to_string(), report);
+            }
+            Err(e) => summary.failures.push((file.clone(), format!("{:#}", e))),
+        }
+    }
+    Ok(summary)
+}
+
+/// Renders one tile into its own subfolder of `output_root`.
+fn process_tile(args: &Args, file: &Path, output_root: &Path) -> Result<QaReport> {
+    let output_dir = tile_output_dir(file, output_root);
+    fs::create_dir_all(&output_dir)
+        .with_context(|| format!("Failed to create {}", output_dir.display()))?;
+    pipeline::run(&tile_args(args, file), &mut FileSink::new(output_dir))
+}
+
+/// The folder of `output_root` a tile writes its images into, named after the tile's file stem.
+pub fn tile_output_dir(file: &Path, output_root: &Path) -> PathBuf {
+    let stem = file.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    output_root.join(stem)
+}
+
+/// The options a tile is rendered with: the batch options, reading that tile alone.
+pub fn tile_args(args: &Args, file: &Path) -> Args {
+    Args { file_path: Some(file.to_string_lossy().into_owned()), batch: None, merge: Vec::new(), ..args.clone() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn ramp_asc(width: u32, height: u32) -> String {
+        let mut content = format!("ncols {}\nnrows {}\nxllcorner 0\nyllcorner 0\ncellsize 1\nnodata_value -9999\n", width, height);
+        for y in 0..height {
+            let row: Vec<String> = (0..width).map(|x| (x * y).to_string()).collect();
+            content.push_str(&row.join(" "));
+            content.push('\n');
+        }
+        content
+    }
+
+    #[test]
+    /// It checks that every ASC file of the directory is rendered into its own subfolder.
+    fn test_run_batch_two_tiles() {
+        let root = std::env::temp_dir().join("dem_batch_test");
+        let _ = fs::remove_dir_all(&root);
+        let input_dir = root.join("tiles");
+        let output_dir = root.join("out");
+        fs::create_dir_all(&input_dir).unwrap();
+        fs::write(input_dir.join("tile_a.asc"), ramp_asc(64, 64)).unwrap();
+        fs::write(input_dir.join("tile_b.asc"), ramp_asc(64, 64)).unwrap();
+        fs::write(input_dir.join("notes.txt"), "not a grid").unwrap();
+
+        let args = Args::parse_from(["dem", "--quiet"]);
+        let summary = run_batch(&args, &input_dir, &output_dir, 2).unwrap();
+        assert_eq!(summary.succeeded, 2);
+        assert!(summary.failures.is_empty());
+        assert!(summary.report.is_clean());
+        for tile in ["tile_a", "tile_b"] {
+            let produced = fs::read_dir(output_dir.join(tile)).unwrap().count();
+            assert_eq!(produced, 5);
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    /// It checks that a corrupt tile is recorded with its error while the valid tile is still rendered.
+    fn test_run_batch_reports_failures() {
+        let root = std::env::temp_dir().join("dem_batch_failure_test");
+        let _ = fs::remove_dir_all(&root);
+        let input_dir = root.join("tiles");
+        let output_dir = root.join("out");
+        fs::create_dir_all(&input_dir).unwrap();
+        fs::write(input_dir.join("good.asc"), ramp_asc(64, 64)).unwrap();
+        fs::write(input_dir.join("corrupt.asc"), "this is not a grid\n").unwrap();
+
+        let args = Args::parse_from(["dem", "--quiet"]);
+        let summary = run_batch(&args, &input_dir, &output_dir, 2).unwrap();
+        assert_eq!(summary.succeeded, 1);
+        assert_eq!(summary.failures.len(), 1);
+        assert_eq!(summary.failures[0].0, input_dir.join("corrupt.asc"));
+        let table = summary.table();
+        assert!(table.starts_with("Batch: 1 succeeded, 1 failed"));
+        assert!(table.contains("corrupt.asc"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
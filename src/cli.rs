@@ -0,0 +1,573 @@
+use chrono::format::{Item, StrftimeItems};
+use clap::Parser;
+use image::Rgba;
+
+use crate::annotate::Corner;
+use crate::color::gradient_by_name;
+use crate::morphometry::AVALANCHE_THRESHOLDS;
+use crate::relief::{BlendMode, SlopeAlgo};
+use crate::resample::ResampleKind;
+use crate::sink::OutputFormat;
+use crate::NormMode;
+
+/// Dataset used when no input path is given on the command line.
+pub const DEFAULT_FILE_PATH: &str = "/home/anas/Downloads/0925_6225/LITTO3D_FRA_0925_6225_20150529_LAMB93_RGF93_IGN69/MNT1m/LITTO3D_FRA_0925_6225_MNT_20150529_LAMB93_RGF93_IGN69.asc";
+
+/// Directory the rendered images are written to unless `--output-dir` is given.
+pub const DEFAULT_OUTPUT_PATH: &str = "src/output_img";
+
+/// Gradient of the RGB images unless `--gradient` is given.
+pub const DEFAULT_GRADIENT: &str = "turbo";
+
+/// Format of the time tag in the output file names unless `--timestamp-format` is given.
+pub const DEFAULT_TIMESTAMP_FORMAT: &str = "%Y%m%d_%H%M%S";
+
+/// Command-line options of the DEM renderer.
+#[derive(Parser, Debug, Clone)]
+#[command(name = "dem", about = "Renders ASC elevation files into grayscale, RGB and hillshade images")]
+pub struct Args {
+    /// Path to the ASC file, or `-` to read it from stdin.
+    /// When omitted, piped stdin is used if present, otherwise the default dataset path.
+    pub file_path: Option<String>,
+
+    /// Suppress progress bars (useful for scripting and CI).
+    #[arg(short, long)]
+    pub quiet: bool,
+
+    /// Log more details: `-v` adds the parsing steps and stage timings, `-vv` everything.
+    /// `RUST_LOG` overrides this (e.g. `RUST_LOG=dem=debug`).
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Also write `<timestamp>.json`, a record of the input, grid, rendering settings and every file produced.
+    #[arg(long)]
+    pub metadata_json: bool,
+
+    /// strftime format of the time tag in the output file names, e.g. `%Y%m%d_%H%M%S%.3f` to keep runs of the same second apart.
+    #[arg(long, value_name = "FORMAT", default_value = DEFAULT_TIMESTAMP_FORMAT, value_parser = parse_timestamp_format)]
+    pub timestamp_format: String,
+
+    /// Name the outputs after the input file (`foo.asc` gives `foo_rgb.png`, ...) instead of the time, so reruns overwrite them.
+    #[arg(long, conflicts_with = "timestamp_format")]
+    pub no_timestamp: bool,
+
+    /// Directory the images are written to, created with its parents when missing.
+    #[arg(long, value_name = "DIR", default_value = DEFAULT_OUTPUT_PATH)]
+    pub output_dir: String,
+
+    /// Print the parsed ASC header (size, cellsize, nodata value, corner, header lines) and exit without rendering.
+    #[arg(long)]
+    pub info: bool,
+
+    /// Print the elevation of cell `X,Y` (column, row from the top), or `NoData`, and exit without rendering.
+    #[arg(long, value_name = "X,Y", value_parser = parse_cell)]
+    pub query: Option<(u32, u32)>,
+
+    /// Like `--query`, for the cell containing the point `X,Y` in map coordinates (e.g. `lon,lat`).
+    #[arg(long, value_name = "X,Y", value_parser = parse_f64_pair, allow_negative_numbers = true, conflicts_with = "query")]
+    pub query_geo: Option<(f64, f64)>,
+
+    /// Only write the grayscale hillshade, skipping the color images and every optional product (fast path for batches).
+    #[arg(long)]
+    pub hillshade_only: bool,
+
+    /// Check the input header and print the files that would be written, without rendering or writing anything.
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Print the names of the supported color gradients, one per line, and exit.
+    #[arg(long)]
+    pub list_gradients: bool,
+
+    /// Exit with a nonzero code when the QA report contains any warning (for CI gating).
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Only render the part of the grid inside this bounding box, in map coordinates.
+    #[arg(long, num_args = 4, value_names = ["XMIN", "YMIN", "XMAX", "YMAX"], allow_negative_numbers = true)]
+    pub crop: Option<Vec<f32>>,
+
+    /// Only render the cells inside the polygon of this GeoJSON file, in map coordinates; the rest becomes NoData.
+    #[arg(long, value_name = "FILE")]
+    pub mask: Option<String>,
+
+    /// Blank the cells of a co-registered mask grid (same dimensions) that do not hold the --mask-valid value.
+    #[arg(long, value_name = "FILE")]
+    pub mask_grid: Option<String>,
+
+    /// Value of the valid cells in the --mask-grid.
+    #[arg(long, value_name = "VALUE", default_value_t = 1.0, allow_negative_numbers = true)]
+    pub mask_valid: f32,
+
+    /// Paint NoData cells with this color instead of transparent (RGB) or black (grayscale), e.g. `220,220,220`.
+    #[arg(long, value_name = "R,G,B", value_parser = parse_rgb)]
+    pub nodata_color: Option<Rgba<u8>>,
+
+    /// Tint the cells at or below this elevation as water on the RGB images.
+    #[arg(long, value_name = "ELEVATION", allow_negative_numbers = true)]
+    pub sea_level: Option<f32>,
+
+    /// Color gradient of the RGB image, see `--list-gradients`.
+    #[arg(long, value_name = "NAME", default_value = DEFAULT_GRADIENT, value_parser = parse_gradient_name)]
+    pub gradient: String,
+
+    /// Run the color gradient from the highest elevation to the lowest.
+    #[arg(long)]
+    pub reverse_gradient: bool,
+
+    /// Color the RGB images with a GDAL color-relief ramp (`elevation R G B [A]` per line) instead of `--gradient`.
+    #[arg(long, value_name = "FILE")]
+    pub color_ramp: Option<String>,
+
+    /// Mosaic these adjacent tiles into one grid (placed by their corner coordinates) instead of reading a single file.
+    #[arg(long, num_args = 1.., value_name = "FILE")]
+    pub merge: Vec<String>,
+
+    /// Render these grids (same dimensions) with a shared elevation range into one animated GIF instead of the usual images.
+    #[arg(long, num_args = 1.., value_name = "FILE", conflicts_with_all = ["merge", "batch"])]
+    pub animate: Vec<String>,
+
+    /// How long each frame of the --animate GIF is shown, in milliseconds.
+    #[arg(long, value_name = "MS", default_value_t = 500)]
+    pub frame_delay: u32,
+
+    /// Resample the grid to this cellsize before rendering.
+    #[arg(long, value_name = "CELLSIZE")]
+    pub resample: Option<f32>,
+
+    /// Shrink the grid by averaging blocks of this many cells per side (NoData ignored), for overviews.
+    #[arg(long, value_name = "FACTOR", value_parser = clap::value_parser!(u32).range(1..))]
+    pub overview: Option<u32>,
+
+    /// Interpolation used by `--resample`.
+    #[arg(long, value_enum, default_value_t = ResampleKind::Bilinear)]
+    pub resample_method: ResampleKind,
+
+    /// The rows of the input are stored bottom to top; reverse them before rendering.
+    #[arg(long)]
+    pub flip_y: bool,
+
+    /// Read numbers written with a comma as decimal separator (`1,23`).
+    #[arg(long)]
+    pub decimal_comma: bool,
+
+    /// Treat cells equal to this value as NoData too, for files whose header omits or misstates their NoData sentinel.
+    #[arg(long, value_name = "VALUE", allow_negative_numbers = true)]
+    pub nodata_override: Option<f32>,
+
+    /// Cell size of the ASC grid, replacing the header's `cellsize` (which defaults to 1 when missing).
+    #[arg(long, value_name = "SIZE", value_parser = parse_positive)]
+    pub cellsize: Option<f32>,
+
+    /// Comma-separated elevations to treat as NoData after parsing (e.g. `0,-1`).
+    #[arg(long, value_delimiter = ',', allow_negative_numbers = true)]
+    pub ignore_values: Vec<f32>,
+
+    /// Clamp every valid elevation into `MIN,MAX` after parsing, so a few spikes do not crush the color scale.
+    #[arg(long, value_name = "MIN,MAX", value_parser = parse_f32_pair, allow_negative_numbers = true)]
+    pub clamp_elevation: Option<(f32, f32)>,
+
+    /// Only render the cells with an elevation in `LOW,HIGH`; the others become transparent NoData.
+    #[arg(long, value_name = "LOW,HIGH", value_parser = parse_f32_pair, allow_negative_numbers = true)]
+    pub band: Option<(f32, f32)>,
+
+    /// Remove isolated spikes with a median filter over a (2r+1)x(2r+1) window.
+    #[arg(long, value_name = "RADIUS")]
+    pub despike: Option<u32>,
+
+    /// Smooth the DEM with a Gaussian blur of this standard deviation (in cells) to reduce noise.
+    #[arg(long, value_name = "SIGMA")]
+    pub smooth: Option<f32>,
+
+    /// Sharpen the relief with an unsharp mask before rendering, e.g. `2,1.5`.
+    #[arg(long, value_name = "SIGMA,AMOUNT", value_parser = parse_f32_pair)]
+    pub unsharp: Option<(f32, f32)>,
+
+    /// Crop the grid to the extent of its valid (non-NoData) cells, keeping the georeferencing aligned.
+    #[arg(long)]
+    pub autocrop: bool,
+
+    /// Write an ESRI world file (`.pgw`, ...) next to every image.
+    #[arg(long)]
+    pub world_file: bool,
+
+    /// Coordinate reference system of the grid as an EPSG code (e.g. `EPSG:2154`).
+    /// When set, a `.prj` sidecar is written next to every world file.
+    #[arg(long)]
+    pub crs: Option<String>,
+
+    /// Range mapped onto the grayscale and color ramps: `minmax`, `percentile:LOW,HIGH` (e.g. `percentile:2,98`)
+    /// or `stddev:K` (mean ± K standard deviations). Elevations outside the range clamp.
+    #[arg(long, value_name = "MODE", value_parser = parse_norm_mode, default_value = "minmax")]
+    pub normalize: NormMode,
+
+    /// Elevation mapped to the low end of the grayscale and color ramps (defaults to the grid minimum).
+    #[arg(long, allow_negative_numbers = true)]
+    pub vmin: Option<f32>,
+
+    /// Elevation mapped to the high end of the grayscale and color ramps (defaults to the grid maximum).
+    #[arg(long, allow_negative_numbers = true)]
+    pub vmax: Option<f32>,
+
+    /// Also split the colored hillshade into a `z/x/y.png` web map tile layer inside this directory.
+    #[arg(long, value_name = "DIR")]
+    pub tiles: Option<String>,
+
+    /// Side of the `--tiles` tiles in pixels.
+    #[arg(long, default_value_t = 256, value_parser = clap::value_parser!(u32).range(1..))]
+    pub tile_size: u32,
+
+    /// Also write a `<name>_thumb` copy of every image, scaled so its longest side is this many pixels.
+    #[arg(long, value_name = "MAX_DIM", value_parser = clap::value_parser!(u32).range(1..))]
+    pub thumbnail: Option<u32>,
+
+    /// Resolution, in dots per inch, declared in the PNGs for print (default: the ground resolution, 1 / cellsize pixels per meter).
+    #[arg(long, value_name = "DPI")]
+    pub print_dpi: Option<f32>,
+
+    /// File format of every written image.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Png)]
+    pub output_format: OutputFormat,
+
+    /// Color transparent pixels (NoData, margins) are flattened onto when writing JPEG, which has no alpha.
+    #[arg(long, value_name = "R,G,B", value_parser = parse_rgb, default_value = "255,255,255")]
+    pub jpeg_background: Rgba<u8>,
+
+    /// Bit depth of the grayscale image: 8, or 16 to keep the elevation precision.
+    #[arg(long, default_value_t = 8, value_parser = parse_bit_depth)]
+    pub bit_depth: u8,
+
+    /// Use histogram equalization instead of linear scaling for the grayscale image.
+    #[arg(long)]
+    pub equalize: bool,
+
+    /// Gamma of the linear grayscale image: above 1 brightens the midtones, which look flat on screen.
+    #[arg(long, default_value_t = 1.0)]
+    pub gamma: f32,
+
+    /// Also write a hypsometric image with discrete elevation bands of this size (e.g. `100`), colored with `--gradient`.
+    #[arg(long, value_name = "METERS")]
+    pub band_size: Option<f32>,
+
+    /// Also write a profile curvature map (concave blue, convex red).
+    #[arg(long)]
+    pub curvature: bool,
+
+    /// Blend the hillshade over the colors with this mode instead of plain multiplication.
+    #[arg(long, value_enum)]
+    pub blend: Option<BlendMode>,
+
+    /// Strength of the `--blend` shading, from 0 (colors only) to 1.
+    #[arg(long, default_value_t = 1.0)]
+    pub shade_opacity: f32,
+
+    /// Ambient light of the hillshade, from 0 (shadows are black) to 1 (no shading).
+    #[arg(long, default_value_t = 0.0, value_parser = parse_fraction)]
+    pub ambient: f32,
+
+    /// Also write the hillshade as black with transparency, shadows opaque up to this strength (1 when omitted).
+    #[arg(long, value_name = "STRENGTH")]
+    pub shade_overlay: Option<Option<f32>>,
+
+    /// Blend the hillshade with the hillshade of the terrain smoothed by a Gaussian of this sigma (cells), for broad landforms.
+    #[arg(long, value_name = "SIGMA")]
+    pub multiscale: Option<f32>,
+
+    /// Share of the smoothed `--multiscale` hillshade, from 0 (detail only) to 1 (overview only).
+    #[arg(long, default_value_t = 0.5, requires = "multiscale")]
+    pub multiscale_blend: f32,
+
+    /// Finite differences used for the hillshade and slope: horn (3x3 weighted window) or zevenbergen-thorne (4 neighbors).
+    #[arg(long, value_enum, default_value_t = SlopeAlgo::Horn)]
+    pub slope_algo: SlopeAlgo,
+
+    /// Fill the depressions of the DEM before rendering, so flow routing reaches the edges.
+    #[arg(long)]
+    pub fill_sinks: bool,
+
+    /// Draw a scale bar of this many meters on the RGB and hillshade images (a round length when omitted).
+    #[arg(long, value_name = "METERS")]
+    pub scale_bar: Option<Option<f32>>,
+
+    /// Draw the rounded elevation of every STEP-th cell on the RGB and hillshade images.
+    #[arg(long, value_name = "STEP", value_parser = clap::value_parser!(u32).range(1..))]
+    pub label_grid: Option<u32>,
+
+    /// Draw a north arrow in this corner of the RGB and hillshade images.
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "top-right")]
+    pub north_arrow: Option<Corner>,
+
+    /// Draw a colorbar with elevation ticks in this corner of the RGB image.
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "bottom-right", conflicts_with = "color_ramp")]
+    pub colorbar: Option<Corner>,
+
+    /// Also write a D8 flow accumulation image where drainage networks show up bright.
+    #[arg(long)]
+    pub flow_accumulation: bool,
+
+    /// Also write the elevations encoded as Mapbox Terrain-RGB, for web maps.
+    #[arg(long)]
+    pub terrain_rgb: bool,
+
+    /// Also write a roughness map (elevation range of each 3x3 neighborhood).
+    #[arg(long)]
+    pub roughness: bool,
+
+    /// Also write a slope map colored in avalanche bands (green, yellow, orange, red, purple).
+    #[arg(long)]
+    pub slope_classes: bool,
+
+    /// Band limits in degrees used by `--slope-classes`.
+    #[arg(long, value_delimiter = ',', default_values_t = AVALANCHE_THRESHOLDS)]
+    pub slope_thresholds: Vec<f32>,
+
+    /// Also write a sky-view factor image, searching the horizon up to this many cells away.
+    #[arg(long, value_name = "RADIUS", num_args = 0..=1, default_missing_value = "10")]
+    pub sky_view: Option<u32>,
+
+    /// Also write a Swiss-style color relief, with sunlit slopes warmer and shadowed slopes cooler.
+    #[arg(long)]
+    pub swiss_relief: bool,
+
+    /// Draw the gradient field arrows with anti-aliased lines, which read better in print.
+    #[arg(long)]
+    pub aa_arrows: bool,
+
+    /// Compute the gradient field arrows with the 3x3 Sobel kernels of the hillshade, giving the true
+    /// slope in elevation per distance, instead of the 61-cell window difference. Arrows point uphill.
+    #[arg(long)]
+    pub sobel_gradients: bool,
+
+    /// Also write the magnitude of the gradient field as a grayscale slope shading.
+    #[arg(long)]
+    pub gradient_magnitude: bool,
+
+    /// Also write the colored hillshade with contour lines every this many meters.
+    #[arg(long, value_name = "INTERVAL")]
+    pub contours: Option<f32>,
+
+    /// Like --contours, with a round interval (1, 2 or 5 times a power of ten) giving about N contour lines.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..), conflicts_with = "contours")]
+    pub contour_count: Option<u32>,
+
+    /// Label every Nth contour line of `--contours` with its elevation (0 for no labels).
+    #[arg(long, value_name = "N", default_value_t = 5)]
+    pub contour_label_every: u32,
+
+    /// Also write the elevation change from this DEM (same grid) to the input, in red (lower) and blue (higher).
+    #[arg(long, value_name = "FILE")]
+    pub diff: Option<String>,
+
+    /// Also write the viewshed of an observer standing on cell `X,Y` (column, row from the top) with the eye `H` above the ground.
+    #[arg(long, value_name = "X,Y,H", value_parser = parse_observer)]
+    pub viewshed: Option<(u32, u32, f32)>,
+
+    /// Also write the elevation profile from cell `X0,Y0` to cell `X1,Y1` (columns, rows from the top) as CSV.
+    #[arg(long, value_name = "X0,Y0,X1,Y1", value_parser = parse_transect)]
+    pub transect: Option<(u32, u32, u32, u32)>,
+
+    /// Render cells flatter than this many degrees as plain mid gray in the hillshades.
+    #[arg(long, value_name = "DEGREES")]
+    pub mask_hillshade_by_slope: Option<f32>,
+
+    /// Adapt the sun altitude to the local slope so gentle and steep areas get a similar contrast.
+    /// The optional value is the neighborhood size (Gaussian sigma, in cells).
+    #[arg(long, value_name = "SIGMA", num_args = 0..=1, default_missing_value = "15")]
+    pub adaptive_hillshade: Option<f32>,
+
+    /// Cast real shadows behind ridges into the hillshade images.
+    #[arg(long)]
+    pub shadows: bool,
+
+    /// Also export the terrain as a triangulated OBJ mesh to this path.
+    #[arg(long, value_name = "PATH")]
+    pub mesh_out: Option<String>,
+
+    /// Also export the elevations as a georeferenced float32 GeoTIFF to this path.
+    #[arg(long, value_name = "PATH")]
+    pub geotiff_out: Option<String>,
+
+    /// Also archive elevation, slope, aspect and hillshade as datasets of one HDF5 file
+    /// (requires building with `--features hdf5`).
+    #[arg(long, value_name = "PATH")]
+    pub hdf5_out: Option<String>,
+
+    /// Vertical exaggeration of the `--mesh-out` mesh.
+    #[arg(long, default_value_t = 1.0)]
+    pub mesh_z_scale: f32,
+
+    /// Print the drainage density, counting cells with at least this many upstream cells as channels.
+    #[arg(long, value_name = "THRESHOLD")]
+    pub drainage_density: Option<u32>,
+
+    /// Print the count, mean, min and max elevation of every zone of this integer zone grid (same dimensions).
+    #[arg(long, value_name = "FILE")]
+    pub zones: Option<String>,
+
+    /// Write a quick subsampled preview of the main images first, then overwrite it with the full render.
+    #[arg(long)]
+    pub progressive: bool,
+
+    /// Longest side, in pixels, of the `--progressive` preview.
+    #[arg(long, default_value_t = 512)]
+    pub preview_size: u32,
+
+    /// Process every `.asc` file of this directory, writing each tile's images into its own subfolder.
+    #[arg(long, value_name = "DIR")]
+    pub batch: Option<String>,
+
+    /// Number of tiles processed in parallel in batch mode (0 = one per CPU).
+    #[arg(long, default_value_t = 0)]
+    pub jobs: usize,
+
+    /// Exit with code 0 even when some batch tiles failed (the failures are still listed).
+    #[arg(long, requires = "batch")]
+    pub keep_going: bool,
+}
+
+/// Parses a comma-separated pair of numbers such as `2,1.5`.
+pub fn parse_f32_pair(value: &str) -> Result<(f32, f32), String> {
+    let parts: Vec<&str> = value.split(',').map(str::trim).collect();
+    match parts.as_slice() {
+        [a, b] => {
+            let a = a.parse::<f32>().map_err(|e| format!("invalid number {:?}: {}", a, e))?;
+            let b = b.parse::<f32>().map_err(|e| format!("invalid number {:?}: {}", b, e))?;
+            Ok((a, b))
+        }
+        _ => Err(format!("expected two comma-separated numbers, got {:?}", value)),
+    }
+}
+
+/// Parses a pair of map coordinates such as `925412.5,6224800`, kept in double precision.
+pub fn parse_f64_pair(value: &str) -> Result<(f64, f64), String> {
+    let parts: Vec<&str> = value.split(',').map(str::trim).collect();
+    match parts.as_slice() {
+        [a, b] => {
+            let a = a.parse::<f64>().map_err(|e| format!("invalid number {:?}: {}", a, e))?;
+            let b = b.parse::<f64>().map_err(|e| format!("invalid number {:?}: {}", b, e))?;
+            Ok((a, b))
+        }
+        _ => Err(format!("expected two comma-separated numbers, got {:?}", value)),
+    }
+}
+
+/// Parses a cell written as `X,Y`: its column and row.
+pub fn parse_cell(value: &str) -> Result<(u32, u32), String> {
+    let parts: Vec<&str> = value.split(',').map(str::trim).collect();
+    match parts.as_slice() {
+        [x, y] => {
+            let x = x.parse::<u32>().map_err(|e| format!("invalid column {:?}: {}", x, e))?;
+            let y = y.parse::<u32>().map_err(|e| format!("invalid row {:?}: {}", y, e))?;
+            Ok((x, y))
+        }
+        _ => Err(format!("expected X,Y, got {:?}", value)),
+    }
+}
+
+/// Parses an observer written as `X,Y,H`: a cell column and row, and a height above the ground.
+pub fn parse_observer(value: &str) -> Result<(u32, u32, f32), String> {
+    let parts: Vec<&str> = value.split(',').map(str::trim).collect();
+    match parts.as_slice() {
+        [x, y, h] => {
+            let x = x.parse::<u32>().map_err(|e| format!("invalid column {:?}: {}", x, e))?;
+            let y = y.parse::<u32>().map_err(|e| format!("invalid row {:?}: {}", y, e))?;
+            let h = h.parse::<f32>().map_err(|e| format!("invalid height {:?}: {}", h, e))?;
+            Ok((x, y, h))
+        }
+        _ => Err(format!("expected X,Y,H, got {:?}", value)),
+    }
+}
+
+/// Parses a transect written as `X0,Y0,X1,Y1`: the column and row of its start and end cells.
+pub fn parse_transect(value: &str) -> Result<(u32, u32, u32, u32), String> {
+    let coords = value
+        .split(',')
+        .map(|part| part.trim().parse::<u32>().map_err(|e| format!("invalid cell index {:?}: {}", part.trim(), e)))
+        .collect::<Result<Vec<u32>, String>>()?;
+    match coords.as_slice() {
+        [x0, y0, x1, y1] => Ok((*x0, *y0, *x1, *y1)),
+        _ => Err(format!("expected X0,Y0,X1,Y1, got {:?}", value)),
+    }
+}
+
+/// Parses a strictly positive, finite number such as a cell size.
+pub fn parse_positive(value: &str) -> Result<f32, String> {
+    match value.trim().parse::<f32>() {
+        Ok(number) if number > 0.0 && number.is_finite() => Ok(number),
+        Ok(number) => Err(format!("expected a positive number, got {}", number)),
+        Err(e) => Err(format!("invalid number {:?}: {}", value, e)),
+    }
+}
+
+/// Parses a fraction from 0 to 1 inclusive, such as the `--ambient` light.
+pub fn parse_fraction(value: &str) -> Result<f32, String> {
+    match value.trim().parse::<f32>() {
+        Ok(number) if (0.0..=1.0).contains(&number) => Ok(number),
+        Ok(number) => Err(format!("expected a number from 0 to 1, got {}", number)),
+        Err(e) => Err(format!("invalid number {:?}: {}", value, e)),
+    }
+}
+
+/// Parses a `--timestamp-format`, rejecting invalid strftime specifiers and path separators.
+pub fn parse_timestamp_format(value: &str) -> Result<String, String> {
+    if value.contains(['/', '\\']) {
+        return Err(format!("{:?} would put a directory separator in the file names", value));
+    }
+    if StrftimeItems::new(value).any(|item| matches!(item, Item::Error)) {
+        return Err(format!("invalid strftime format {:?}", value));
+    }
+    Ok(value.to_string())
+}
+
+/// Parses a `--gradient` name, rejecting names `color::gradient_by_name` does not know.
+pub fn parse_gradient_name(value: &str) -> Result<String, String> {
+    match gradient_by_name(value) {
+        Some(_) => Ok(value.to_string()),
+        None => Err(format!("unknown gradient {:?}, see --list-gradients", value)),
+    }
+}
+
+/// Parses a normalization mode: `minmax`, `percentile:LOW,HIGH` (or `percentile:P` for `P,100-P`) or `stddev:K`.
+pub fn parse_norm_mode(value: &str) -> Result<NormMode, String> {
+    let number = |v: &str| v.trim().parse::<f32>().map_err(|e| format!("invalid number {:?}: {}", v, e));
+    let (mode, parameters) = value.split_once(':').unwrap_or((value, ""));
+    match (mode.trim().to_ascii_lowercase().as_str(), parameters) {
+        ("minmax", "") => Ok(NormMode::MinMax),
+        ("percentile", parameters) => {
+            let (low, high) = match parameters.split_once(',') {
+                Some((low, high)) => (number(low)?, number(high)?),
+                None => (number(parameters)?, 100.0 - number(parameters)?),
+            };
+            if !(0.0..high).contains(&low) || high > 100.0 {
+                return Err(format!("expected percentiles with 0 <= LOW < HIGH <= 100, got {:?}", value));
+            }
+            Ok(NormMode::Percentile(low, high))
+        }
+        ("stddev", parameters) => match number(parameters)? {
+            k if k > 0.0 => Ok(NormMode::StdDev(k)),
+            k => Err(format!("expected a positive number of standard deviations, got {}", k)),
+        },
+        _ => Err(format!("expected minmax, percentile:LOW,HIGH or stddev:K, got {:?}", value)),
+    }
+}
+
+/// Parses a grayscale bit depth, either 8 or 16.
+pub fn parse_bit_depth(value: &str) -> Result<u8, String> {
+    match value.trim() {
+        "8" => Ok(8),
+        "16" => Ok(16),
+        _ => Err(format!("expected a bit depth of 8 or 16, got {:?}", value)),
+    }
+}
+
+/// Parses an opaque color written as `R,G,B` with components from 0 to 255.
+pub fn parse_rgb(value: &str) -> Result<Rgba<u8>, String> {
+    let parts: Vec<&str> = value.split(',').map(str::trim).collect();
+    match parts.as_slice() {
+        [r, g, b] => {
+            let channel = |c: &str| c.parse::<u8>().map_err(|e| format!("invalid color component {:?}: {}", c, e));
+            Ok(Rgba([channel(r)?, channel(g)?, channel(b)?, 255]))
+        }
+        _ => Err(format!("expected a color as R,G,B, got {:?}", value)),
+    }
+}
@@ -0,0 +1,360 @@
+use std::error::Error;
+use std::fs;
+use colorgrad::{preset, Gradient};
+use image::{GrayImage, Luma, Rgba, RgbaImage};
+
+/// Colors elevations in discrete bands, like the stepped tints of classic atlases.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the image.
+/// * `height` - The height of the image.
+/// * `band_size` - The height of one band in elevation units (e.g. 100 m).
+/// * `gradient` - The gradient sampled once per band.
+/// * `reverse` - Run the gradient from the highest band to the lowest.
+/// # Returns
+/// * A `RgbaImage` with one flat color per band and transparent NaN cells.
+///
+/// Bands are aligned on multiples of `band_size` (0-100, 100-200, ...), and the band colors are
+/// spread evenly over the gradient between the lowest and highest band present.
+pub fn hypsometric_tint(data: &Vec<f32>, width: u32, height: u32, band_size: f32, gradient: &dyn Gradient, reverse: bool) -> RgbaImage {
+    let mut image = RgbaImage::new(width, height);
+    let band_of = |value: f32| (value / band_size).floor() as i64;
+    let valid = data.iter().cloned().filter(|v| !v.is_nan());
+    let min_band = valid.clone().map(band_of).min().unwrap_or(0);
+    let max_band = valid.map(band_of).max().unwrap_or(0);
+    let band_count = (max_band - min_band) as f32;
+
+    for (i, &value) in data.iter().enumerate() {
+        if value.is_nan() {
+            continue;
+        }
+        let x = (i % width as usize) as u32;
+        let y = (i / width as usize) as u32;
+        let t = if band_count > 0.0 { (band_of(value) - min_band) as f32 / band_count } else { 0.0 };
+        let t = if reverse { 1.0 - t } else { t };
+        let [r, g, b, _] = gradient.at(t).to_rgba8();
+        image.put_pixel(x, y, Rgba([r, g, b, 255]));
+    }
+    image
+}
+
+/// Converts elevation data into a grayscale image using histogram equalization.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the image.
+/// * `height` - The height of the image.
+/// # Returns
+/// * A `GrayImage` where each cell is mapped through the cumulative distribution of the valid elevations.
+///
+/// Unlike the linear mapping of `data_to_grayscale`, equalization spreads the 0..255 range over the
+/// elevations that actually occur, which brings out detail in flat lowlands. NaN cells map to 0.
+pub fn data_to_grayscale_equalized(data: &Vec<f32>, width: u32, height: u32) -> GrayImage {
+    let mut image = GrayImage::new(width, height);
+    let mut sorted: Vec<f32> = data.iter().cloned().filter(|v| !v.is_nan()).collect();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    if sorted.is_empty() {
+        return image;
+    }
+
+    // cdf(v) = fraction of valid cells <= v, rescaled so the lowest elevation maps to 0
+    let count = sorted.len() as f32;
+    let cdf = |value: f32| sorted.partition_point(|&v| v <= value) as f32 / count;
+    let cdf_min = cdf(sorted[0]);
+    let span = 1.0 - cdf_min;
+
+    for (i, &value) in data.iter().enumerate() {
+        let x = (i % width as usize) as u32;
+        let y = (i / width as usize) as u32;
+        let pixel_value = if value.is_nan() || span <= 0.0 {
+            0
+        } else {
+            (255.0 * (cdf(value) - cdf_min) / span).round() as u8
+        };
+        image.put_pixel(x, y, Luma([pixel_value]));
+    }
+    image
+}
+
+/// Encodes elevations as Mapbox Terrain-RGB, where `height = -10000 + (R * 256 * 256 + G * 256 + B) * 0.1`.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the image.
+/// * `height` - The height of the image.
+/// # Returns
+/// * An opaque `RgbaImage` holding the elevations with a 0.1 m precision, from -10000 m up to
+///   about 1667721 m. NaN cells get the Mapbox no-data sentinel (0, 0, 0).
+pub fn terrain_rgb(data: &Vec<f32>, width: u32, height: u32) -> RgbaImage {
+    let mut image = RgbaImage::new(width, height);
+    for (i, &value) in data.iter().enumerate() {
+        let code = if value.is_nan() {
+            0
+        } else {
+            ((value as f64 + 10000.0) * 10.0).round().clamp(0.0, 0xFF_FFFF as f64) as u32
+        };
+        let color = Rgba([(code >> 16) as u8, (code >> 8) as u8, code as u8, 255]);
+        image.put_pixel(i as u32 % width, i as u32 / width, color);
+    }
+    image
+}
+
+/// Renders signed values, such as elevation changes, with a diverging red-white-blue ramp.
+/// # Arguments
+/// * `data` - A vector of f32 holding the signed values.
+/// * `width` - The width of the image.
+/// * `height` - The height of the image.
+/// # Returns
+/// * An `RgbaImage` where zero is white, negative values fade to red (loss) and positive values
+///   to blue (gain). The ramp is symmetric around zero and scaled to the largest magnitude of the map.
+///   NaN cells are transparent.
+pub fn diverging_map(data: &Vec<f32>, width: u32, height: u32) -> RgbaImage {
+    let max_abs = data.iter().filter(|v| !v.is_nan()).fold(0.0f32, |m, v| m.max(v.abs()));
+    let mut image = RgbaImage::new(width, height);
+    for (i, &value) in data.iter().enumerate() {
+        if value.is_nan() {
+            continue;
+        }
+        let t = if max_abs > 0.0 { value / max_abs } else { 0.0 };
+        let fade = (255.0 * (1.0 - t.abs())).round() as u8;
+        let color = if t >= 0.0 { Rgba([fade, fade, 255, 255]) } else { Rgba([255, fade, fade, 255]) };
+        image.put_pixel(i as u32 % width, i as u32 / width, color);
+    }
+    image
+}
+
+/// Water color used by `--sea-level`, blended at about 80% over the map.
+pub const WATER_COLOR: Rgba<u8> = Rgba([38, 102, 191, 204]);
+
+/// Tints the cells at or below sea level as water.
+/// # Arguments
+/// * `image` - The color map to modify, of the grid size.
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the image.
+/// * `height` - The height of the image.
+/// * `sea_level` - Cells with an elevation up to this value are water.
+/// * `water_color` - The water color, blended over the existing pixel with its alpha as opacity.
+///
+/// NaN cells are left as they are, so NoData stays visible as such.
+pub fn overlay_water(image: &mut RgbaImage, data: &Vec<f32>, width: u32, height: u32, sea_level: f32, water_color: Rgba<u8>) {
+    let opacity = water_color[3] as f32 / 255.0;
+    for (i, &value) in data.iter().enumerate().take((width * height) as usize) {
+        if value.is_nan() || value > sea_level {
+            continue;
+        }
+        let pixel = image.get_pixel_mut(i as u32 % width, i as u32 / width);
+        for c in 0..3 {
+            pixel[c] = (water_color[c] as f32 * opacity + pixel[c] as f32 * (1.0 - opacity)).round() as u8;
+        }
+        pixel[3] = pixel[3].max(water_color[3]);
+    }
+}
+
+/// Reads a color ramp from a GDAL color-relief text file.
+/// # Arguments
+/// * `path` - The ramp file: one `elevation R G B [A]` stop per line, the values separated by
+///   spaces, tabs, commas or colons. Blank lines and lines starting with `#` are ignored.
+/// # Returns
+/// * The stops sorted by elevation. The optional `nv R G B [A]` line (the NoData color) is
+///   returned with a NaN elevation.
+pub fn load_color_ramp(path: &str) -> Result<Vec<(f32, [u8; 4])>, Box<dyn Error>> {
+    let content = fs::read_to_string(path).map_err(|e| format!("cannot read color ramp {}: {}", path, e))?;
+    let mut ramp = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(|c: char| c.is_whitespace() || c == ',' || c == ':').filter(|f| !f.is_empty()).collect();
+        if fields.len() != 4 && fields.len() != 5 {
+            return Err(format!("{} line {}: expected `elevation R G B [A]`, got {:?}", path, number + 1, line).into());
+        }
+        let elevation = if fields[0].eq_ignore_ascii_case("nv") {
+            f32::NAN
+        } else {
+            fields[0].parse::<f32>().map_err(|e| format!("{} line {}: invalid elevation {:?}: {}", path, number + 1, fields[0], e))?
+        };
+        let mut color = [255u8; 4];
+        for (channel, field) in color.iter_mut().zip(&fields[1..]) {
+            *channel = field.parse().map_err(|e| format!("{} line {}: invalid color component {:?}: {}", path, number + 1, field, e))?;
+        }
+        ramp.push((elevation, color));
+    }
+    if ramp.iter().all(|(elevation, _)| elevation.is_nan()) {
+        return Err(format!("color ramp {} has no elevation stop", path).into());
+    }
+    ramp.sort_by(|a, b| a.0.total_cmp(&b.0));
+    Ok(ramp)
+}
+
+/// Colors elevations with a custom ramp, as loaded by `load_color_ramp`.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the image.
+/// * `height` - The height of the image.
+/// * `ramp` - The elevation stops, sorted by elevation. A stop with a NaN elevation colors the NaN cells.
+/// # Returns
+/// * A `RgbaImage` with colors linearly interpolated between the two stops around each elevation.
+///   Elevations outside the ramp take the color of the closest end stop, and NaN cells are
+///   transparent unless the ramp has a NoData stop.
+pub fn rgb_with_ramp(data: &Vec<f32>, width: u32, height: u32, ramp: &[(f32, [u8; 4])]) -> RgbaImage {
+    let stops: Vec<(f32, [u8; 4])> = ramp.iter().copied().filter(|(elevation, _)| !elevation.is_nan()).collect();
+    let nodata = ramp.iter().find(|(elevation, _)| elevation.is_nan()).map_or([0; 4], |(_, color)| *color);
+    let mut image = RgbaImage::new(width, height);
+    for (i, &value) in data.iter().enumerate() {
+        let color = if value.is_nan() || stops.is_empty() {
+            nodata
+        } else {
+            let upper = stops.partition_point(|(elevation, _)| *elevation < value);
+            if upper == 0 {
+                stops[0].1
+            } else if upper == stops.len() {
+                stops[stops.len() - 1].1
+            } else {
+                let ((z0, c0), (z1, c1)) = (stops[upper - 1], stops[upper]);
+                let t = if z1 > z0 { (value - z0) / (z1 - z0) } else { 1.0 };
+                let mix = |c: usize| (c0[c] as f32 + (c1[c] as f32 - c0[c] as f32) * t).round() as u8;
+                [mix(0), mix(1), mix(2), mix(3)]
+            }
+        };
+        image.put_pixel(i as u32 % width, i as u32 / width, Rgba(color));
+    }
+    image
+}
+
+/// Names of the color gradients accepted by `gradient_by_name`, as printed by `--list-gradients`.
+pub const GRADIENT_NAMES: &[&str] = &[
+    "turbo", "viridis", "magma", "inferno", "plasma", "cividis", "spectral", "rainbow", "sinebow",
+    "cubehelix", "warm", "cool", "blues", "greens", "greys", "oranges", "purples", "reds",
+    "br_bg", "rd_yl_bu", "rd_yl_gn", "yl_gn", "yl_or_br", "yl_or_rd",
+];
+
+/// Returns the colorgrad preset gradient with this name, one of `GRADIENT_NAMES`.
+/// # Returns
+/// * `None` for an unknown name.
+pub fn gradient_by_name(name: &str) -> Option<Box<dyn Gradient>> {
+    let gradient: Box<dyn Gradient> = match name {
+        "turbo" => Box::new(preset::turbo()),
+        "viridis" => Box::new(preset::viridis()),
+        "magma" => Box::new(preset::magma()),
+        "inferno" => Box::new(preset::inferno()),
+        "plasma" => Box::new(preset::plasma()),
+        "cividis" => Box::new(preset::cividis()),
+        "spectral" => Box::new(preset::spectral()),
+        "rainbow" => Box::new(preset::rainbow()),
+        "sinebow" => Box::new(preset::sinebow()),
+        "cubehelix" => Box::new(preset::cubehelix_default()),
+        "warm" => Box::new(preset::warm()),
+        "cool" => Box::new(preset::cool()),
+        "blues" => Box::new(preset::blues()),
+        "greens" => Box::new(preset::greens()),
+        "greys" => Box::new(preset::greys()),
+        "oranges" => Box::new(preset::oranges()),
+        "purples" => Box::new(preset::purples()),
+        "reds" => Box::new(preset::reds()),
+        "br_bg" => Box::new(preset::br_bg()),
+        "rd_yl_bu" => Box::new(preset::rd_yl_bu()),
+        "rd_yl_gn" => Box::new(preset::rd_yl_gn()),
+        "yl_gn" => Box::new(preset::yl_gn()),
+        "yl_or_br" => Box::new(preset::yl_or_br()),
+        "yl_or_rd" => Box::new(preset::yl_or_rd()),
+        _ => return None,
+    };
+    Some(gradient)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_to_grayscale;
+    use std::collections::HashSet;
+
+    #[test]
+    /// Elevations in the same band share a color, the next band differs and NaN is transparent.
+    fn test_hypsometric_tint_bands() {
+        let data = vec![105.0, 180.0, 250.0, f32::NAN, 420.0];
+        let image = hypsometric_tint(&data, 5, 1, 100.0, &preset::turbo(), false);
+        assert_eq!(image.get_pixel(0, 0), image.get_pixel(1, 0));
+        assert_ne!(image.get_pixel(1, 0), image.get_pixel(2, 0));
+        assert_eq!(image.get_pixel(3, 0), &Rgba([0, 0, 0, 0]));
+        assert_eq!(image.get_pixel(4, 0)[3], 255);
+
+        // reversed, the lowest band takes the color of the highest one
+        let reversed = hypsometric_tint(&data, 5, 1, 100.0, &preset::turbo(), true);
+        assert_eq!(reversed.get_pixel(0, 0), image.get_pixel(4, 0));
+        assert_eq!(reversed.get_pixel(4, 0), image.get_pixel(0, 0));
+    }
+
+    #[test]
+    /// Cells at or below sea level become the water color, the others and NaN cells are unchanged.
+    fn test_overlay_water() {
+        let data = vec![-3.0, 0.0, 0.5, 120.0, f32::NAN];
+        let land = Rgba([90, 160, 60, 255]);
+        let mut image = RgbaImage::from_pixel(5, 1, land);
+        let blue = Rgba([0, 0, 255, 255]);
+        overlay_water(&mut image, &data, 5, 1, 0.0, blue);
+        assert_eq!((image.get_pixel(0, 0), image.get_pixel(1, 0)), (&blue, &blue));
+        assert!((2..5).all(|x| image.get_pixel(x, 0) == &land));
+
+        let mut image = RgbaImage::from_pixel(1, 1, land);
+        overlay_water(&mut image, &vec![-1.0], 1, 1, 0.0, WATER_COLOR);
+        let water = image.get_pixel(0, 0);
+        assert!(water[2] > water[0] && water[2] > water[1] && water[2] < 255, "{:?}", water);
+    }
+
+    #[test]
+    /// Every listed gradient name resolves to a preset, including turbo and viridis.
+    fn test_gradient_names() {
+        assert!(GRADIENT_NAMES.contains(&"turbo") && GRADIENT_NAMES.contains(&"viridis"));
+        for name in GRADIENT_NAMES {
+            assert!(gradient_by_name(name).is_some(), "{}", name);
+        }
+        assert!(gradient_by_name("no-such-gradient").is_none());
+        assert_eq!(gradient_by_name("turbo").unwrap().at(0.3).to_rgba8(), preset::turbo().at(0.3).to_rgba8());
+    }
+
+    #[test]
+    /// Elevations survive the Terrain-RGB encoding within 0.1 m, and NaN becomes black.
+    fn test_terrain_rgb_round_trip() {
+        let data = vec![4807.3, -432.1, 0.0, f32::NAN];
+        let image = terrain_rgb(&data, 4, 1);
+        for (x, &elevation) in data.iter().take(3).enumerate() {
+            let [r, g, b, a] = image.get_pixel(x as u32, 0).0;
+            let decoded = -10000.0 + (r as f64 * 256.0 * 256.0 + g as f64 * 256.0 + b as f64) * 0.1;
+            assert!((decoded - elevation as f64).abs() <= 0.1, "{} decoded as {}", elevation, decoded);
+            assert_eq!(a, 255);
+        }
+        assert_eq!(image.get_pixel(3, 0), &Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    /// On a distribution skewed by one high cell, equalization uses far more gray levels than the linear mapping.
+    fn test_equalized_spreads_skewed_data() {
+        let data = vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 1000.0, f32::NAN];
+        let linear = data_to_grayscale(data.clone(), 11, 1, None, None, 1.0).unwrap();
+        let equalized = data_to_grayscale_equalized(&data, 11, 1);
+
+        let levels = |img: &GrayImage| img.pixels().take(10).map(|p| p[0]).collect::<HashSet<u8>>().len();
+        assert!(levels(&equalized) > levels(&linear));
+        assert_eq!(equalized.get_pixel(0, 0), &Luma([0]));
+        assert_eq!(equalized.get_pixel(9, 0), &Luma([255]));
+        assert!(equalized.get_pixel(4, 0)[0] > 100);
+        assert_eq!(equalized.get_pixel(10, 0), &Luma([0]));
+    }
+
+    #[test]
+    /// A two-stop ramp gives the average color halfway, clamps outside its range and colors NoData with `nv`.
+    fn test_color_ramp_interpolation() {
+        let path = std::env::temp_dir().join("dem_color_ramp.txt");
+        fs::write(&path, "# elevation R G B\n100 0 0 200\n\n300,200,100,0\nnv 9 9 9 0\n").unwrap();
+        let ramp = load_color_ramp(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(ramp.len(), 3);
+        assert_eq!(ramp[0], (100.0, [0, 0, 200, 255]));
+
+        let data = vec![200.0, 50.0, 1000.0, f32::NAN];
+        let image = rgb_with_ramp(&data, 4, 1, &ramp);
+        assert_eq!(image.get_pixel(0, 0), &Rgba([100, 50, 100, 255]));
+        assert_eq!(image.get_pixel(1, 0), &Rgba([0, 0, 200, 255]));
+        assert_eq!(image.get_pixel(2, 0), &Rgba([200, 100, 0, 255]));
+        assert_eq!(image.get_pixel(3, 0), &Rgba([9, 9, 9, 0]));
+    }
+}
@@ -0,0 +1,137 @@
+/// Neighborhood used to decide whether two mask cells touch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+    /// Only the left, right, top and bottom neighbors.
+    Four,
+    /// The four orthogonal neighbors plus the diagonals.
+    Eight,
+}
+
+/// Disjoint-set forest with path halving and union by size.
+struct UnionFind {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(n: usize) -> Self {
+        UnionFind { parent: (0..n).collect(), size: vec![1; n] }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        if self.size[a] < self.size[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parent[b] = a;
+        self.size[a] += self.size[b];
+    }
+}
+
+/// Labels the connected regions of a boolean mask.
+/// # Arguments
+/// * `mask` - One flag per cell, `true` for cells that belong to a region.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `connectivity` - Whether diagonal neighbors connect regions.
+/// # Returns
+/// * A tuple with one label per cell (0 for cells outside the mask, regions numbered from 1)
+///   and the number of regions.
+pub fn label_components(mask: &[bool], width: u32, height: u32, connectivity: Connectivity) -> (Vec<u32>, u32) {
+    let (w, h) = (width as usize, height as usize);
+    let mut sets = UnionFind::new(w * h);
+
+    // Only look at neighbors that were already visited (above and to the left)
+    let mut neighbors: Vec<(isize, isize)> = vec![(-1, 0), (0, -1)];
+    if connectivity == Connectivity::Eight {
+        neighbors.extend([(-1, -1), (1, -1)]);
+    }
+
+    for y in 0..h {
+        for x in 0..w {
+            let idx = y * w + x;
+            if !mask[idx] {
+                continue;
+            }
+            for &(dx, dy) in &neighbors {
+                let (nx, ny) = (x as isize + dx, y as isize + dy);
+                if nx < 0 || ny < 0 || nx >= w as isize {
+                    continue;
+                }
+                let neighbor = ny as usize * w + nx as usize;
+                if mask[neighbor] {
+                    sets.union(idx, neighbor);
+                }
+            }
+        }
+    }
+
+    // Second pass: give each root a compact label
+    let mut root_labels = vec![0u32; w * h];
+    let mut labels = vec![0u32; w * h];
+    let mut count = 0;
+    for idx in 0..w * h {
+        if !mask[idx] {
+            continue;
+        }
+        let root = sets.find(idx);
+        if root_labels[root] == 0 {
+            count += 1;
+            root_labels[root] = count;
+        }
+        labels[idx] = root_labels[root];
+    }
+    (labels, count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mask_from(rows: &[&str]) -> (Vec<bool>, u32, u32) {
+        let mask = rows.iter().flat_map(|row| row.chars().map(|c| c == '#')).collect();
+        (mask, rows[0].len() as u32, rows.len() as u32)
+    }
+
+    #[test]
+    /// Two separate blobs get two distinct labels and the background stays 0.
+    fn test_label_two_blobs() {
+        let (mask, width, height) = mask_from(&[
+            "##...",
+            "##..#",
+            "....#",
+        ]);
+        let (labels, count) = label_components(&mask, width, height, Connectivity::Four);
+        assert_eq!(count, 2);
+        assert_eq!(labels[0], labels[6]);
+        assert_eq!(labels[9], labels[14]);
+        assert_ne!(labels[0], labels[9]);
+        assert_eq!(labels[2], 0);
+    }
+
+    #[test]
+    /// Cells touching only by a corner merge under 8-connectivity but not under 4-connectivity.
+    fn test_label_diagonal_touch() {
+        let (mask, width, height) = mask_from(&[
+            "#..",
+            ".#.",
+            "..#",
+        ]);
+        let (_, count4) = label_components(&mask, width, height, Connectivity::Four);
+        let (labels8, count8) = label_components(&mask, width, height, Connectivity::Eight);
+        assert_eq!(count4, 3);
+        assert_eq!(count8, 1);
+        assert_eq!(labels8[0], labels8[8]);
+    }
+}
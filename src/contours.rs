@@ -0,0 +1,213 @@
+use image::{imageops, Rgba, RgbaImage};
+use imageproc::drawing::{draw_line_segment_mut, draw_text_mut, text_size};
+use imageproc::geometric_transformations::{rotate_about_center, Interpolation};
+
+use crate::annotate::font;
+
+/// Color of the regular contour lines.
+const MINOR_COLOR: Rgba<u8> = Rgba([90, 60, 30, 160]);
+/// Color of the labeled (major) contour lines and their labels.
+const MAJOR_COLOR: Rgba<u8> = Rgba([70, 40, 15, 255]);
+
+/// Height of the contour labels in pixels.
+pub const CONTOUR_LABEL_SIZE: f32 = 12.0;
+
+/// Minimum distance in pixels between two labels of the same contour level.
+pub const LABEL_SPACING: f32 = 200.0;
+
+/// A straight piece of an isoline, in pixel coordinates (cell centers at integer positions).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+    pub level: f32,
+    pub start: (f32, f32),
+    pub end: (f32, f32),
+}
+
+/// A contour label placed by `draw_labeled_contours`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContourLabel {
+    pub text: String,
+    /// Center of the label in pixels.
+    pub position: (f32, f32),
+    /// Clockwise rotation of the text in radians, between -π/2 and π/2 so it never reads upside down.
+    pub angle: f32,
+}
+
+/// Traces the isolines of a grid with marching squares.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `interval` - Elevation step between two contour levels; levels are multiples of it.
+/// # Returns
+/// * The contour segments of every level. Squares with a NaN corner are left out, and saddles
+///   are resolved by pairing the crossings in edge order.
+pub fn contour_segments(data: &Vec<f32>, width: u32, height: u32, interval: f32) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    if interval <= 0.0 {
+        return segments;
+    }
+    let at = |x: u32, y: u32| data[(y * width + x) as usize];
+    for y in 0..height.saturating_sub(1) {
+        for x in 0..width.saturating_sub(1) {
+            let corners = [
+                ((x as f32, y as f32), at(x, y)),
+                ((x as f32 + 1.0, y as f32), at(x + 1, y)),
+                ((x as f32 + 1.0, y as f32 + 1.0), at(x + 1, y + 1)),
+                ((x as f32, y as f32 + 1.0), at(x, y + 1)),
+            ];
+            if corners.iter().any(|(_, z)| z.is_nan()) {
+                continue;
+            }
+            let low = corners.iter().map(|(_, z)| *z).fold(f32::INFINITY, f32::min);
+            let high = corners.iter().map(|(_, z)| *z).fold(f32::NEG_INFINITY, f32::max);
+            let mut k = (low / interval).ceil() as i64;
+            while (k as f32) * interval <= high {
+                let level = k as f32 * interval;
+                // points where the level crosses the four edges, walking around the square
+                let crossings: Vec<(f32, f32)> = (0..4)
+                    .filter_map(|i| {
+                        let ((p, a), (q, b)) = (corners[i], corners[(i + 1) % 4]);
+                        if (a >= level) == (b >= level) {
+                            return None;
+                        }
+                        let t = (level - a) / (b - a);
+                        Some((p.0 + (q.0 - p.0) * t, p.1 + (q.1 - p.1) * t))
+                    })
+                    .collect();
+                for pair in crossings.chunks_exact(2) {
+                    segments.push(Segment { level, start: pair[0], end: pair[1] });
+                }
+                k += 1;
+            }
+        }
+    }
+    segments
+}
+
+/// Draws contour lines over an image, labeling every `label_every`-th level with its elevation.
+/// # Arguments
+/// * `image` - The map to draw on, of the same size as the grid.
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `interval` - Elevation step between two contour lines.
+/// * `label_every` - Levels that are a multiple of `label_every * interval` are drawn darker and
+///   labeled (e.g. 5 with a 10 m interval labels 0, 50, 100 m...). `0` disables the labels.
+/// # Returns
+/// * The labels that were drawn.
+///
+/// Labels are repeated at least `LABEL_SPACING` pixels apart along each major contour and
+/// rotated to follow the line. A label whose box would overlap a previous label is dropped.
+pub fn draw_labeled_contours(image: &mut RgbaImage, data: &Vec<f32>, width: u32, height: u32, interval: f32, label_every: u32) -> Vec<ContourLabel> {
+    let is_major = |level: f32| label_every > 0 && ((level / interval).round() as i64).rem_euclid(label_every as i64) == 0;
+    let segments = contour_segments(data, width, height, interval);
+    for segment in &segments {
+        let color = if is_major(segment.level) { MAJOR_COLOR } else { MINOR_COLOR };
+        draw_line_segment_mut(image, segment.start, segment.end, color);
+    }
+
+    let font = font();
+    let mut labels: Vec<ContourLabel> = Vec::new();
+    let mut boxes: Vec<((f32, f32), f32)> = Vec::new();
+    for segment in segments.iter().filter(|s| is_major(s.level)) {
+        let text = format_level(segment.level, interval);
+        let (text_width, text_height) = text_size(CONTOUR_LABEL_SIZE, &font, &text);
+        let center = ((segment.start.0 + segment.end.0) / 2.0, (segment.start.1 + segment.end.1) / 2.0);
+        let radius = (text_width.max(text_height) as f32) / 2.0 + 2.0;
+        let distance = |p: (f32, f32)| ((p.0 - center.0).powi(2) + (p.1 - center.1).powi(2)).sqrt();
+        let too_close = labels.iter().any(|l| l.text == text && distance(l.position) < LABEL_SPACING)
+            || boxes.iter().any(|&(p, r)| distance(p) < r + radius);
+        let inside = center.0 >= radius && center.1 >= radius && center.0 + radius < image.width() as f32 && center.1 + radius < image.height() as f32;
+        if too_close || !inside {
+            continue;
+        }
+
+        let (dx, dy) = (segment.end.0 - segment.start.0, segment.end.1 - segment.start.1);
+        let mut angle = dy.atan2(dx);
+        if angle > std::f32::consts::FRAC_PI_2 {
+            angle -= std::f32::consts::PI;
+        } else if angle <= -std::f32::consts::FRAC_PI_2 {
+            angle += std::f32::consts::PI;
+        }
+        // render the text flat on a square canvas large enough for any rotation, then turn it
+        let side = (2.0 * radius).ceil() as u32;
+        let mut canvas = RgbaImage::new(side, side);
+        let (left, top) = ((side - text_width) as i32 / 2, (side - text_height) as i32 / 2);
+        draw_text_mut(&mut canvas, MAJOR_COLOR, left, top, CONTOUR_LABEL_SIZE, &font, &text);
+        let rotated = rotate_about_center(&canvas, angle, Interpolation::Bilinear, Rgba([0, 0, 0, 0]));
+        imageops::overlay(image, &rotated, (center.0 - side as f32 / 2.0) as i64, (center.1 - side as f32 / 2.0) as i64);
+
+        boxes.push((center, radius));
+        labels.push(ContourLabel { text, position: center, angle });
+    }
+    labels
+}
+
+/// Picks a round contour interval (1, 2 or 5 times a power of ten) so about `target` contours span a range.
+/// # Arguments
+/// * `min` - The lowest elevation.
+/// * `max` - The highest elevation.
+/// * `target` - The number of contours wanted.
+/// # Returns
+/// * The round interval closest to `(max - min) / target` on a logarithmic scale, or 1 for an empty range.
+pub fn nice_interval(min: f32, max: f32, target: u32) -> f32 {
+    let raw = (max - min) / target.max(1) as f32;
+    if raw.is_nan() || raw <= 0.0 {
+        return 1.0;
+    }
+    let magnitude = 10f32.powf(raw.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|step| step * magnitude)
+        .min_by(|a, b| (a / raw).ln().abs().total_cmp(&(b / raw).ln().abs()))
+        .unwrap_or(magnitude)
+}
+
+/// Formats a contour level with as many decimals as the interval needs.
+pub(crate) fn format_level(level: f32, interval: f32) -> String {
+    if interval.fract() == 0.0 {
+        format!("{}", level.round())
+    } else {
+        let decimals = (-interval.fract().log10().floor()).max(1.0) as usize;
+        format!("{:.*}", decimals, level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// A plane rising eastwards has vertical contours, and the 100 m line gets an upright label near x = 100.
+    fn test_labeled_contours_on_plane() {
+        let (width, height) = (240, 120);
+        let data: Vec<f32> = (0..width * height).map(|i| (i % width) as f32).collect();
+        let background = Rgba([255, 255, 255, 255]);
+        let mut image = RgbaImage::from_pixel(width, height, background);
+        let labels = draw_labeled_contours(&mut image, &data, width, height, 10.0, 5);
+
+        let label = labels.iter().find(|l| l.text == "100").expect("the 100 m contour is labeled");
+        assert!((label.position.0 - 100.0).abs() < 1.0);
+        assert!((label.angle.abs() - std::f32::consts::FRAC_PI_2).abs() < 1e-3);
+        assert!(labels.iter().all(|l| l.text.parse::<f32>().unwrap() % 50.0 == 0.0));
+        // one label per major line on this short map, none closer than their own size
+        assert_eq!(labels.iter().filter(|l| l.text == "100").count(), 1);
+        // the minor 30 m contour is drawn, and the label inked pixels off its line
+        assert_ne!(image.get_pixel(30, 5), &background);
+        let (x, y) = (label.position.0 as u32, label.position.1 as u32);
+        assert!((y - 4..y + 4).any(|y| (x - 6..x + 6).any(|x| x != 100 && image.get_pixel(x, y)[0] < 150)));
+    }
+
+    #[test]
+    /// The interval is a round value giving about the requested number of contours.
+    fn test_nice_interval() {
+        assert_eq!(nice_interval(0.0, 1000.0, 10), 100.0);
+        assert_eq!(nice_interval(120.0, 1480.0, 10), 100.0);
+        assert_eq!(nice_interval(0.0, 1000.0, 6), 200.0);
+        assert_eq!(nice_interval(0.0, 1000.0, 3), 500.0);
+        assert!((nice_interval(-3.0, 12.0, 20) - 1.0).abs() < 1e-6);
+        assert!((nice_interval(0.0, 2.4, 12) - 0.2).abs() < 1e-6);
+        assert_eq!(nice_interval(5.0, 5.0, 10), 1.0);
+    }
+}
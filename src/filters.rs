@@ -0,0 +1,175 @@
+/// Builds a normalized 1D Gaussian kernel covering three standard deviations on each side.
+fn gaussian_kernel(sigma: f32) -> Vec<f32> {
+    let radius = (3.0 * sigma).ceil() as i32;
+    let kernel: Vec<f32> = (-radius..=radius)
+        .map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let sum: f32 = kernel.iter().sum();
+    kernel.iter().map(|w| w / sum).collect()
+}
+
+/// Convolves the grid with a 1D kernel along one axis, skipping NaN cells and renormalizing the weights.
+fn convolve_axis(data: &[f32], width: u32, height: u32, kernel: &[f32], horizontal: bool) -> Vec<f32> {
+    let (w, h) = (width as i32, height as i32);
+    let radius = (kernel.len() / 2) as i32;
+    let mut out = vec![f32::NAN; data.len()];
+    for y in 0..h {
+        for x in 0..w {
+            let idx = (y * w + x) as usize;
+            if data[idx].is_nan() {
+                continue;
+            }
+            let (mut sum, mut weight) = (0.0, 0.0);
+            for (k, &kw) in kernel.iter().enumerate() {
+                let offset = k as i32 - radius;
+                let (nx, ny) = if horizontal { (x + offset, y) } else { (x, y + offset) };
+                if nx < 0 || ny < 0 || nx >= w || ny >= h {
+                    continue;
+                }
+                let value = data[(ny * w + nx) as usize];
+                if !value.is_nan() {
+                    sum += kw * value;
+                    weight += kw;
+                }
+            }
+            out[idx] = sum / weight;
+        }
+    }
+    out
+}
+
+/// Smooths elevation data with a separable Gaussian blur.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `sigma` - The standard deviation of the Gaussian, in cells.
+/// # Returns
+/// * The smoothed elevation data.
+///
+/// NaN cells stay NaN and are left out of their neighbors' averages (the remaining weights are
+/// renormalized), so holes don't bleed into valid terrain. Grid borders are handled the same way.
+pub fn gaussian_smooth(data: &Vec<f32>, width: u32, height: u32, sigma: f32) -> Vec<f32> {
+    if sigma <= 0.0 {
+        return data.clone();
+    }
+    let kernel = gaussian_kernel(sigma);
+    let rows = convolve_axis(data, width, height, &kernel, true);
+    convolve_axis(&rows, width, height, &kernel, false)
+}
+
+/// Replaces every cell with the median of its neighborhood, which removes isolated spikes
+/// while keeping edges sharp.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `radius` - The neighborhood is the `(2 * radius + 1)²` window around the cell, cut at the borders.
+/// # Returns
+/// * The filtered elevation data.
+///
+/// NaN cells are left out of the median, so a cell is NaN only when its whole window is NaN; small
+/// holes are filled from their neighbors. Windows with an even count take the mean of the two middle values.
+pub fn median_filter(data: &Vec<f32>, width: u32, height: u32, radius: u32) -> Vec<f32> {
+    let (w, h, r) = (width as i32, height as i32, radius as i32);
+    let mut out = vec![f32::NAN; data.len()];
+    let mut window = Vec::with_capacity(((2 * r + 1) * (2 * r + 1)) as usize);
+    for y in 0..h {
+        for x in 0..w {
+            window.clear();
+            for ny in (y - r).max(0)..=(y + r).min(h - 1) {
+                for nx in (x - r).max(0)..=(x + r).min(w - 1) {
+                    let value = data[(ny * w + nx) as usize];
+                    if !value.is_nan() {
+                        window.push(value);
+                    }
+                }
+            }
+            if window.is_empty() {
+                continue;
+            }
+            window.sort_by(|a, b| a.total_cmp(b));
+            let mid = window.len() / 2;
+            out[(y * w + x) as usize] = if window.len() % 2 == 1 { window[mid] } else { (window[mid - 1] + window[mid]) / 2.0 };
+        }
+    }
+    out
+}
+
+/// Sharpens relief with an unsharp mask: `data + amount * (data - gaussian(data))`.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `sigma` - The standard deviation of the blur, in cells.
+/// * `amount` - How much of the detail layer is added back.
+/// # Returns
+/// * The sharpened elevation data. Flat areas are unchanged and NaN cells stay NaN.
+pub fn unsharp_mask(data: &Vec<f32>, width: u32, height: u32, sigma: f32, amount: f32) -> Vec<f32> {
+    let blurred = gaussian_smooth(data, width, height, sigma);
+    data.iter()
+        .zip(blurred.iter())
+        .map(|(&value, &blur)| value + amount * (value - blur))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// A small bump on a flat plain gets taller while the distant plain keeps its elevation.
+    fn test_unsharp_mask_enhances_bump() {
+        let (width, height) = (21, 21);
+        let mut data = vec![10.0; (width * height) as usize];
+        let center = (10 * width + 10) as usize;
+        data[center] = 12.0;
+        data[center + 1] = 11.0;
+        data[center - 1] = 11.0;
+
+        let sharpened = unsharp_mask(&data, width, height, 1.5, 1.0);
+        assert!(sharpened[center] - 10.0 > data[center] - 10.0);
+        assert!((sharpened[0] - 10.0).abs() < 1e-5);
+    }
+
+    #[test]
+    /// A single spike spreads to its neighbors, with the total elevation above the plain conserved.
+    fn test_gaussian_smooth_spreads_spike() {
+        let (width, height) = (15, 15);
+        let mut data = vec![0.0; (width * height) as usize];
+        let center = (7 * width + 7) as usize;
+        data[center] = 100.0;
+
+        let smoothed = gaussian_smooth(&data, width, height, 1.0);
+        assert!(smoothed[center] < 100.0);
+        assert!(smoothed[center + 1] > 0.0 && smoothed[center + width as usize] > 0.0);
+        assert!(smoothed[center + 1] < smoothed[center]);
+        let mass: f32 = smoothed.iter().sum();
+        assert!((mass - 100.0).abs() < 0.01, "mass {}", mass);
+    }
+
+    #[test]
+    /// An extreme outlier takes the median of its neighborhood, and a lone NaN hole is filled.
+    fn test_median_filter_removes_spike() {
+        let (width, height) = (5, 5);
+        let mut data: Vec<f32> = (0..25).map(|i| 10.0 + (i % 3) as f32).collect();
+        data[12] = 9000.0;
+        data[0] = f32::NAN;
+        let filtered = median_filter(&data, width, height, 1);
+        // window of the spike: 10, 11, 12 / 12, 9000, 10 / 11, 12, 10 -> median 11
+        assert_eq!(filtered[12], 11.0);
+        assert!(!filtered[0].is_nan());
+        assert!(filtered.iter().all(|v| *v < 100.0));
+        assert!(median_filter(&vec![f32::NAN; 4], 2, 2, 1).iter().all(|v| v.is_nan()));
+    }
+
+    #[test]
+    /// NaN holes stay NaN and do not pull their neighbors toward NaN.
+    fn test_gaussian_smooth_nan_hole() {
+        let mut data = vec![5.0; 25];
+        data[12] = f32::NAN;
+        let smoothed = gaussian_smooth(&data, 5, 5, 1.0);
+        assert!(smoothed[12].is_nan());
+        assert!(smoothed.iter().enumerate().all(|(i, v)| i == 12 || (v - 5.0).abs() < 1e-5));
+    }
+}
@@ -0,0 +1,321 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use serde::Deserialize;
+
+use crate::strip_bom;
+
+/// A DEM stored as JSON: grid metadata plus the cell values, one array per row (top row first).
+/// `nodata` and `null` cells both become NaN.
+#[derive(Debug, Deserialize)]
+struct JsonGrid {
+    ncols: u32,
+    nrows: u32,
+    cellsize: f32,
+    xll: f64,
+    yll: f64,
+    nodata: Option<f32>,
+    data: Vec<Vec<Option<f32>>>,
+}
+
+/// Parses a gridded JSON DEM of the form `{ncols, nrows, cellsize, xll, yll, nodata, data: [[...]]}`.
+/// # Arguments
+/// * `content` - A string containing the JSON document.
+/// # Returns
+/// * The elevation data, width, height, cell size and the lower-left corner coordinates.
+pub fn read_grid_json(content: &str) -> Result<(Vec<f32>, u32, u32, f32, f64, f64), Box<dyn Error>> {
+    let grid: JsonGrid = serde_json::from_str(strip_bom(content))?;
+    if grid.data.len() != grid.nrows as usize {
+        return Err(format!("expected {} rows, found {}", grid.nrows, grid.data.len()).into());
+    }
+    let mut data = Vec::with_capacity((grid.ncols * grid.nrows) as usize);
+    for (y, row) in grid.data.iter().enumerate() {
+        if row.len() != grid.ncols as usize {
+            return Err(format!("row {} has {} values, expected {}", y, row.len(), grid.ncols).into());
+        }
+        data.extend(row.iter().map(|value| match value {
+            Some(v) if Some(*v) != grid.nodata => *v,
+            _ => f32::NAN,
+        }));
+    }
+    Ok((data, grid.ncols, grid.nrows, grid.cellsize, grid.xll, grid.yll))
+}
+
+/// Reads the outer ring of the first polygon of a GeoJSON document.
+/// # Arguments
+/// * `content` - A GeoJSON `FeatureCollection`, `Feature`, `Polygon` or `MultiPolygon`.
+/// # Returns
+/// * The `(x, y)` vertices of the ring, in the coordinates of the document.
+///
+/// Holes and any further polygons are ignored.
+pub fn read_geojson_ring(content: &str) -> Result<Vec<(f32, f32)>, Box<dyn Error>> {
+    let document: serde_json::Value = serde_json::from_str(strip_bom(content))?;
+    let mut geometry = &document;
+    loop {
+        geometry = match geometry["type"].as_str() {
+            Some("FeatureCollection") => geometry["features"].get(0).ok_or("the FeatureCollection has no feature")?,
+            Some("Feature") => &geometry["geometry"],
+            Some("Polygon") => &geometry["coordinates"][0],
+            Some("MultiPolygon") => &geometry["coordinates"][0][0],
+            Some(other) => return Err(format!("expected a Polygon, found a {}", other).into()),
+            None => break,
+        };
+    }
+    let vertices = geometry.as_array().ok_or("the polygon has no coordinates")?;
+    let ring = vertices
+        .iter()
+        .map(|vertex| match (vertex[0].as_f64(), vertex[1].as_f64()) {
+            (Some(x), Some(y)) => Ok((x as f32, y as f32)),
+            _ => Err(format!("invalid vertex {}", vertex)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if ring.len() < 3 {
+        return Err(format!("the ring has {} vertices, at least 3 are needed", ring.len()).into());
+    }
+    Ok(ring)
+}
+
+/// Header of an ARC/INFO binary float grid, read from the `.hdr` sidecar of a `.flt` file.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FltHeader {
+    pub ncols: u32,
+    pub nrows: u32,
+    /// X coordinate of the lower-left corner of the grid.
+    pub xllcorner: f64,
+    /// Y coordinate of the lower-left corner of the grid.
+    pub yllcorner: f64,
+    pub cellsize: f32,
+    pub nodata_value: Option<f32>,
+    /// `BYTEORDER MSBFIRST`; the default `LSBFIRST` is little-endian.
+    pub big_endian: bool,
+}
+
+/// Parses the `.hdr` sidecar of a `.flt` grid. Keys are case-insensitive.
+/// # Arguments
+/// * `content` - A string containing the content of the `.hdr` file.
+pub fn read_flt_header(content: &str) -> Result<FltHeader, Box<dyn Error>> {
+    let mut header = FltHeader { cellsize: 1.0, ..Default::default() };
+    for line in strip_bom(content).lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let [key, value] = parts.as_slice() else { continue };
+        match key.to_ascii_lowercase().as_str() {
+            "ncols" => header.ncols = value.parse()?,
+            "nrows" => header.nrows = value.parse()?,
+            "xllcorner" => header.xllcorner = value.parse()?,
+            "yllcorner" => header.yllcorner = value.parse()?,
+            "cellsize" => header.cellsize = value.parse()?,
+            "nodata_value" => header.nodata_value = Some(value.parse()?),
+            "byteorder" => header.big_endian = match value.to_ascii_uppercase().as_str() {
+                "MSBFIRST" => true,
+                "LSBFIRST" => false,
+                other => return Err(format!("unknown byte order {}", other).into()),
+            },
+            _ => {}
+        }
+    }
+    Ok(header)
+}
+
+/// Reads a `.flt` grid and the `.hdr` sidecar next to it.
+/// # Arguments
+/// * `flt_path` - Path to the `.flt` file.
+/// # Returns
+/// * The header and the elevation data, NoData cells as NaN.
+pub fn read_flt(flt_path: &str) -> Result<(FltHeader, Vec<f32>), Box<dyn Error>> {
+    let hdr_path = Path::new(flt_path).with_extension("hdr");
+    let hdr = fs::read_to_string(&hdr_path).map_err(|e| format!("{}: {}", hdr_path.display(), e))?;
+    let header = read_flt_header(&hdr)?;
+    let bytes = fs::read(flt_path)?;
+    let expected = header.ncols as usize * header.nrows as usize * 4;
+    if bytes.len() != expected {
+        return Err(format!("{} has {} bytes, {}x{} float32 cells need {}", flt_path, bytes.len(), header.ncols, header.nrows, expected).into());
+    }
+    let data = bytes
+        .chunks_exact(4)
+        .map(|chunk| {
+            let raw = [chunk[0], chunk[1], chunk[2], chunk[3]];
+            let value = if header.big_endian { f32::from_be_bytes(raw) } else { f32::from_le_bytes(raw) };
+            if Some(value) == header.nodata_value { f32::NAN } else { value }
+        })
+        .collect();
+    Ok((header, data))
+}
+
+/// Reads an ARC/INFO binary float grid (`.flt` with its `.hdr` sidecar).
+/// # Arguments
+/// * `flt_path` - Path to the `.flt` file.
+/// # Returns
+/// * The elevation data, width, height and cell size, like `asc_to_image`.
+pub fn flt_to_image(flt_path: &str) -> Result<(Vec<f32>, u32, u32, f32), Box<dyn Error>> {
+    let (header, data) = read_flt(flt_path)?;
+    Ok((data, header.ncols, header.nrows, header.cellsize))
+}
+
+/// Finds the spacing of sorted, deduplicated coordinates, requiring every gap to be a whole
+/// number of steps. Returns `None` for a single coordinate.
+fn grid_spacing(coordinates: &[f64], axis: &str) -> Result<Option<f64>, Box<dyn Error>> {
+    let step = coordinates.windows(2).map(|pair| pair[1] - pair[0]).fold(f64::INFINITY, f64::min);
+    if !step.is_finite() {
+        return Ok(None);
+    }
+    for pair in coordinates.windows(2) {
+        let steps = (pair[1] - pair[0]) / step;
+        if (steps - steps.round()).abs() > 1e-6 {
+            return Err(format!("irregular {} spacing: {} is not a multiple of {}", axis, pair[1] - pair[0], step).into());
+        }
+    }
+    Ok(Some(step))
+}
+
+/// Parses a three-column `x y z` point list sampled on a regular grid, as exported by many LiDAR tools.
+/// # Arguments
+/// * `content` - One point per line, with the columns separated by spaces, tabs, commas or semicolons.
+///   A non-numeric first line (column names) is skipped.
+/// # Returns
+/// * The elevation data, width, height, cell size and the lower-left corner coordinates.
+///
+/// The points are cell centers. The spacing is inferred from the unique X and Y coordinates and
+/// must be the same along both axes; cells without a point are NaN.
+pub fn read_xyz(content: &str) -> Result<(Vec<f32>, u32, u32, f32, f64, f64), Box<dyn Error>> {
+    let mut points = Vec::new();
+    for (number, line) in strip_bom(content).lines().enumerate() {
+        let fields: Vec<&str> = line.split(|c: char| c.is_whitespace() || c == ',' || c == ';').filter(|f| !f.is_empty()).collect();
+        if fields.is_empty() {
+            continue;
+        }
+        let point = match fields.as_slice() {
+            [x, y, z] => x.parse::<f64>().and_then(|x| Ok((x, y.parse::<f64>()?, z.parse::<f32>()?))),
+            _ => return Err(format!("line {}: expected 3 columns, found {}", number + 1, fields.len()).into()),
+        };
+        match point {
+            Ok(point) => points.push(point),
+            Err(_) if points.is_empty() && number == 0 => continue,
+            Err(e) => return Err(format!("line {}: {}", number + 1, e).into()),
+        }
+    }
+    if points.is_empty() {
+        return Err("no points".into());
+    }
+
+    let unique = |mut values: Vec<f64>| {
+        values.sort_by(|a, b| a.total_cmp(b));
+        values.dedup();
+        values
+    };
+    let xs = unique(points.iter().map(|p| p.0).collect());
+    let ys = unique(points.iter().map(|p| p.1).collect());
+    let cellsize = match (grid_spacing(&xs, "X")?, grid_spacing(&ys, "Y")?) {
+        (Some(dx), Some(dy)) if ((dx - dy) / dx).abs() > 1e-6 => {
+            return Err(format!("cells are not square: X spacing {} vs Y spacing {}", dx, dy).into())
+        }
+        (Some(step), _) | (None, Some(step)) => step,
+        (None, None) => return Err("a single point has no grid spacing".into()),
+    };
+
+    let (min_x, max_x) = (xs[0], xs[xs.len() - 1]);
+    let (min_y, max_y) = (ys[0], ys[ys.len() - 1]);
+    let width = ((max_x - min_x) / cellsize).round() as u32 + 1;
+    let height = ((max_y - min_y) / cellsize).round() as u32 + 1;
+    let mut data = vec![f32::NAN; (width * height) as usize];
+    for (x, y, z) in points {
+        let column = ((x - min_x) / cellsize).round() as u32;
+        // rows run from the top (largest Y) down
+        let row = ((max_y - y) / cellsize).round() as u32;
+        data[(row * width + column) as usize] = z;
+    }
+    let half = cellsize / 2.0;
+    Ok((data, width, height, cellsize as f32, min_x - half, min_y - half))
+}
+
+/// Reads an XYZ point list into a grid (see `read_xyz`).
+/// # Arguments
+/// * `content` - A string containing one `x y z` point per line.
+/// # Returns
+/// * The elevation data, width, height and cell size, like `asc_to_image`.
+pub fn xyz_to_image(content: String) -> Result<(Vec<f32>, u32, u32, f32), Box<dyn Error>> {
+    let (data, width, height, cellsize, _, _) = read_xyz(&content)?;
+    Ok((data, width, height, cellsize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{asc_to_image, parse_header};
+
+    #[test]
+    /// It checks that a JSON grid parses to the same data and georeferencing as the equivalent ASC file.
+    fn test_read_grid_json_matches_asc() {
+        let json = r#"{"ncols": 3, "nrows": 2, "cellsize": 10, "xll": 100.5, "yll": 200,
+            "nodata": -9999, "data": [[1, 2, -9999], [4, null, 6.5]]}"#;
+        let asc = "ncols 3\nnrows 2\nxllcorner 100.5\nyllcorner 200\ncellsize 10\nnodata_value -9999\n1 2 -9999\n4 -9999 6.5\n";
+
+        let (data, width, height, cellsize, xll, yll) = read_grid_json(json).unwrap();
+        let (asc_data, asc_width, asc_height, asc_cellsize) = asc_to_image(asc.to_string()).unwrap();
+        let header = parse_header(asc).unwrap();
+
+        assert_eq!((width, height, cellsize), (asc_width, asc_height, asc_cellsize));
+        assert_eq!((xll, yll), (header.xllcorner, header.yllcorner));
+        assert_eq!(data.len(), asc_data.len());
+        for (a, b) in data.iter().zip(asc_data.iter()) {
+            assert!(a == b || (a.is_nan() && b.is_nan()));
+        }
+    }
+
+    #[test]
+    /// It checks that a row with the wrong number of values is rejected.
+    fn test_read_grid_json_ragged_row() {
+        let json = r#"{"ncols": 2, "nrows": 2, "cellsize": 1, "xll": 0, "yll": 0, "data": [[1, 2], [3]]}"#;
+        assert!(read_grid_json(json).is_err());
+    }
+
+    #[test]
+    /// It checks that a 3x2 XYZ listing in any order lands in the right cells, with gaps as NaN.
+    fn test_xyz_to_image() {
+        let xyz = "x,y,z\n\
+            1000,2010,4\n1010,2010,5\n1020,2010,6\n\
+            1000,2020,1\n1020,2020,3\n";
+        let (data, width, height, cellsize) = xyz_to_image(xyz.to_string()).unwrap();
+        assert_eq!((width, height, cellsize), (3, 2, 10.0));
+        assert_eq!(data[..1], [1.0]);
+        assert!(data[1].is_nan());
+        assert_eq!(data[2..], [3.0, 4.0, 5.0, 6.0]);
+
+        let (.., xll, yll) = read_xyz(xyz).unwrap();
+        assert_eq!((xll, yll), (995.0, 2005.0));
+
+        assert!(xyz_to_image("0 0 1\n10 0 2\n25 0 3\n".to_string()).is_err());
+    }
+
+    #[test]
+    /// It checks that a big-endian `.flt`/`.hdr` pair reads back with NoData mapped to NaN.
+    fn test_flt_round_trip() {
+        let dir = std::env::temp_dir().join("dem_test_flt");
+        fs::create_dir_all(&dir).unwrap();
+        let flt_path = dir.join("tiny.flt");
+        fs::write(dir.join("tiny.hdr"), "NCOLS 3\nNROWS 2\nXLLCORNER 10\nYLLCORNER 20\nCELLSIZE 5\nNODATA_VALUE -9999\nBYTEORDER MSBFIRST\n").unwrap();
+        let values = [1.0f32, 2.5, -9999.0, 4.0, 5.0, 6.0];
+        let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_be_bytes()).collect();
+        fs::write(&flt_path, bytes).unwrap();
+
+        let (data, width, height, cellsize) = flt_to_image(flt_path.to_str().unwrap()).unwrap();
+        assert_eq!((width, height, cellsize), (3, 2, 5.0));
+        assert_eq!(data[..2], [1.0, 2.5]);
+        assert!(data[2].is_nan());
+        assert_eq!(data[3..], [4.0, 5.0, 6.0]);
+
+        fs::write(&flt_path, [0u8; 8]).unwrap();
+        assert!(flt_to_image(flt_path.to_str().unwrap()).is_err());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    /// It checks that the outer ring of a polygon feature is read, whatever the wrapping.
+    fn test_read_geojson_ring() {
+        let feature = r#"{"type": "FeatureCollection", "features": [{"type": "Feature", "properties": {},
+            "geometry": {"type": "Polygon", "coordinates": [[[0, 0], [10, 0], [10, 5.5], [0, 0]], [[1, 1], [2, 1], [2, 2]]]}}]}"#;
+        assert_eq!(read_geojson_ring(feature).unwrap(), vec![(0.0, 0.0), (10.0, 0.0), (10.0, 5.5), (0.0, 0.0)]);
+        let multi = r#"{"type": "MultiPolygon", "coordinates": [[[[3, 3], [4, 3], [4, 4]]]]}"#;
+        assert_eq!(read_geojson_ring(multi).unwrap().len(), 3);
+        assert!(read_geojson_ring(r#"{"type": "Point", "coordinates": [1, 2]}"#).is_err());
+    }
+}
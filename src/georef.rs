@@ -0,0 +1,122 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Coordinate reference systems with a built-in WKT definition for `.prj` sidecars.
+const KNOWN_CRS: &[(u32, &str)] = &[
+    (2154, r#"PROJCS["RGF93 / Lambert-93",GEOGCS["RGF93",DATUM["Reseau_Geodesique_Francais_1993",SPHEROID["GRS 1980",6378137,298.257222101,AUTHORITY["EPSG","7019"]],TOWGS84[0,0,0,0,0,0,0],AUTHORITY["EPSG","6171"]],PRIMEM["Greenwich",0,AUTHORITY["EPSG","8901"]],UNIT["degree",0.0174532925199433,AUTHORITY["EPSG","9122"]],AUTHORITY["EPSG","4171"]],PROJECTION["Lambert_Conformal_Conic_2SP"],PARAMETER["standard_parallel_1",49],PARAMETER["standard_parallel_2",44],PARAMETER["latitude_of_origin",46.5],PARAMETER["central_meridian",3],PARAMETER["false_easting",700000],PARAMETER["false_northing",6600000],UNIT["metre",1,AUTHORITY["EPSG","9001"]],AXIS["X",EAST],AXIS["Y",NORTH],AUTHORITY["EPSG","2154"]]"#),
+    (3857, r#"PROJCS["WGS 84 / Pseudo-Mercator",GEOGCS["WGS 84",DATUM["WGS_1984",SPHEROID["WGS 84",6378137,298.257223563,AUTHORITY["EPSG","7030"]],AUTHORITY["EPSG","6326"]],PRIMEM["Greenwich",0,AUTHORITY["EPSG","8901"]],UNIT["degree",0.0174532925199433,AUTHORITY["EPSG","9122"]],AUTHORITY["EPSG","4326"]],PROJECTION["Mercator_1SP"],PARAMETER["central_meridian",0],PARAMETER["scale_factor",1],PARAMETER["false_easting",0],PARAMETER["false_northing",0],UNIT["metre",1,AUTHORITY["EPSG","9001"]],AXIS["Easting",EAST],AXIS["Northing",NORTH],EXTENSION["PROJ4","+proj=merc +a=6378137 +b=6378137 +lat_ts=0 +lon_0=0 +x_0=0 +y_0=0 +k=1 +units=m +nadgrids=@null +wktext +no_defs"],AUTHORITY["EPSG","3857"]]"#),
+    (4326, r#"GEOGCS["WGS 84",DATUM["WGS_1984",SPHEROID["WGS 84",6378137,298.257223563,AUTHORITY["EPSG","7030"]],AUTHORITY["EPSG","6326"]],PRIMEM["Greenwich",0,AUTHORITY["EPSG","8901"]],UNIT["degree",0.0174532925199433,AUTHORITY["EPSG","9122"]],AUTHORITY["EPSG","4326"]]"#),
+];
+
+/// Looks up the WKT definition of a coordinate reference system.
+/// # Arguments
+/// * `crs` - An EPSG code, written either as `2154` or `EPSG:2154`.
+/// # Returns
+/// * The WKT string, or `None` if the code is not one of the built-in systems.
+pub fn crs_wkt(crs: &str) -> Option<&'static str> {
+    let code = crs.trim();
+    let code = code.strip_prefix("EPSG:").or_else(|| code.strip_prefix("epsg:")).unwrap_or(code);
+    let code: u32 = code.parse().ok()?;
+    KNOWN_CRS.iter().find(|(known, _)| *known == code).map(|(_, wkt)| *wkt)
+}
+
+/// Lists the EPSG codes that `crs_wkt` knows about.
+pub fn known_crs_codes() -> Vec<u32> {
+    KNOWN_CRS.iter().map(|(code, _)| *code).collect()
+}
+
+/// Returns the world file name belonging to an image, following the ESRI convention
+/// (first and last letter of the extension followed by `w`, e.g. `.png` -> `.pgw`).
+/// Images without a usable extension get a generic `.wld` file.
+pub fn world_file_name(image_name: &str) -> String {
+    let path = Path::new(image_name);
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let world_extension = match (extension.chars().next(), extension.chars().last()) {
+        (Some(first), Some(last)) if extension.len() >= 2 => format!("{}{}w", first, last),
+        _ => "wld".to_string(),
+    };
+    path.with_extension(world_extension).to_string_lossy().into_owned()
+}
+
+/// Returns the `.prj` sidecar name belonging to an image.
+pub fn prj_file_name(image_name: &str) -> String {
+    Path::new(image_name).with_extension("prj").to_string_lossy().into_owned()
+}
+
+/// Builds the six lines of an ESRI world file for a north-up raster.
+/// # Arguments
+/// * `cellsize` - The `(dx, dy)` ground size of one pixel.
+/// * `xllcorner` - X coordinate of the lower-left corner of the grid.
+/// * `yllcorner` - Y coordinate of the lower-left corner of the grid.
+/// * `height` - The number of rows of the grid.
+/// # Returns
+/// * The pixel width, the two rotation terms, the negative pixel height and the
+///   coordinates of the center of the top-left pixel, one value per line.
+pub fn world_file_text(cellsize: (f32, f32), xllcorner: f64, yllcorner: f64, height: u32) -> String {
+    let (dx, dy) = (cellsize.0 as f64, cellsize.1 as f64);
+    let x_center = xllcorner + dx / 2.0;
+    let y_center = yllcorner + height as f64 * dy - dy / 2.0;
+    format!("{}\n0\n0\n{}\n{}\n{}\n", dx, -dy, x_center, y_center)
+}
+
+/// Writes the ESRI world file belonging to an image saved on disk.
+/// # Arguments
+/// * `png_path` - The path of the image, the world file is written next to it (`.png` -> `.pgw`).
+/// * `cellsize` - The `(dx, dy)` ground size of one pixel.
+/// * `xllcorner` - X coordinate of the lower-left corner of the grid.
+/// * `yllcorner` - Y coordinate of the lower-left corner of the grid.
+/// * `height` - The number of rows of the grid.
+///
+/// The pipeline writes the same file through its `OutputSink` when `--world-file` is given;
+/// this function is meant for images saved directly by library users.
+pub fn write_world_file(png_path: &str, cellsize: (f32, f32), xllcorner: f32, yllcorner: f32, height: u32) -> Result<(), Box<dyn Error>> {
+    let text = world_file_text(cellsize, xllcorner as f64, yllcorner as f64, height);
+    fs::write(world_file_name(png_path), text)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// It checks the ESRI naming rule for world files and the prj sidecar name.
+    fn test_sidecar_names() {
+        assert_eq!(world_file_name("hillshade_rgb_x.png"), "hillshade_rgb_x.pgw");
+        assert_eq!(world_file_name("output.tiff"), "output.tfw");
+        assert_eq!(world_file_name("output"), "output.wld");
+        assert_eq!(prj_file_name("output_x.png"), "output_x.prj");
+    }
+
+    #[test]
+    /// It checks that EPSG codes resolve with or without the prefix.
+    fn test_crs_wkt_lookup() {
+        assert!(crs_wkt("EPSG:2154").unwrap().contains("Lambert-93"));
+        assert_eq!(crs_wkt("2154"), crs_wkt("epsg:2154"));
+        assert!(crs_wkt("99999").is_none());
+        assert!(crs_wkt("abc").is_none());
+    }
+
+    #[test]
+    /// It checks the affine values written next to a PNG: pixel size, rotations and top-left pixel center.
+    fn test_write_world_file() {
+        let png_path = std::env::temp_dir().join("dem_test_world_file.png");
+        let png_path = png_path.to_str().unwrap();
+        write_world_file(png_path, (25.0, 25.0), 925000.0, 6224000.0, 4).unwrap();
+
+        let pgw_path = world_file_name(png_path);
+        let contents = fs::read_to_string(&pgw_path).unwrap();
+        let values: Vec<f64> = contents.lines().map(|l| l.parse().unwrap()).collect();
+        assert_eq!(values, vec![25.0, 0.0, 0.0, -25.0, 925012.5, 6224087.5]);
+        fs::remove_file(pgw_path).unwrap();
+    }
+
+    #[test]
+    /// It checks that rectangular cells use dy for the pixel height and the top-left pixel center.
+    fn test_world_file_text_rectangular_cells() {
+        let text = world_file_text((10.0, 20.0), 1000.0, 5000.0, 3);
+        let values: Vec<f64> = text.lines().map(|l| l.parse().unwrap()).collect();
+        assert_eq!(values, vec![10.0, 0.0, 0.0, -20.0, 1005.0, 5050.0]);
+    }
+}
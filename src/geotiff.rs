@@ -0,0 +1,80 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use tiff::encoder::{colortype, TiffEncoder};
+use tiff::tags::Tag;
+
+/// GeoKeyDirectory with a single key: GTRasterTypeGeoKey = RasterPixelIsArea.
+const GEO_KEY_DIRECTORY: [u16; 8] = [1, 1, 0, 1, 1025, 0, 1, 1];
+
+/// Writes the elevation grid as a single-band float32 GeoTIFF.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `cellsize` - The `(dx, dy)` size of each cell in the elevation data.
+/// * `xllcorner` - The x coordinate of the lower-left corner of the grid.
+/// * `yllcorner` - The y coordinate of the lower-left corner of the grid.
+/// * `path` - The `.tif` file to write.
+///
+/// The ModelPixelScale and ModelTiepoint tags anchor the top-left corner of the raster so GIS
+/// tools such as QGIS place it correctly. NaN cells are kept and flagged with a GDAL_NODATA tag.
+pub fn save_geotiff(data: &Vec<f32>, width: u32, height: u32, cellsize: (f32, f32), xllcorner: f32, yllcorner: f32, path: &str) -> Result<(), Box<dyn Error>> {
+    let (dx, dy) = (cellsize.0 as f64, cellsize.1 as f64);
+    let top = yllcorner as f64 + height as f64 * dy;
+    let mut encoder = TiffEncoder::new(BufWriter::new(File::create(path)?))?;
+    let mut image = encoder.new_image::<colortype::Gray32Float>(width, height)?;
+    image.encoder().write_tag(Tag::ModelPixelScaleTag, &[dx, dy, 0.0][..])?;
+    image.encoder().write_tag(Tag::ModelTiepointTag, &[0.0, 0.0, 0.0, xllcorner as f64, top, 0.0][..])?;
+    image.encoder().write_tag(Tag::GeoKeyDirectoryTag, &GEO_KEY_DIRECTORY[..])?;
+    image.encoder().write_tag(Tag::GdalNodata, "nan")?;
+    image.write_data(data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiff::decoder::{Decoder, DecodingResult};
+
+    #[test]
+    /// The georeferencing tags and the NaN cells survive a round trip through the file.
+    fn test_save_geotiff_tags() {
+        let path = std::env::temp_dir().join("dem_test_save_geotiff.tif");
+        let data = vec![1.0, 2.0, f32::NAN, 4.0, 5.0, 6.0];
+        save_geotiff(&data, 3, 2, (25.0, 25.0), 925000.0, 6224000.0, path.to_str().unwrap()).unwrap();
+
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(decoder.dimensions().unwrap(), (3, 2));
+        assert_eq!(decoder.get_tag_f64_vec(Tag::ModelPixelScaleTag).unwrap(), vec![25.0, 25.0, 0.0]);
+        assert_eq!(
+            decoder.get_tag_f64_vec(Tag::ModelTiepointTag).unwrap(),
+            vec![0.0, 0.0, 0.0, 925000.0, 6224050.0, 0.0]
+        );
+        assert_eq!(decoder.get_tag_ascii_string(Tag::GdalNodata).unwrap(), "nan");
+        match decoder.read_image().unwrap() {
+            DecodingResult::F32(values) => {
+                assert!(values[2].is_nan());
+                assert_eq!(values[5], 6.0);
+            }
+            _ => panic!("expected float32 samples"),
+        }
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    /// Rectangular cells keep their own x and y pixel sizes, and dy places the top edge.
+    fn test_save_geotiff_rectangular_cells() {
+        let path = std::env::temp_dir().join("dem_test_save_geotiff_dxdy.tif");
+        let data = vec![1.0; 6];
+        save_geotiff(&data, 3, 2, (10.0, 20.0), 1000.0, 5000.0, path.to_str().unwrap()).unwrap();
+
+        let mut decoder = Decoder::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(decoder.get_tag_f64_vec(Tag::ModelPixelScaleTag).unwrap(), vec![10.0, 20.0, 0.0]);
+        assert_eq!(
+            decoder.get_tag_f64_vec(Tag::ModelTiepointTag).unwrap(),
+            vec![0.0, 0.0, 0.0, 1000.0, 5040.0, 0.0]
+        );
+        std::fs::remove_file(path).ok();
+    }
+}
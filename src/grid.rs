@@ -0,0 +1,518 @@
+use std::error::Error;
+
+use crate::formats::{read_flt, read_grid_json, read_xyz};
+use crate::{asc_to_image, asc_to_image_with, check_dimensions, parse_header, parse_header_with, read_asc_streaming, AscHeader, ParseOptions};
+
+/// An elevation raster together with its georeferencing.
+/// Rows are stored top to bottom, as in the ASC file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grid {
+    pub data: Vec<f32>,
+    pub width: u32,
+    pub height: u32,
+    pub cellsize: f32,
+    /// Height of the cells, equal to `cellsize` unless the header declares `dx` and `dy`.
+    pub cellsize_y: f32,
+    /// X coordinate of the lower-left corner of the grid.
+    pub xllcorner: f64,
+    /// Y coordinate of the lower-left corner of the grid.
+    pub yllcorner: f64,
+}
+
+impl Grid {
+    /// Parses an ASC file content into a georeferenced grid.
+    /// # Arguments
+    /// * `content` - A string containing the content of the ASC file.
+    pub fn from_asc(content: String) -> Result<Grid, Box<dyn Error>> {
+        let header = parse_header(&content)?;
+        let (data, width, height, cellsize) = asc_to_image(content)?;
+        let cellsize_y = header.cell_dims().1;
+        Ok(Grid { data, width, height, cellsize, cellsize_y, xllcorner: header.xllcorner, yllcorner: header.yllcorner })
+    }
+
+    /// Same as `from_asc`, reading the numbers according to `options`.
+    /// # Returns
+    /// * The grid and the number of unparseable tokens that were skipped.
+    pub fn from_asc_with(content: String, options: &ParseOptions) -> Result<(Grid, usize), Box<dyn Error>> {
+        let header = parse_header_with(&content, options)?;
+        let (data, width, height, cellsize, skipped) = asc_to_image_with(content, options)?;
+        let cellsize_y = header.cell_dims().1;
+        let grid = Grid { data, width, height, cellsize, cellsize_y, xllcorner: header.xllcorner, yllcorner: header.yllcorner };
+        Ok((grid, skipped))
+    }
+
+    /// Reads an ASC file into a georeferenced grid without loading the whole file into memory
+    /// (see `read_asc_streaming`).
+    /// # Arguments
+    /// * `path` - Path to the ASC file.
+    /// * `options` - How numbers are written in the file.
+    /// # Returns
+    /// * The grid, its header and the number of unparseable tokens that were skipped.
+    pub fn from_asc_file(path: &str, options: &ParseOptions) -> Result<(Grid, AscHeader, usize), Box<dyn Error>> {
+        let (header, data, skipped) = read_asc_streaming(path, options)?;
+        // a truncated or corrupt file is reported here rather than as an out-of-bounds panic later
+        check_dimensions(data.len(), header.ncols, header.nrows)?;
+        let (cellsize, cellsize_y) = header.cell_dims();
+        let grid = Grid {
+            data,
+            width: header.ncols,
+            height: header.nrows,
+            cellsize,
+            cellsize_y,
+            xllcorner: header.xllcorner,
+            yllcorner: header.yllcorner,
+        };
+        Ok((grid, header, skipped))
+    }
+
+    /// Parses a gridded JSON DEM (see `formats::read_grid_json`) into a georeferenced grid.
+    /// # Arguments
+    /// * `content` - A string containing the JSON document.
+    pub fn from_json(content: &str) -> Result<Grid, Box<dyn Error>> {
+        let (data, width, height, cellsize, xllcorner, yllcorner) = read_grid_json(content)?;
+        Ok(Grid { data, width, height, cellsize, cellsize_y: cellsize, xllcorner, yllcorner })
+    }
+
+    /// Reads an ARC/INFO binary float grid (see `formats::read_flt`) into a georeferenced grid.
+    /// # Arguments
+    /// * `flt_path` - Path to the `.flt` file, with its `.hdr` sidecar next to it.
+    pub fn from_flt(flt_path: &str) -> Result<Grid, Box<dyn Error>> {
+        let (header, data) = read_flt(flt_path)?;
+        Ok(Grid {
+            data,
+            width: header.ncols,
+            height: header.nrows,
+            cellsize: header.cellsize,
+            cellsize_y: header.cellsize,
+            xllcorner: header.xllcorner,
+            yllcorner: header.yllcorner,
+        })
+    }
+
+    /// Parses an XYZ point list (see `formats::read_xyz`) into a georeferenced grid.
+    /// # Arguments
+    /// * `content` - A string containing one `x y z` point per line.
+    pub fn from_xyz(content: &str) -> Result<Grid, Box<dyn Error>> {
+        let (data, width, height, cellsize, xllcorner, yllcorner) = read_xyz(content)?;
+        Ok(Grid { data, width, height, cellsize, cellsize_y: cellsize, xllcorner, yllcorner })
+    }
+
+    /// Keeps every `factor`-th cell in both directions, producing a coarse copy of the grid.
+    /// # Arguments
+    /// * `factor` - The sampling step, `1` returns an identical grid.
+    /// # Returns
+    /// * A grid of `ceil(width / factor)` x `ceil(height / factor)` cells with a cellsize `factor` times larger.
+    pub fn subsample(&self, factor: u32) -> Grid {
+        let factor = factor.max(1);
+        let width = self.width.div_ceil(factor);
+        let height = self.height.div_ceil(factor);
+        let mut data = Vec::with_capacity((width * height) as usize);
+        for y in (0..self.height).step_by(factor as usize) {
+            for x in (0..self.width).step_by(factor as usize) {
+                data.push(self.data[(y * self.width + x) as usize]);
+            }
+        }
+        Grid { data, width, height, cellsize: self.cellsize * factor as f32, cellsize_y: self.cellsize_y * factor as f32, ..*self }
+    }
+
+    /// Finds the cell containing a point given in map coordinates.
+    /// # Arguments
+    /// * `x` - The x (easting or longitude) of the point.
+    /// * `y` - The y (northing or latitude) of the point.
+    /// # Returns
+    /// * The column and row (from the top) of the cell, or `None` when the point is outside the grid.
+    ///
+    /// A cell covers its left and bottom edges but not its right and top ones, so a point on the
+    /// lower-left corner is inside the grid and a point on the right or top edge of the extent is not.
+    pub fn cell_at(&self, x: f64, y: f64) -> Option<(u32, u32)> {
+        let column = ((x - self.xllcorner) / self.cellsize as f64).floor();
+        let row_from_bottom = ((y - self.yllcorner) / self.cellsize_y as f64).floor();
+        let inside = (0.0..self.width as f64).contains(&column) && (0.0..self.height as f64).contains(&row_from_bottom);
+        inside.then(|| (column as u32, self.height - 1 - row_from_bottom as u32))
+    }
+}
+
+/// Reverses the row order of a grid, for exports that store rows bottom to top.
+/// # Arguments
+/// * `grid` - The grid to flip in place; its size and corner coordinates are unchanged.
+///
+/// The ASC header carries no orientation, so bottom-up grids can't be detected and must be
+/// flipped explicitly (`--flip-y`).
+pub fn flip_vertical(grid: &mut Grid) {
+    let width = grid.width as usize;
+    if width == 0 {
+        return;
+    }
+    let rows: Vec<Vec<f32>> = grid.data.chunks(width).rev().map(|row| row.to_vec()).collect();
+    grid.data = rows.concat();
+}
+
+/// Extracts a rectangular window of a grid, keeping its georeferencing consistent.
+/// # Arguments
+/// * `grid` - The source grid.
+/// * `x0` - First column of the window.
+/// * `y0` - First row of the window (rows count from the top).
+/// * `width` - Number of columns of the window.
+/// * `height` - Number of rows of the window.
+/// # Returns
+/// * The sub-grid, whose lower-left corner is shifted by the window offset times the cellsize.
+pub fn sub_grid(grid: &Grid, x0: u32, y0: u32, width: u32, height: u32) -> Grid {
+    let mut data = Vec::with_capacity((width * height) as usize);
+    for y in y0..y0 + height {
+        let start = (y * grid.width + x0) as usize;
+        data.extend_from_slice(&grid.data[start..start + width as usize]);
+    }
+    // The lower-left corner moves right by the skipped columns and up by the rows cut at the bottom
+    let rows_below = grid.height - (y0 + height);
+    Grid {
+        data,
+        width,
+        height,
+        xllcorner: grid.xllcorner + x0 as f64 * grid.cellsize as f64,
+        yllcorner: grid.yllcorner + rows_below as f64 * grid.cellsize_y as f64,
+        ..*grid
+    }
+}
+
+/// Crops a grid to the bounding box of its finite (non-NaN) cells.
+/// # Arguments
+/// * `grid` - The source grid.
+/// # Returns
+/// * The cropped grid with corrected corner coordinates, or an unchanged copy when no cell is finite.
+pub fn autocrop(grid: &Grid) -> Grid {
+    let (mut x_min, mut y_min, mut x_max, mut y_max) = (u32::MAX, u32::MAX, 0, 0);
+    for (i, value) in grid.data.iter().enumerate() {
+        if value.is_nan() {
+            continue;
+        }
+        let x = (i % grid.width as usize) as u32;
+        let y = (i / grid.width as usize) as u32;
+        x_min = x_min.min(x);
+        x_max = x_max.max(x);
+        y_min = y_min.min(y);
+        y_max = y_max.max(y);
+    }
+    if x_min == u32::MAX {
+        return grid.clone();
+    }
+    sub_grid(grid, x_min, y_min, x_max - x_min + 1, y_max - y_min + 1)
+}
+
+/// Crops a grid to a bounding box given in map coordinates.
+/// # Arguments
+/// * `grid` - The source grid.
+/// * `xmin` - West edge of the box.
+/// * `ymin` - South edge of the box.
+/// * `xmax` - East edge of the box.
+/// * `ymax` - North edge of the box.
+/// # Returns
+/// * The cells touched by the box, with corrected corner coordinates. Bounds outside the grid are
+///   clamped to its extent, so a box that misses the grid entirely yields an empty grid.
+pub fn crop(grid: &Grid, xmin: f32, ymin: f32, xmax: f32, ymax: f32) -> Grid {
+    let (cellsize, cellsize_y) = (grid.cellsize as f64, grid.cellsize_y as f64);
+    let top = grid.yllcorner + grid.height as f64 * cellsize_y;
+    let column = |x: f32, round: fn(f64) -> f64| round((x as f64 - grid.xllcorner) / cellsize).clamp(0.0, grid.width as f64) as u32;
+    let row = |y: f32, round: fn(f64) -> f64| round((top - y as f64) / cellsize_y).clamp(0.0, grid.height as f64) as u32;
+    let (x0, x1) = (column(xmin, f64::floor), column(xmax, f64::ceil));
+    let (y0, y1) = (row(ymax, f64::floor), row(ymin, f64::ceil));
+    sub_grid(grid, x0, y0, x1.saturating_sub(x0), y1.saturating_sub(y0))
+}
+
+/// Sets the cells whose center lies outside a polygon to NaN, e.g. to clip a DEM to a study area.
+/// # Arguments
+/// * `grid` - The grid to mask in place.
+/// * `polygon` - The `(x, y)` vertices of the ring in map coordinates. Closing the ring by repeating
+///   the first vertex is optional.
+///
+/// Cell centers are placed from the lower-left corner and tested with the even-odd rule, so a
+/// self-intersecting ring keeps the cells covered an odd number of times.
+pub fn mask_by_polygon(grid: &mut Grid, polygon: &[(f32, f32)]) {
+    let (cellsize, cellsize_y) = (grid.cellsize as f64, grid.cellsize_y as f64);
+    let top = grid.yllcorner + grid.height as f64 * cellsize_y;
+    let width = grid.width as usize;
+    for (i, value) in grid.data.iter_mut().enumerate() {
+        let x = grid.xllcorner + ((i % width) as f64 + 0.5) * cellsize;
+        let y = top - ((i / width) as f64 + 0.5) * cellsize_y;
+        if !point_in_polygon(x, y, polygon) {
+            *value = f32::NAN;
+        }
+    }
+}
+
+/// Sets the cells whose elevation lies outside a window to NaN, e.g. to isolate a floodplain.
+/// # Arguments
+/// * `grid` - The grid to mask in place.
+/// * `low` - The lowest elevation kept.
+/// * `high` - The highest elevation kept (inclusive).
+pub fn mask_elevation_band(grid: &mut Grid, low: f32, high: f32) {
+    for value in grid.data.iter_mut() {
+        if !(low..=high).contains(value) {
+            *value = f32::NAN;
+        }
+    }
+}
+
+/// Even-odd ray casting test of a point against a polygon ring.
+fn point_in_polygon(x: f64, y: f64, polygon: &[(f32, f32)]) -> bool {
+    let mut inside = false;
+    for (i, &(xi, yi)) in polygon.iter().enumerate() {
+        let (xj, yj) = polygon[(i + polygon.len() - 1) % polygon.len()];
+        let (xi, yi, xj, yj) = (xi as f64, yi as f64, xj as f64, yj as f64);
+        if (yi > y) != (yj > y) && x < xj + (y - yj) * (xi - xj) / (yi - yj) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Mosaics adjacent tiles into a single grid using their corner coordinates.
+/// # Arguments
+/// * `tiles` - The tiles to merge, all with the same cellsize.
+/// # Returns
+/// * A grid covering the bounding box of every tile, NaN where no tile has data.
+///
+/// Tiles must be aligned on the same cell raster. Where tiles overlap, valid cells of later
+/// tiles overwrite earlier ones.
+pub fn merge_tiles(tiles: &[Grid]) -> Result<Grid, Box<dyn Error>> {
+    let first = tiles.first().ok_or("no tiles to merge")?;
+    let (cellsize, cellsize_y) = (first.cellsize, first.cellsize_y);
+    if let Some(tile) = tiles.iter().find(|tile| (tile.cellsize, tile.cellsize_y) != (cellsize, cellsize_y)) {
+        return Err(format!("cellsize mismatch: {} vs {}", tile.cellsize, cellsize).into());
+    }
+    let (cs, cs_y) = (cellsize as f64, cellsize_y as f64);
+    let right = |tile: &Grid| tile.xllcorner + tile.width as f64 * cs;
+    let top = |tile: &Grid| tile.yllcorner + tile.height as f64 * cs_y;
+    let min_x = tiles.iter().map(|t| t.xllcorner).fold(f64::INFINITY, f64::min);
+    let min_y = tiles.iter().map(|t| t.yllcorner).fold(f64::INFINITY, f64::min);
+    let max_x = tiles.iter().map(right).fold(f64::NEG_INFINITY, f64::max);
+    let max_y = tiles.iter().map(top).fold(f64::NEG_INFINITY, f64::max);
+
+    let width = ((max_x - min_x) / cs).round() as u32;
+    let height = ((max_y - min_y) / cs_y).round() as u32;
+    let mut data = vec![f32::NAN; (width * height) as usize];
+    for tile in tiles {
+        let col0 = ((tile.xllcorner - min_x) / cs).round() as u32;
+        let row0 = ((max_y - top(tile)) / cs_y).round() as u32;
+        for y in 0..tile.height {
+            for x in 0..tile.width {
+                let value = tile.data[(y * tile.width + x) as usize];
+                if !value.is_nan() {
+                    data[((row0 + y) * width + col0 + x) as usize] = value;
+                }
+            }
+        }
+    }
+    Ok(Grid { data, width, height, cellsize, cellsize_y, xllcorner: min_x, yllcorner: min_y })
+}
+
+/// Subtracts one grid from another, e.g. to measure erosion or deposition between two surveys.
+/// # Arguments
+/// * `a` - The minuend, typically the later survey.
+/// * `b` - The subtrahend, covering the same cells.
+/// # Returns
+/// * A grid with `a`'s georeferencing holding `a - b`, NaN where either input is NaN.
+/// * An error if the dimensions or cellsizes differ.
+pub fn diff_dems(a: &Grid, b: &Grid) -> Result<Grid, Box<dyn Error>> {
+    if (a.width, a.height) != (b.width, b.height) {
+        return Err(format!("dimension mismatch: {}x{} vs {}x{}", a.width, a.height, b.width, b.height).into());
+    }
+    if (a.cellsize, a.cellsize_y) != (b.cellsize, b.cellsize_y) {
+        return Err(format!("cellsize mismatch: {} vs {}", a.cellsize, b.cellsize).into());
+    }
+    // NaN on either side propagates through the subtraction
+    let data = a.data.iter().zip(&b.data).map(|(x, y)| x - y).collect();
+    Ok(Grid { data, ..*a })
+}
+
+/// Blanks the cells a co-registered validity mask flags as invalid.
+/// # Arguments
+/// * `grid` - The elevation grid to mask in place.
+/// * `mask` - A grid with the same dimensions, e.g. read from a separate mask ASC file.
+/// * `valid_value` - The mask value of the cells to keep.
+/// # Returns
+/// * An error when the dimensions differ, leaving `grid` untouched.
+///
+/// Every cell whose mask value differs from `valid_value` becomes NaN, including cells where the mask is NoData.
+pub fn apply_mask(grid: &mut Grid, mask: &Grid, valid_value: f32) -> Result<(), Box<dyn Error>> {
+    if (grid.width, grid.height) != (mask.width, mask.height) {
+        return Err(format!("dimension mismatch: {}x{} vs {}x{}", grid.width, grid.height, mask.width, mask.height).into());
+    }
+    for (value, &flag) in grid.data.iter_mut().zip(&mask.data) {
+        if flag != valid_value {
+            *value = f32::NAN;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// It checks that subsampling keeps every n-th cell and scales the cellsize.
+    fn test_subsample() {
+        let grid = Grid { data: (0..15).map(|v| v as f32).collect(), width: 5, height: 3, cellsize: 2.0, cellsize_y: 2.0, xllcorner: 1.0, yllcorner: 2.0 };
+        let coarse = grid.subsample(2);
+        assert_eq!((coarse.width, coarse.height), (3, 2));
+        assert_eq!(coarse.data, vec![0.0, 2.0, 4.0, 10.0, 12.0, 14.0]);
+        assert_eq!(coarse.cellsize, 4.0);
+        assert_eq!(coarse.xllcorner, 1.0);
+    }
+
+    #[test]
+    /// A point is mapped to the cell it falls in, counting rows from the top; points off the grid,
+    /// including the right and top edges of the extent, have no cell.
+    fn test_cell_at() {
+        let grid = Grid { data: vec![0.0; 6], width: 3, height: 2, cellsize: 10.0, cellsize_y: 10.0, xllcorner: 100.0, yllcorner: 200.0 };
+        assert_eq!(grid.cell_at(105.0, 215.0), Some((0, 0)));
+        assert_eq!(grid.cell_at(125.0, 201.0), Some((2, 1)));
+        assert_eq!(grid.cell_at(100.0, 200.0), Some((0, 1)));
+        assert_eq!(grid.cell_at(99.0, 205.0), None);
+        assert_eq!(grid.cell_at(105.0, 220.0), None);
+        assert_eq!(grid.cell_at(130.0, 205.0), None);
+    }
+
+    #[test]
+    /// Data in the top-right corner is cropped out and the corner moves by the crop offset times the cellsize.
+    fn test_autocrop_corner_data() {
+        let nan = f32::NAN;
+        let grid = Grid {
+            data: vec![
+                nan, nan, 1.0, 2.0,
+                nan, nan, 3.0, nan,
+                nan, nan, nan, nan,
+            ],
+            width: 4,
+            height: 3,
+            cellsize: 10.0,
+            cellsize_y: 10.0,
+            xllcorner: 1000.0,
+            yllcorner: 5000.0,
+        };
+        let cropped = autocrop(&grid);
+        assert_eq!((cropped.width, cropped.height), (2, 2));
+        assert_eq!(cropped.data[..3], [1.0, 2.0, 3.0]);
+        assert!(cropped.data[3].is_nan());
+        // two columns cut on the left, one row cut at the bottom
+        assert_eq!(cropped.xllcorner, 1000.0 + 2.0 * 10.0);
+        assert_eq!(cropped.yllcorner, 5000.0 + 1.0 * 10.0);
+        // the top edge stays where it was
+        let top = |g: &Grid| g.yllcorner + g.height as f64 * g.cellsize as f64;
+        assert_eq!(top(&cropped), top(&grid));
+    }
+
+    #[test]
+    /// A grid without finite cells is left untouched.
+    fn test_autocrop_all_nan() {
+        let grid = Grid { data: vec![f32::NAN; 4], width: 2, height: 2, cellsize: 1.0, cellsize_y: 1.0, xllcorner: 0.0, yllcorner: 0.0 };
+        let cropped = autocrop(&grid);
+        assert_eq!((cropped.width, cropped.height), (2, 2));
+    }
+
+    #[test]
+    /// It checks that two horizontally adjacent 2x2 tiles merge into a 4x2 grid, in any order.
+    fn test_merge_tiles_side_by_side() {
+        let west = Grid { data: vec![1.0, 2.0, 5.0, 6.0], width: 2, height: 2, cellsize: 10.0, cellsize_y: 10.0, xllcorner: 100.0, yllcorner: 50.0 };
+        let east = Grid { data: vec![3.0, 4.0, 7.0, 8.0], width: 2, height: 2, cellsize: 10.0, cellsize_y: 10.0, xllcorner: 120.0, yllcorner: 50.0 };
+        let merged = merge_tiles(&[east.clone(), west.clone()]).unwrap();
+        assert_eq!((merged.width, merged.height), (4, 2));
+        assert_eq!(merged.data, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0]);
+        assert_eq!((merged.xllcorner, merged.yllcorner), (100.0, 50.0));
+
+        let coarse = Grid { cellsize: 20.0, ..east };
+        assert!(merge_tiles(&[west, coarse]).is_err());
+    }
+
+    #[test]
+    /// It checks that flipping a 2x3 grid reverses its rows and keeps its size.
+    fn test_flip_vertical() {
+        let mut grid = Grid { data: vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], width: 2, height: 3, cellsize: 1.0, cellsize_y: 1.0, xllcorner: 0.0, yllcorner: 0.0 };
+        flip_vertical(&mut grid);
+        assert_eq!(grid.data, vec![5.0, 6.0, 3.0, 4.0, 1.0, 2.0]);
+        assert_eq!((grid.width, grid.height), (2, 3));
+    }
+
+    #[test]
+    /// It checks that cropping an interior box keeps the touched cells and moves the origin, and that bounds clamp.
+    fn test_crop_interior() {
+        // 5x4 grid of 10 m cells, lower-left corner at (1000, 2000), top edge at 2040
+        let grid = Grid { data: (0..20).map(|v| v as f32).collect(), width: 5, height: 4, cellsize: 10.0, cellsize_y: 10.0, xllcorner: 1000.0, yllcorner: 2000.0 };
+        let cropped = crop(&grid, 1012.0, 2011.0, 1038.0, 2029.0);
+        assert_eq!((cropped.width, cropped.height), (3, 2));
+        assert_eq!(cropped.data, vec![6.0, 7.0, 8.0, 11.0, 12.0, 13.0]);
+        assert_eq!((cropped.xllcorner, cropped.yllcorner), (1010.0, 2010.0));
+
+        let clamped = crop(&grid, 0.0, 0.0, 1e9, 1e9);
+        assert_eq!(clamped, grid);
+    }
+
+    #[test]
+    /// A grid minus itself is flat zero, and minus a copy shifted by one cell it holds the local step.
+    fn test_diff_dems() {
+        let data: Vec<f32> = (0..12).map(|v| (v * v) as f32).collect();
+        let a = Grid { data, width: 4, height: 3, cellsize: 5.0, cellsize_y: 5.0, xllcorner: 10.0, yllcorner: 20.0 };
+        let zero = diff_dems(&a, &a).unwrap();
+        assert!(zero.data.iter().all(|v| *v == 0.0));
+        assert_eq!((zero.width, zero.xllcorner, zero.yllcorner), (4, 10.0, 20.0));
+
+        let mut shifted = a.clone();
+        shifted.data = (1..13).map(|v| (v * v) as f32).collect();
+        shifted.data[5] = f32::NAN;
+        let diff = diff_dems(&shifted, &a).unwrap();
+        for (i, value) in diff.data.iter().enumerate() {
+            if i == 5 {
+                assert!(value.is_nan());
+            } else {
+                assert_eq!(*value, (2 * i + 1) as f32);
+            }
+        }
+
+        let other = Grid { cellsize: 2.0, ..a.clone() };
+        assert!(diff_dems(&a, &other).is_err());
+        assert!(diff_dems(&a, &a.subsample(2)).is_err());
+    }
+
+    #[test]
+    /// Cells centered inside a rectangle keep their value and the ones outside become NaN.
+    fn test_mask_by_polygon_rectangle() {
+        let mut grid = Grid { data: (0..20).map(|v| v as f32).collect(), width: 5, height: 4, cellsize: 10.0, cellsize_y: 10.0, xllcorner: 100.0, yllcorner: 200.0 };
+        // covers the centers of columns 1 to 3 in the two middle rows
+        let rectangle = [(112.0, 212.0), (138.0, 212.0), (138.0, 228.0), (112.0, 228.0), (112.0, 212.0)];
+        mask_by_polygon(&mut grid, &rectangle);
+        for (i, value) in grid.data.iter().enumerate() {
+            let (x, y) = (i % 5, i / 5);
+            if (1..=3).contains(&x) && (1..=2).contains(&y) {
+                assert_eq!(*value, i as f32, "({}, {})", x, y);
+            } else {
+                assert!(value.is_nan(), "({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    /// Only the cells inside the elevation window stay valid, the bounds included.
+    fn test_mask_elevation_band() {
+        let mut grid = Grid { data: vec![1.0, 2.0, 3.0, f32::NAN, 4.0, 5.0], width: 3, height: 2, cellsize: 1.0, cellsize_y: 1.0, xllcorner: 0.0, yllcorner: 0.0 };
+        mask_elevation_band(&mut grid, 2.0, 4.0);
+        let valid: Vec<bool> = grid.data.iter().map(|v| !v.is_nan()).collect();
+        assert_eq!(valid, vec![false, true, true, false, true, false]);
+        assert_eq!(grid.data[1..3], [2.0, 3.0]);
+    }
+
+    #[test]
+    /// A checkerboard mask blanks every other cell and keeps the rest.
+    fn test_apply_mask_checkerboard() {
+        let mut grid = Grid { data: (0..24).map(|v| v as f32).collect(), width: 6, height: 4, cellsize: 1.0, cellsize_y: 1.0, xllcorner: 0.0, yllcorner: 0.0 };
+        let checkerboard = (0..24).map(|i| ((i % 6 + i / 6) % 2) as f32).collect();
+        let mask = Grid { data: checkerboard, ..grid.clone() };
+        apply_mask(&mut grid, &mask, 1.0).unwrap();
+        assert_eq!(grid.data.iter().filter(|v| v.is_nan()).count(), 12);
+        assert!(grid.data[0].is_nan());
+        assert_eq!(grid.data[1], 1.0);
+        assert_eq!(grid.data[6], 6.0);
+
+        let small = Grid { data: vec![1.0; 4], width: 2, height: 2, ..mask };
+        assert!(apply_mask(&mut grid, &small, 1.0).is_err());
+    }
+}
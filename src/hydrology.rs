@@ -0,0 +1,331 @@
+use std::collections::VecDeque;
+
+use image::{GrayImage, Luma};
+
+/// D8 neighbor offsets `(dx, dy)`: E, SE, S, SW, W, NW, N, NE.
+/// A flow direction is stored as an index into this table.
+pub const D8_OFFSETS: [(i32, i32); 8] = [(1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1), (1, -1)];
+
+/// Returns whether a D8 direction index points to a diagonal neighbor.
+pub fn is_diagonal(direction: u8) -> bool {
+    direction % 2 == 1
+}
+
+/// Computes D8 flow directions (steepest descent to one of the 8 neighbors).
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// # Returns
+/// * One direction per cell as an index into `D8_OFFSETS`, or `None` for NaN cells and outlets.
+///
+/// NaN cells and the grid border act as boundaries: a cell next to them without a lower neighbor
+/// is an outlet. Interior depressions route to their lowest neighbor.
+pub fn flow_direction_d8(data: &Vec<f32>, width: u32, height: u32) -> Vec<Option<u8>> {
+    let (w, h) = (width as i32, height as i32);
+    let mut directions = vec![None; data.len()];
+
+    for y in 0..h {
+        for x in 0..w {
+            let z = data[(y * w + x) as usize];
+            if z.is_nan() {
+                continue;
+            }
+            let mut steepest: Option<(u8, f32)> = None;
+            let mut lowest: Option<(u8, f32)> = None;
+            let mut on_boundary = false;
+
+            for (direction, &(dx, dy)) in D8_OFFSETS.iter().enumerate() {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || ny < 0 || nx >= w || ny >= h {
+                    on_boundary = true;
+                    continue;
+                }
+                let zn = data[(ny * w + nx) as usize];
+                if zn.is_nan() {
+                    on_boundary = true;
+                    continue;
+                }
+                let distance = if is_diagonal(direction as u8) { std::f32::consts::SQRT_2 } else { 1.0 };
+                let drop = (z - zn) / distance;
+                if drop > 0.0 && steepest.is_none_or(|(_, best)| drop > best) {
+                    steepest = Some((direction as u8, drop));
+                }
+                if lowest.is_none_or(|(_, best)| zn < best) {
+                    lowest = Some((direction as u8, zn));
+                }
+            }
+
+            directions[(y * w + x) as usize] = match (steepest, on_boundary) {
+                (Some((direction, _)), _) => Some(direction),
+                (None, true) => None,
+                (None, false) => lowest.map(|(direction, _)| direction),
+            };
+        }
+    }
+    directions
+}
+
+/// Index of the cell a D8 direction points to.
+fn downstream(idx: usize, direction: u8, width: u32) -> usize {
+    let (dx, dy) = D8_OFFSETS[direction as usize];
+    let x = (idx % width as usize) as i32 + dx;
+    let y = (idx / width as usize) as i32 + dy;
+    (y * width as i32 + x) as usize
+}
+
+/// Accumulates upstream cell counts along D8 flow directions.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `directions` - The flow directions from `flow_direction_d8`.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// # Returns
+/// * The number of cells draining through each cell, itself included (0 for NaN cells).
+///
+/// Cells are visited in topological order (Kahn's algorithm), so each cell is passed downstream
+/// once all of its contributors are done. Cells caught in a flat loop keep their partial counts.
+pub fn accumulate_flow(data: &Vec<f32>, directions: &[Option<u8>], width: u32, height: u32) -> Vec<u32> {
+    let n = (width * height) as usize;
+    let mut accumulation: Vec<u32> = data.iter().map(|z| if z.is_nan() { 0 } else { 1 }).collect();
+    let mut in_degree = vec![0u32; n];
+    for (idx, direction) in directions.iter().enumerate() {
+        if let Some(direction) = direction {
+            in_degree[downstream(idx, *direction, width)] += 1;
+        }
+    }
+
+    let mut queue: VecDeque<usize> = (0..n).filter(|&idx| in_degree[idx] == 0 && !data[idx].is_nan()).collect();
+    while let Some(idx) = queue.pop_front() {
+        if let Some(direction) = directions[idx] {
+            let target = downstream(idx, direction, width);
+            accumulation[target] += accumulation[idx];
+            in_degree[target] -= 1;
+            if in_degree[target] == 0 {
+                queue.push_back(target);
+            }
+        }
+    }
+    accumulation
+}
+
+/// One triangular facet of D-infinity, between a cell, a cardinal neighbor `e1` and a diagonal
+/// neighbor `e2` given as `(dx, dy)` offsets. `ac` and `af` turn the angle inside the facet into
+/// a flow angle (Tarboton, 1997).
+struct Facet {
+    e1: (i32, i32),
+    e2: (i32, i32),
+    ac: f32,
+    af: f32,
+}
+
+/// The eight facets around a cell, counterclockwise from east. North is the row above.
+const DINF_FACETS: [Facet; 8] = [
+    Facet { e1: (1, 0), e2: (1, -1), ac: 0.0, af: 1.0 },
+    Facet { e1: (0, -1), e2: (1, -1), ac: 1.0, af: -1.0 },
+    Facet { e1: (0, -1), e2: (-1, -1), ac: 1.0, af: 1.0 },
+    Facet { e1: (-1, 0), e2: (-1, -1), ac: 2.0, af: -1.0 },
+    Facet { e1: (-1, 0), e2: (-1, 1), ac: 2.0, af: 1.0 },
+    Facet { e1: (0, 1), e2: (-1, 1), ac: 3.0, af: -1.0 },
+    Facet { e1: (0, 1), e2: (1, 1), ac: 3.0, af: 1.0 },
+    Facet { e1: (1, 0), e2: (1, 1), ac: 4.0, af: -1.0 },
+];
+
+/// Computes D-infinity flow directions (Tarboton, 1997): the direction of steepest descent over
+/// the eight triangular facets formed by each cell and two adjacent neighbors.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `cellsize` - The size of each cell in the elevation data.
+/// # Returns
+/// * One flow angle per cell in radians, from 0 to 2π counterclockwise from east (π/2 is north,
+///   towards the top row). NaN cells and cells without any downhill facet (pits, flats) are NaN.
+///
+/// Unlike D8, the angle is continuous, so flow on a planar slope follows the true gradient.
+/// Facets touching the grid border or a NaN cell are left out.
+pub fn flow_direction_dinf(data: &Vec<f32>, width: u32, height: u32, cellsize: f32) -> Vec<f32> {
+    let (w, h) = (width as i32, height as i32);
+    let at = |x: i32, y: i32| if x < 0 || y < 0 || x >= w || y >= h { f32::NAN } else { data[(y * w + x) as usize] };
+    let diagonal = cellsize * std::f32::consts::SQRT_2;
+    let mut directions = vec![f32::NAN; data.len()];
+
+    for y in 0..h {
+        for x in 0..w {
+            let e0 = at(x, y);
+            if e0.is_nan() {
+                continue;
+            }
+            let mut steepest: Option<(f32, f32)> = None;
+            for facet in &DINF_FACETS {
+                let (e1, e2) = (at(x + facet.e1.0, y + facet.e1.1), at(x + facet.e2.0, y + facet.e2.1));
+                if e1.is_nan() || e2.is_nan() {
+                    continue;
+                }
+                let s1 = (e0 - e1) / cellsize;
+                let s2 = (e1 - e2) / cellsize;
+                let mut r = s2.atan2(s1);
+                let mut slope = (s1 * s1 + s2 * s2).sqrt();
+                // keep the direction inside the facet, else flow along one of its edges
+                if r < 0.0 {
+                    r = 0.0;
+                    slope = s1;
+                } else if r > std::f32::consts::FRAC_PI_4 {
+                    r = std::f32::consts::FRAC_PI_4;
+                    slope = (e0 - e2) / diagonal;
+                }
+                if slope > 0.0 && steepest.is_none_or(|(best, _)| slope > best) {
+                    steepest = Some((slope, facet.af * r + facet.ac * std::f32::consts::FRAC_PI_2));
+                }
+            }
+            if let Some((_, angle)) = steepest {
+                directions[(y * w + x) as usize] = angle % std::f32::consts::TAU;
+            }
+        }
+    }
+    directions
+}
+
+/// Renders the D8 flow accumulation so that drainage networks stand out.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// # Returns
+/// * A `GrayImage` of `ln(accumulation)` scaled so the largest stream is 255. Hillslope cells
+///   draining only themselves and NaN cells are black.
+///
+/// Run `preprocess::fill_sinks` first on raw DEMs, otherwise pits break the networks apart.
+pub fn flow_accumulation(data: &Vec<f32>, width: u32, height: u32) -> GrayImage {
+    let directions = flow_direction_d8(data, width, height);
+    let accumulation = accumulate_flow(data, &directions, width, height);
+    let max = accumulation.iter().copied().max().unwrap_or(0);
+    let mut image = GrayImage::new(width, height);
+    if max <= 1 {
+        return image;
+    }
+    let scale = 255.0 / (max as f32).ln();
+    for (i, &count) in accumulation.iter().enumerate() {
+        if count > 0 {
+            let value = ((count as f32).ln() * scale).round() as u8;
+            image.put_pixel(i as u32 % width, i as u32 / width, Luma([value]));
+        }
+    }
+    image
+}
+
+/// Computes the drainage density: total channel length per unit basin area.
+/// # Arguments
+/// * `accumulation` - Upstream cell counts from `accumulate_flow`.
+/// * `directions` - The flow directions from `flow_direction_d8`.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `cellsize` - The size of each cell in the elevation data.
+/// * `channel_threshold` - Minimum accumulation for a cell to count as a channel.
+/// # Returns
+/// * The channel length divided by the basin area (in 1/units of `cellsize`), 0 for an empty basin.
+///
+/// Each channel cell contributes the length of its flow step: `cellsize` orthogonally,
+/// `cellsize * sqrt(2)` diagonally, and `cellsize` for outlets.
+pub fn drainage_density(accumulation: &[u32], directions: &[Option<u8>], width: u32, height: u32, cellsize: f32, channel_threshold: u32) -> f32 {
+    let cells = (width * height) as usize;
+    let mut channel_length = 0.0;
+    let mut basin_cells = 0;
+    for idx in 0..cells {
+        if accumulation[idx] == 0 {
+            continue;
+        }
+        basin_cells += 1;
+        if accumulation[idx] >= channel_threshold {
+            channel_length += match directions[idx] {
+                Some(direction) if is_diagonal(direction) => cellsize * std::f32::consts::SQRT_2,
+                _ => cellsize,
+            };
+        }
+    }
+    if basin_cells == 0 {
+        return 0.0;
+    }
+    channel_length / (basin_cells as f32 * cellsize * cellsize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 5x5 V-shaped valley draining south along the middle column.
+    fn v_valley() -> Vec<f32> {
+        let mut data = Vec::new();
+        for y in 0..5 {
+            for x in 0..5i32 {
+                data.push((x - 2).abs() as f32 * 10.0 + (4 - y) as f32);
+            }
+        }
+        data
+    }
+
+    #[test]
+    /// Banks drain into the middle column, which collects the whole valley at the outlet.
+    fn test_accumulate_flow_valley() {
+        let data = v_valley();
+        let directions = flow_direction_d8(&data, 5, 5);
+        let accumulation = accumulate_flow(&data, &directions, 5, 5);
+        assert_eq!(directions[2 * 5 + 2], Some(2)); // channel flows south
+        assert_eq!(directions[4 * 5 + 2], None); // outlet on the border
+        assert_eq!(accumulation[4 * 5 + 2], 25);
+        assert_eq!(accumulation[2], 5);
+        assert_eq!(accumulation[1], 2);
+    }
+
+    #[test]
+    /// The 5 channel cells with a 10 m cellsize give 50 m of channel over 2500 m² of basin.
+    fn test_drainage_density_valley() {
+        let data = v_valley();
+        let directions = flow_direction_d8(&data, 5, 5);
+        let accumulation = accumulate_flow(&data, &directions, 5, 5);
+        let density = drainage_density(&accumulation, &directions, 5, 5, 10.0, 5);
+        assert!((density - 0.02).abs() < 1e-6);
+    }
+
+    #[test]
+    /// The outlet of the channel is the brightest cell, and the channel is brighter than its banks.
+    fn test_flow_accumulation_valley() {
+        let image = flow_accumulation(&v_valley(), 5, 5);
+        assert_eq!(image.get_pixel(2, 4)[0], 255);
+        assert_eq!(image.pixels().filter(|p| p[0] == 255).count(), 1);
+        for y in 1..5 {
+            assert!(image.get_pixel(2, y)[0] > image.get_pixel(1, y)[0]);
+        }
+        assert_eq!(image.get_pixel(0, 0)[0], 0);
+    }
+
+    #[test]
+    /// On tilted planes the D-infinity angle matches the downhill direction of the plane.
+    fn test_dinf_planar_slope() {
+        let (width, height) = (7, 7);
+        for degrees in [0.0f32, 30.0, 135.0, 250.0, 330.0] {
+            let angle = degrees.to_radians();
+            // the row index grows southwards, hence the minus sign on the north component
+            let data: Vec<f32> = (0..width * height)
+                .map(|i| {
+                    let (x, y) = ((i % width) as f32, (i / width) as f32);
+                    100.0 - 2.0 * (x * angle.cos() - y * angle.sin())
+                })
+                .collect();
+            let directions = flow_direction_dinf(&data, width, height, 10.0);
+            for y in 1..height - 1 {
+                for x in 1..width - 1 {
+                    let direction = directions[(y * width + x) as usize];
+                    let error = (direction - angle).abs().min(std::f32::consts::TAU - (direction - angle).abs());
+                    assert!(error < 1e-4, "{} degrees: got {} at ({}, {})", degrees, direction.to_degrees(), x, y);
+                }
+            }
+        }
+
+        let mut pit = vec![5.0; 9];
+        pit[4] = 1.0;
+        pit[0] = f32::NAN;
+        let directions = flow_direction_dinf(&pit, 3, 3, 1.0);
+        assert!(directions[4].is_nan() && directions[0].is_nan());
+    }
+}
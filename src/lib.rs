@@ -0,0 +1,1421 @@
+use std::fs::File;
+use std::error::Error;
+use std::fmt;
+use colorgrad::{Gradient, preset};
+use image::{ImageBuffer, Luma, Rgba, RgbaImage, GrayImage};
+use anyhow::Result;
+use std::io::{BufRead, BufReader, Read};
+use imageproc::drawing::{draw_antialiased_line_segment_mut, draw_line_segment_mut};
+use imageproc::pixelops::interpolate;
+use std::f32::consts::PI;
+use rayon::prelude::*;
+use log::warn;
+use crate::relief::SlopeAlgo;
+
+pub mod animate;
+pub mod annotate;
+pub mod archive;
+pub mod batch;
+pub mod cli;
+pub mod color;
+pub mod components;
+pub mod contours;
+pub mod filters;
+pub mod formats;
+pub mod georef;
+pub mod geotiff;
+pub mod grid;
+pub mod hydrology;
+pub mod mesh;
+pub mod metadata;
+pub mod morphometry;
+pub mod pipeline;
+pub mod preprocess;
+pub mod progress;
+pub mod qa;
+pub mod relief;
+pub mod resample;
+pub mod sink;
+pub mod tiles;
+pub mod zonal;
+
+/// Reads the content of a file and returns it as a string.
+/// # Arguments
+/// * `file_path` - A string representing path to the file.
+///
+/// # Returns
+/// * A `String` containing the content of the file.
+///
+pub fn read_file(file_path: &str) -> String {
+    // Read a file in the local file system
+    let mut data_file = File::open(file_path).unwrap();
+    
+    // Create an empty mutable string
+    let mut file_content = String::new();
+    
+    // Copy contents of file to a mutable string
+    data_file.read_to_string(&mut file_content).unwrap();
+    return file_content;
+}
+
+/// Reads the whole standard input and returns it as a string.
+/// Used when the input path is `-` or when data is piped in without a path.
+/// # Returns
+/// * A `String` containing everything read from stdin.
+pub fn read_stdin() -> std::io::Result<String> {
+    let mut content = String::new();
+    std::io::stdin().read_to_string(&mut content)?;
+    Ok(content)
+}
+
+
+/// Removes the UTF-8 byte order mark that some Windows editors put at the start of text files.
+/// # Arguments
+/// * `content` - The text as read from the file.
+pub fn strip_bom(content: &str) -> &str {
+    content.strip_prefix('\u{feff}').unwrap_or(content)
+}
+
+/// Number of header lines at the top of an ASC file.
+pub const ASC_HEADER_LINES: usize = 6;
+
+/// Number of lines of a header declaring `dx` and `dy` instead of `cellsize`.
+pub const ASC_MAX_HEADER_LINES: usize = 7;
+
+/// Keys that may open an ASC header line, lowercase.
+const ASC_HEADER_KEYS: [&str; 10] = ["ncols", "nrows", "xllcorner", "yllcorner", "xllcenter", "yllcenter", "cellsize", "dx", "dy", "nodata_value"];
+
+/// Whether a token opens an ASC header line: one of the known keys, or another word some exporters
+/// add (which is skipped). Data rows open with a number, even one that doesn't parse with the
+/// current options, such as `1,25` read without `--decimal-comma`.
+fn is_header_key(token: &str) -> bool {
+    let key = token.to_ascii_lowercase();
+    ASC_HEADER_KEYS.contains(&key.as_str()) || (key.starts_with(|c: char| c.is_ascii_alphabetic()) && key.parse::<f32>().is_err())
+}
+
+/// Header fields of an ESRI ASCII grid.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AscHeader {
+    pub ncols: u32,
+    pub nrows: u32,
+    /// X coordinate of the lower-left corner of the lower-left cell.
+    pub xllcorner: f64,
+    /// Y coordinate of the lower-left corner of the lower-left cell.
+    pub yllcorner: f64,
+    /// `None` when the header does not declare a cellsize. A `dx` key is read as the cellsize.
+    pub cellsize: Option<f32>,
+    /// Cell height declared by a `dy` key, `None` for square cells.
+    pub dy: Option<f32>,
+    /// `None` when the header does not declare a nodata value.
+    pub nodata_value: Option<f32>,
+    /// Number of lines consumed by the header.
+    pub header_lines: usize,
+}
+
+impl AscHeader {
+    /// Returns the `(dx, dy)` size of the cells: `dy` defaults to the cellsize, which defaults to 1.
+    pub fn cell_dims(&self) -> (f32, f32) {
+        let dx = self.cellsize.unwrap_or(1.0);
+        (dx, self.dy.unwrap_or(dx))
+    }
+}
+
+impl fmt::Display for AscHeader {
+    /// Writes one `key: value` line per field, as printed by `--info`. Missing values read `none`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let optional = |value: Option<f32>| value.map_or("none".to_string(), |v| v.to_string());
+        writeln!(f, "ncols: {}", self.ncols)?;
+        writeln!(f, "nrows: {}", self.nrows)?;
+        writeln!(f, "cellsize: {}", optional(self.cellsize))?;
+        writeln!(f, "dy: {}", optional(self.dy))?;
+        writeln!(f, "nodata_value: {}", optional(self.nodata_value))?;
+        writeln!(f, "xllcorner: {}", self.xllcorner)?;
+        writeln!(f, "yllcorner: {}", self.yllcorner)?;
+        writeln!(f, "header_lines: {}", self.header_lines)
+    }
+}
+
+/// Options controlling how the numbers of an ASC file are read.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ParseOptions {
+    /// Numbers use a comma as decimal separator (`1,23`), as in many European exports.
+    pub decimal_comma: bool,
+    /// A value masked as NoData in addition to the header's `nodata_value`, for files that use a
+    /// sentinel (e.g. `-32768`) without declaring it.
+    pub nodata_override: Option<f32>,
+    /// Size of the (square) cells, replacing the header's `cellsize` or `dx`/`dy`, for files that omit
+    /// or misstate it. Without it or a header value the cells default to 1.
+    pub cellsize: Option<f32>,
+}
+
+/// Parses one number of an ASC file, honoring the decimal separator of the options.
+/// Scientific notation (`1.2E3`, `4e-1`) is accepted as well.
+fn parse_number<T: std::str::FromStr>(token: &str, options: &ParseOptions) -> Result<T, T::Err> {
+    if options.decimal_comma {
+        token.replace(',', ".").parse::<T>()
+    } else {
+        token.parse::<T>()
+    }
+}
+
+/// Parses the header of an ASC file.
+/// # Arguments
+/// * `content` - A string containing the content of the ASC file.
+/// # Returns
+/// * An `AscHeader` with the grid size, corner coordinates, cell size and nodata value.
+///
+/// `xllcenter`/`yllcenter` headers are converted to corner coordinates. A leading BOM is
+/// ignored and lines may end with `\n` or `\r\n`.
+pub fn parse_header(content: &str) -> Result<AscHeader, Box<dyn Error>> {
+    parse_header_with(content, &ParseOptions::default())
+}
+
+/// Same as `parse_header`, reading the numbers according to `options`.
+pub fn parse_header_with(content: &str, options: &ParseOptions) -> Result<AscHeader, Box<dyn Error>> {
+    let content = strip_bom(content);
+    let mut header = AscHeader::default();
+    let mut center = (false, false);
+
+    for line in content.lines().take(ASC_MAX_HEADER_LINES) {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        // the data starts at the first line not opening with a header key, so a data row whose
+        // numbers fail to parse (e.g. decimal commas) is still read as data and counted as skipped
+        if parts.first().is_none_or(|first| !is_header_key(first)) {
+            break;
+        }
+        header.header_lines += 1;
+        let [key, value] = parts.as_slice() else { continue };
+        // keys are case-insensitive, ArcGIS writes `NCOLS` and `NODATA_value`
+        match key.to_ascii_lowercase().as_str() {
+            "ncols" => header.ncols = value.parse::<u32>()?,
+            "nrows" => header.nrows = value.parse::<u32>()?,
+            "xllcorner" => header.xllcorner = parse_number(value, options)?,
+            "yllcorner" => header.yllcorner = parse_number(value, options)?,
+            "xllcenter" => { header.xllcorner = parse_number(value, options)?; center.0 = true; }
+            "yllcenter" => { header.yllcorner = parse_number(value, options)?; center.1 = true; }
+            "nodata_value" => header.nodata_value = Some(parse_number(value, options)?),
+            "cellsize" | "dx" => header.cellsize = Some(parse_number(value, options)?),
+            "dy" => header.dy = Some(parse_number(value, options)?),
+            _ => {}
+        }
+    }
+
+    if let Some(cellsize) = options.cellsize {
+        if cellsize <= 0.0 || !cellsize.is_finite() {
+            return Err(format!("cellsize must be positive, got {}", cellsize).into());
+        }
+        header.cellsize = Some(cellsize);
+        header.dy = None;
+    }
+
+    // Center coordinates refer to the middle of the lower-left cell
+    let (dx, dy) = header.cell_dims();
+    if center.0 {
+        header.xllcorner -= dx as f64 / 2.0;
+    }
+    if center.1 {
+        header.yllcorner -= dy as f64 / 2.0;
+    }
+    Ok(header)
+}
+
+/// Logs a warning when the header gives no cellsize, since slopes and hillshades computed with the
+/// default of 1 are wrong for any other cell size. The CLI also lists it in its QA report.
+fn warn_default_cellsize(header: &AscHeader) {
+    if header.cellsize.is_none() {
+        warn!("No cellsize in the ASC header, defaulting to 1.0 (set the true value with --cellsize)");
+    }
+}
+
+/// Parses an ASC file content into elevation data, width, and height.
+/// Arguments
+/// * `content` - A string containing the content of the ASC file.
+/// Returns a tuple containing the elevation data as a vector of f32, width, height, and cell size.
+pub fn asc_to_image(content: String) -> Result<(Vec<f32>, u32, u32,f32), Box<dyn Error>> {
+    let (data_elevation, width, height, cellsize, _) = asc_to_image_with(content, &ParseOptions::default())?;
+    Ok((data_elevation, width, height, cellsize))
+}
+
+/// Same as `asc_to_image`, reading the numbers according to `options`.
+/// # Arguments
+/// * `content` - A string containing the content of the ASC file.
+/// * `options` - How numbers are written in the file.
+/// # Returns
+/// * The elevation data, width, height, cell size and the number of tokens that could not be
+///   parsed as numbers and were skipped.
+pub fn asc_to_image_with(content: String, options: &ParseOptions) -> Result<(Vec<f32>, u32, u32, f32, usize), Box<dyn Error>> {
+    let header = parse_header_with(&content, options)?;
+    warn_default_cellsize(&header);
+    let nodata_value = header.nodata_value.unwrap_or(f32::NAN);
+    let nodata_override = options.nodata_override.unwrap_or(f32::NAN);
+    let mut data_elevation = Vec::new();
+    let mut skipped = 0;
+
+    // lines() also drops the '\r' of Windows line endings
+    for line in strip_bom(&content).lines().skip(header.header_lines) {
+        // Read the elevation data
+        for part in line.split_whitespace() {
+            if let Ok(value) = parse_number::<f32>(part, options) {
+                // Check if the value is equal to the nodata_value
+                // and push it as NaN if it is
+                // Otherwise, push the value as is
+                data_elevation.push(if value == nodata_value || value == nodata_override {f32::NAN} else {value});
+            } else {
+                skipped += 1;
+            }
+        }
+    }
+    Ok((data_elevation, header.ncols, header.nrows, header.cellsize.unwrap_or(1.0), skipped))
+}
+
+/// Same as `asc_to_image`, reading the file line by line instead of loading it into memory first.
+/// # Arguments
+/// * `path` - Path to the ASC file.
+/// Returns a tuple containing the elevation data as a vector of f32, width, height, and cell size.
+pub fn asc_to_image_streaming(path: &str) -> Result<(Vec<f32>, u32, u32, f32), Box<dyn Error>> {
+    let (header, data_elevation, _) = read_asc_streaming(path, &ParseOptions::default())?;
+    Ok((data_elevation, header.ncols, header.nrows, header.cellsize.unwrap_or(1.0)))
+}
+
+/// Reads an ASC file through a buffered reader, so only the elevation data is held in memory.
+/// # Arguments
+/// * `path` - Path to the ASC file.
+/// * `options` - How numbers are written in the file.
+/// # Returns
+/// * The header, the elevation data and the number of tokens that could not be parsed as numbers
+///   and were skipped. The values are the same as `asc_to_image_with` on the file content.
+pub fn read_asc_streaming(path: &str, options: &ParseOptions) -> Result<(AscHeader, Vec<f32>, usize), Box<dyn Error>> {
+    let mut reader = BufReader::new(File::open(path).map_err(|e| format!("{}: {}", path, e))?);
+    let header_text = read_header_text(&mut reader)?;
+    let header = parse_header_with(&header_text, options)?;
+    warn_default_cellsize(&header);
+    let nodata_value = header.nodata_value.unwrap_or(f32::NAN);
+    let nodata_override = options.nodata_override.unwrap_or(f32::NAN);
+    let mut data_elevation = Vec::with_capacity(header.ncols as usize * header.nrows as usize);
+    let mut skipped = 0;
+
+    let mut parse_line = |line: &str| {
+        for part in line.split_whitespace() {
+            if let Ok(value) = parse_number::<f32>(part, options) {
+                data_elevation.push(if value == nodata_value || value == nodata_override {f32::NAN} else {value});
+            } else {
+                skipped += 1;
+            }
+        }
+    };
+    // data lines read along with the header
+    for line in strip_bom(&header_text).lines().skip(header.header_lines) {
+        parse_line(line);
+    }
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 {
+        parse_line(&line);
+        line.clear();
+    }
+    Ok((header, data_elevation, skipped))
+}
+
+/// Parses the header of an ASC file without reading its elevation data.
+/// # Arguments
+/// * `path` - Path to the ASC file.
+/// * `options` - How numbers are written in the file.
+/// # Returns
+/// * The header, as `parse_header_with` reads it. Only the first `ASC_MAX_HEADER_LINES` lines are read.
+pub fn read_asc_header(path: &str, options: &ParseOptions) -> Result<AscHeader, Box<dyn Error>> {
+    let mut reader = BufReader::new(File::open(path).map_err(|e| format!("{}: {}", path, e))?);
+    parse_header_with(&read_header_text(&mut reader)?, options)
+}
+
+/// Reads the first lines of an ASC file, which hold the whole header: it never spans more than
+/// `ASC_MAX_HEADER_LINES` lines. The text may end with the first data rows.
+fn read_header_text(reader: &mut impl BufRead) -> std::io::Result<String> {
+    let mut header_text = String::new();
+    for _ in 0..ASC_MAX_HEADER_LINES {
+        if reader.read_line(&mut header_text)? == 0 {
+            break;
+        }
+    }
+    Ok(header_text)
+}
+
+/// Checks that a grid has a non-zero size and exactly `width * height` values.
+/// # Arguments
+/// * `len` - The number of values of the grid.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+pub fn check_dimensions(len: usize, width: u32, height: u32) -> Result<(), Box<dyn Error>> {
+    if width == 0 || height == 0 {
+        return Err(format!("empty grid: {}x{}", width, height).into());
+    }
+    let expected = width as usize * height as usize;
+    if len != expected {
+        return Err(format!("grid has {} values but {}x{} needs {}", len, width, height, expected).into());
+    }
+    Ok(())
+}
+
+/// Error returned when a grid has no valid (non-NaN) cell to normalize.
+pub const NO_VALID_DATA: &str = "no valid data: every cell is NoData";
+
+/// Returns the minimum and maximum of the valid (non-NaN) values.
+/// # Arguments
+/// * `data` - The elevation data.
+/// # Returns
+/// * `None` when every value is NaN, in which case no normalization is possible.
+pub fn elevation_range(data: &[f32]) -> Option<(f32, f32)> {
+    data.iter()
+        .filter(|v| !v.is_nan())
+        .fold(None, |range, &v| match range {
+            None => Some((v, v)),
+            Some((min, max)) => Some((f32::min(min, v), f32::max(max, v))),
+        })
+}
+
+/// How the elevation range mapped onto the grayscale and color ramps is chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum NormMode {
+    /// The lowest and highest valid elevations.
+    #[default]
+    MinMax,
+    /// The given low and high percentiles (e.g. 2 and 98), so a few outliers don't flatten the rest.
+    Percentile(f32, f32),
+    /// The mean plus or minus this many standard deviations.
+    StdDev(f32),
+}
+
+impl NormMode {
+    /// Computes the range to pass as `value_range` to `data_to_grayscale` and `rgb`.
+    /// # Arguments
+    /// * `data` - The elevation data, NaN cells are ignored.
+    /// # Returns
+    /// * `None` for `MinMax`, for data without valid cells, and when the range would be empty
+    ///   (e.g. a flat grid), so the functions fall back to the min/max of the data.
+    pub fn value_range(&self, data: &[f32]) -> Option<(f32, f32)> {
+        let mut valid: Vec<f32> = data.iter().copied().filter(|v| !v.is_nan()).collect();
+        if valid.is_empty() {
+            return None;
+        }
+        let range = match *self {
+            NormMode::MinMax => return None,
+            NormMode::Percentile(low, high) => {
+                valid.sort_by(|a, b| a.total_cmp(b));
+                (percentile(&valid, low), percentile(&valid, high))
+            }
+            NormMode::StdDev(k) => {
+                let n = valid.len() as f64;
+                let mean = valid.iter().map(|&v| v as f64).sum::<f64>() / n;
+                let std_dev = (valid.iter().map(|&v| (v as f64 - mean).powi(2)).sum::<f64>() / n).sqrt();
+                ((mean - k as f64 * std_dev) as f32, (mean + k as f64 * std_dev) as f32)
+            }
+        };
+        (range.0 < range.1).then_some(range)
+    }
+}
+
+/// Linearly interpolated percentile (0 to 100) of sorted values.
+fn percentile(sorted: &[f32], p: f32) -> f32 {
+    let rank = (p.clamp(0.0, 100.0) / 100.0) * (sorted.len() - 1) as f32;
+    let (i, f) = (rank.floor() as usize, rank.fract());
+    let next = sorted[(i + 1).min(sorted.len() - 1)];
+    sorted[i] + (next - sorted[i]) * f
+}
+
+/// Converts elevation data into a grayscale image.
+/// # Arguments
+/// * `data_processed` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the image.
+/// * `height` - The height of the image.
+/// * `nodata_color` - The gray level of NaN cells, `None` maps them to black.
+/// * `value_range` - Elevations mapped to black and white, `None` uses the min/max of the data.
+///   Elevations outside a fixed range clamp, so tiles rendered with the same range match.
+/// * `gamma` - Gamma correction applied as `normalized.powf(1.0 / gamma)`: above 1 brightens the
+///   midtones, 1 keeps the linear mapping. Must be positive.
+/// # Returns
+/// * A `GrayImage` object representing the grayscale image, or an error when the size is zero,
+///   does not match the number of values, or when every cell is NaN.
+pub fn data_to_grayscale(data_processed: Vec<f32>, width: u32, height: u32, nodata_color: Option<Luma<u8>>, value_range: Option<(f32, f32)>, gamma: f32) -> Result<GrayImage, Box<dyn Error>> {
+    check_dimensions(data_processed.len(), width, height)?;
+    let mut image = GrayImage::new(width, height);
+    let data_range = elevation_range(&data_processed).ok_or(NO_VALID_DATA)?;
+    let (min_val, max_val) = value_range.unwrap_or(data_range);
+    let range = max_val - min_val;
+
+    for (i, &value) in data_processed.iter().enumerate() {
+        let x = (i % width as usize) as u32;
+        let y = (i / width as usize) as u32;
+        if let (true, Some(color)) = (value.is_nan(), nodata_color) {
+            image.put_pixel(x, y, color);
+            continue;
+        }
+        let normalized_value = if range > 0.0 { ((value - min_val) / range).clamp(0.0, 1.0) } else { 0.0 };
+        let pixel_value = (normalized_value.powf(1.0 / gamma) * 255.0) as u8;
+        image.put_pixel(x, y, Luma([pixel_value]));
+    }
+    Ok(image)
+}
+
+/// Converts elevation data into a 16-bit grayscale image, keeping 256 times the precision of `data_to_grayscale`.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the image.
+/// * `height` - The height of the image.
+/// # Returns
+/// * An image where the min..max elevation range is spread over 0..65535. NaN cells are 0,
+///   as is every cell of a constant or all-NaN grid.
+pub fn data_to_grayscale16(data: &Vec<f32>, width: u32, height: u32) -> ImageBuffer<Luma<u16>, Vec<u16>> {
+    let mut image = ImageBuffer::new(width, height);
+    let Some((min_val, max_val)) = elevation_range(data) else {
+        return image;
+    };
+    let range = max_val - min_val;
+    for (i, &value) in data.iter().enumerate() {
+        if value.is_nan() || range <= 0.0 {
+            continue;
+        }
+        let normalized_value = (value - min_val) / range;
+        image.put_pixel(i as u32 % width, i as u32 / width, Luma([(normalized_value * 65535.0).round() as u16]));
+    }
+    image
+}
+
+/// Converts elevation data into an RGB image using a color gradient.
+/// # Arguments
+/// * `data_processed` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the image. 
+/// * `height` - The height of the image.
+/// * `nodata_color` - The color of NaN cells, `None` makes them fully transparent.
+/// * `value_range` - Elevations mapped to both ends of the gradient, `None` uses the min/max of
+///   the data. Elevations outside a fixed range clamp, so tiles rendered with the same range match.
+/// # Returns
+/// * A `RgbaImage` object representing the RGB image, or an error when the size is zero,
+///   does not match the number of values, or when every cell is NaN.
+///
+/// The function uses a color gradient to map the elevation data to RGB colors.
+/// The gradient is generated using the `colorgrad` crate.
+/// The function normalizes the elevation data to the range [0, 1] and then maps it to RGB colors.
+/// The function uses the `turbo` gradient from the `colorgrad` crate.
+pub fn rgb(data_processed: Vec<f32>, width: u32, height: u32, nodata_color: Option<Rgba<u8>>, value_range: Option<(f32, f32)>) -> Result<RgbaImage, Box<dyn Error>> {
+    rgb_with(data_processed, width, height, nodata_color, value_range, &preset::turbo(), false)
+}
+
+/// Same as `rgb`, coloring with `gradient` (e.g. from `color::gradient_by_name`). With `reverse`,
+/// the gradient runs from the highest elevation to the lowest.
+pub fn rgb_with(data_processed: Vec<f32>, width: u32, height: u32, nodata_color: Option<Rgba<u8>>, value_range: Option<(f32, f32)>, gradient: &dyn Gradient, reverse: bool) -> Result<RgbaImage, Box<dyn Error>> {
+    check_dimensions(data_processed.len(), width, height)?;
+    let mut image = RgbaImage::new(width, height);
+    let data_range = elevation_range(&data_processed).ok_or(NO_VALID_DATA)?;
+    let (min_val, max_val) = value_range.unwrap_or(data_range);
+    let range = max_val - min_val;
+    let progress = progress::row_progress(height, "rgb");
+
+    for (i, &value) in data_processed.iter().enumerate() {
+        let x = (i % width as usize) as u32;
+        let y = (i / width as usize) as u32;
+        if value.is_nan() {
+            // NoData stays fully transparent by default so it is obvious when overlaid
+            image.put_pixel(x, y, nodata_color.unwrap_or(Rgba([0, 0, 0, 0])));
+        } else {
+            let normalized_value = if range > 0.0 { ((value - min_val) / range).clamp(0.0, 1.0) } else { 0.0 };
+            let color = gradient.at(if reverse { 1.0 - normalized_value } else { normalized_value });
+            let [r, g, b, _] = color.to_rgba8();
+            image.put_pixel(x, y, Rgba([r, g, b, 255]));
+        }
+        // Advance once per completed row to keep the inner loop cheap
+        if x == width - 1 {
+            progress.inc(1);
+        }
+    }
+    progress.finish();
+    Ok(image)
+}
+
+/// Computes how much light an interior cell receives, the shading term of `hill_shading`.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `x`, `y` - The cell, which must not lie on the grid border (its 3x3 window is read).
+/// * `cellsize` - The `(dx, dy)` size of each cell in the elevation data.
+/// * `azimuth_rad` - The azimuth angle for the light source, in radians.
+/// * `altitude_rad` - The altitude angle for the light source, in radians.
+/// # Returns
+/// * The cosine of the angle between the light and the surface normal: 1 faces the sun,
+///   `altitude_rad.cos()` is flat ground and values below 0 are turned away from it.
+pub fn illumination(data: &[f32], width: u32, x: u32, y: u32, cellsize: (f32, f32), azimuth_rad: f32, altitude_rad: f32) -> f32 {
+    illumination_with(data, width, x, y, cellsize, azimuth_rad, altitude_rad, SlopeAlgo::Horn)
+}
+
+/// Same as `illumination`, with the surface derivatives estimated by `algo`.
+#[allow(clippy::too_many_arguments)]
+pub fn illumination_with(data: &[f32], width: u32, x: u32, y: u32, cellsize: (f32, f32), azimuth_rad: f32, altitude_rad: f32, algo: SlopeAlgo) -> f32 {
+    let z = |dx: i32, dy: i32| data[((y as i32 + dy) * width as i32 + (x as i32 + dx)) as usize];
+    let (dz_dx, dz_dy) = algo.derivatives(z, cellsize);
+
+    let slope = (dz_dx.powi(2) + dz_dy.powi(2)).sqrt().atan();
+    let aspect = dz_dy.atan2(dz_dx);
+
+    altitude_rad.cos() * slope.cos() + altitude_rad.sin() * slope.sin() * (azimuth_rad - aspect).cos()
+}
+
+/// The 3x3 Sobel derivatives of an interior cell, divided by `8 * dx` and `8 * dy` so they are in
+/// elevation per distance. `y` grows downward, as the rows of the grid.
+fn sobel_gradient(data: &[f32], width: u32, x: u32, y: u32, cellsize: (f32, f32)) -> (f32, f32) {
+    let z = |dx: i32, dy: i32| data[((y as i32 + dy) * width as i32 + (x as i32 + dx)) as usize];
+    SlopeAlgo::Horn.derivatives(z, cellsize)
+}
+
+/// Computes the grayscale hillshade of elevation data.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the image.
+/// * `height` - The height of the image.
+/// * `cellsize` - The `(dx, dy)` size of each cell in the elevation data, see `AscHeader::cell_dims`.
+/// * `azimuth` - The azimuth angle for the light source.
+/// * `altitude` - The altitude angle for the light source.
+/// * `ambient` - Share of ambient light (0 to 1), the lowest intensity of interior cells, so shadowed
+///   slopes keep their texture instead of going black. 0 gives the plain Lambert shading.
+/// # Returns
+/// * The hillshade, 255 facing the sun. Border pixels and NaN cells are black.
+/// The function calculates the slope and aspect of the terrain using the hillshading algorithm introduced in:
+/// https://pro.arcgis.com/en/pro-app/latest/tool-reference/3d-analyst/how-hillshade-works.htm
+pub fn hillshade_gray(data: &Vec<f32>, width: u32, height: u32, cellsize: (f32, f32), azimuth: f32, altitude: f32, ambient: f32) -> GrayImage {
+    hillshade_gray_with(data, width, height, cellsize, azimuth, altitude, ambient, SlopeAlgo::Horn)
+}
+
+/// Same as `hillshade_gray`, with the surface derivatives estimated by `algo`.
+#[allow(clippy::too_many_arguments)]
+pub fn hillshade_gray_with(data: &Vec<f32>, width: u32, height: u32, cellsize: (f32, f32), azimuth: f32, altitude: f32, ambient: f32, algo: SlopeAlgo) -> GrayImage {
+    let mut shaded = vec![0u8; (width * height) as usize];
+    let radians = std::f32::consts::PI / 180.0;
+    let azimuth_rad = azimuth * radians;
+    let altitude_rad = altitude * radians;
+    let progress = progress::row_progress(height.saturating_sub(2), "hillshade");
+    let row_len = width.max(1) as usize;
+
+    // Every interior row only reads its neighbors in `data` and writes its own output row,
+    // so rows are shaded in parallel. Border rows and columns keep their zero pixels.
+    shaded
+        .par_chunks_mut(row_len)
+        .enumerate()
+        .filter(|(y, _)| *y >= 1 && *y + 1 < height as usize)
+        .for_each(|(y, row)| {
+            let y = y as u32;
+            for x in 1..width.saturating_sub(1) {
+                let lambert = (255.0 * illumination_with(data, width, x, y, cellsize, azimuth_rad, altitude_rad, algo)).clamp(0.0, 255.0);
+                // NaN cells stay NaN and end up black
+                let intensity = ambient * 255.0 + (1.0 - ambient) * lambert;
+                row[x as usize] = intensity.clamp(0.0, 255.0) as u8;
+            }
+            progress.inc(1);
+        });
+    progress.finish();
+
+    GrayImage::from_raw(width, height, shaded).expect("buffer matches the image size")
+}
+
+/// Darkens a color map by a hillshade.
+/// # Arguments
+/// * `color` - The colored image, e.g. from `rgb`.
+/// * `shade` - A hillshade of the same size, e.g. from `hillshade_gray`.
+/// # Returns
+/// * The colors multiplied by `shade / 255`, opaque. The one-pixel border, where the hillshade is
+///   not defined, repeats the nearest interior pixel so adjacent tiles join without a seam.
+///
+/// Computing the shade once and blending it with several color maps avoids shading the terrain again.
+pub fn blend_hillshade(color: &RgbaImage, shade: &GrayImage) -> RgbaImage {
+    let (width, height) = shade.dimensions();
+    let mut blended = RgbaImage::from_fn(width, height, |x, y| {
+        let pixel_value = shade.get_pixel(x, y)[0] as f32;
+        let color = color.get_pixel(x, y);
+        let r  = (color[0] as f32 * pixel_value / 255.0) as u8;
+        let g  = (color[1] as f32 * pixel_value / 255.0) as u8;
+        let b  = (color[2] as f32 * pixel_value / 255.0) as u8;
+        Rgba([r, g, b, 255])
+    });
+    extend_border(&mut blended);
+    blended
+}
+
+/// Copies the nearest interior pixel onto the one-pixel border of an image (edge extension).
+/// Images without interior pixels, narrower or shorter than 3, are left as they are.
+fn extend_border(image: &mut RgbaImage) {
+    let (width, height) = image.dimensions();
+    if width < 3 || height < 3 {
+        return;
+    }
+    for y in 0..height {
+        for x in 0..width {
+            if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+                let nearest = *image.get_pixel(x.clamp(1, width - 2), y.clamp(1, height - 2));
+                image.put_pixel(x, y, nearest);
+            }
+        }
+    }
+}
+
+/// Generates hillshade images (grayscale and RGB) from elevation data.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `colored_image` - A `RgbaImage` object representing the colored image.
+/// * `width` - The width of the image.
+/// * `height` - The height of the image.
+/// * `cellsize` - The `(dx, dy)` size of each cell in the elevation data, see `AscHeader::cell_dims`.
+/// * `azimuth` - The azimuth angle for the light source.       
+/// * `altitude` - The altitude angle for the light source.
+/// * `ambient` - Share of ambient light, see `hillshade_gray`.
+/// # Returns     
+/// * A tuple containing two images: the grayscale hillshade image (`hillshade_gray`) and the RGB
+///   hillshade image (`blend_hillshade` of the colored image).
+#[allow(clippy::too_many_arguments)]
+pub fn hill_shading(data: &Vec<f32>, colored_image:RgbaImage, width: u32, height: u32, cellsize: (f32, f32), azimuth: f32, altitude: f32, ambient: f32) -> (GrayImage, RgbaImage) {
+    let shaded_image = hillshade_gray(data, width, height, cellsize, azimuth, altitude, ambient);
+    let shaded_image_rgb = blend_hillshade(&colored_image, &shaded_image);
+    (shaded_image, shaded_image_rgb)
+}
+
+pub fn draw_vector_field(image: &mut RgbaImage, gradients: &Vec<(f32, f32)>, width: u32, height: u32) {
+    draw_vector_field_with(image, gradients, width, height, false)
+}
+
+/// Same as `draw_vector_field`; with `antialiased`, the arrows are drawn with anti-aliased lines
+/// (Xiaolin Wu's algorithm) blended into the image, which reads much better in print.
+pub fn draw_vector_field_with(image: &mut RgbaImage, gradients: &Vec<(f32, f32)>, width: u32, height: u32, antialiased: bool) {
+    let line = |image: &mut RgbaImage, start: (f32, f32), end: (f32, f32), color: Rgba<u8>| {
+        if antialiased {
+            let (start, end) = ((start.0.round() as i32, start.1.round() as i32), (end.0.round() as i32, end.1.round() as i32));
+            draw_antialiased_line_segment_mut(image, start, end, color, interpolate);
+        } else {
+            draw_line_segment_mut(image, start, end, color);
+        }
+    };
+    let arrow_color = Rgba([255, 255, 255, 255]); // Red color
+    let step = 30;  // ⬆ Increase spacing (fewer arrows)
+    let arrow_length = step as f32 * 0.8; // ⬆ Make arrows bigger
+    let arrowhead_size = step as f32 * 0.5; // ⬆ Increase arrowhead size
+
+    for y in (0..height).step_by(step) {
+        for x in (0..width).step_by(step) {
+            let idx = (y * width + x) as usize;
+
+            let (dx, dy) = gradients[idx];
+            let norm = (dx.powi(2) + dy.powi(2)).sqrt();
+
+            if norm > 0.0 {
+                let scale = arrow_length / norm; // Normalize arrow size
+                let end_x = x as f32 + scale * dx;
+                let end_y = y as f32 + scale * dy;
+
+                // Draw the main arrow line
+                line(image, (x as f32, y as f32), (end_x, end_y), arrow_color);
+
+                // Compute arrowhead directions (-30° and +30°)
+                let angle = dy.atan2(dx); // Compute direction
+                let left_angle = angle + (PI / 6.0);  // +30°
+                let right_angle = angle - (PI / 6.0); // -30°
+
+                let left_x = end_x - arrowhead_size * left_angle.cos();
+                let left_y = end_y - arrowhead_size * left_angle.sin();
+                let right_x = end_x - arrowhead_size * right_angle.cos();
+                let right_y = end_y - arrowhead_size * right_angle.sin();
+
+                // Draw the arrowhead lines
+                line(image, (end_x, end_y), (left_x, left_y), arrow_color);
+                line(image, (end_x, end_y), (right_x, right_y), arrow_color);
+            }
+        }
+    }
+}
+pub fn compute_gradients(data: &Vec<f32>, width: u32, height: u32, window_size: u32) -> Vec<(f32, f32)> {
+    let mut gradients = vec![(0.0, 0.0); (width * height) as usize];
+    
+    // Ensure window_size is odd for symmetry
+    let half_window = window_size / 2;
+    
+    for y in half_window..(height - half_window) {
+        for x in half_window..(width - half_window) {
+            let idx = (y * width + x) as usize;
+            
+            // Compute dz/dx using the specified window size
+            let mut dz_dx = 0.0;
+            for offset in 1..=half_window as i32 {
+                // Positive contribution from left points
+                dz_dx += data[(y * width + (x as i32 - offset) as u32) as usize];
+                // Negative contribution from right points
+                dz_dx -= data[(y * width + (x as i32 + offset) as u32) as usize];
+            }
+            dz_dx /= half_window as f32;
+            
+            // Compute dz/dy using the specified window size
+            let mut dz_dy = 0.0;
+            for offset in 1..=half_window as i32 {
+                // Positive contribution from top points
+                dz_dy += data[((y as i32 - offset) as u32 * width + x) as usize];
+                // Negative contribution from bottom points
+                dz_dy -= data[((y as i32 + offset) as u32 * width + x) as usize];
+            }
+            dz_dy /= half_window as f32;
+            
+            gradients[idx] = (dz_dx, dz_dy);
+        }
+    }
+    
+    gradients
+}
+
+/// Computes the elevation gradient of every cell with the 3x3 Sobel kernels of `hill_shading`.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `cellsize` - The `(dx, dy)` size of each cell in the elevation data.
+/// # Returns
+/// * The `(dz/dx, dz/dy)` of every cell in elevation per distance, pointing uphill with `y` growing
+///   downward. Unlike `compute_gradients` its magnitude is the slope, whatever the resolution.
+///   Border cells are `(0, 0)` and cells next to NoData are NaN, so no arrow is drawn for them.
+pub fn compute_gradients_sobel(data: &Vec<f32>, width: u32, height: u32, cellsize: (f32, f32)) -> Vec<(f32, f32)> {
+    let mut gradients = vec![(0.0, 0.0); (width * height) as usize];
+    for y in 1..height.saturating_sub(1) {
+        for x in 1..width.saturating_sub(1) {
+            gradients[(y * width + x) as usize] = sobel_gradient(data, width, x, y, cellsize);
+        }
+    }
+    gradients
+}
+
+/// Renders the magnitude `sqrt(dx² + dy²)` of a gradient field as a grayscale slope shading.
+/// # Arguments
+/// * `gradients` - The `(dx, dy)` of every cell, from `compute_gradients` or `compute_gradients_sobel`.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// # Returns
+/// * A `GrayImage` scaled so the steepest cell is 255. NaN and zero gradients are black.
+pub fn gradient_magnitude_image(gradients: &Vec<(f32, f32)>, width: u32, height: u32) -> GrayImage {
+    let magnitudes: Vec<f32> = gradients.iter()
+        .map(|&(dx, dy)| (dx.powi(2) + dy.powi(2)).sqrt())
+        .map(|m| if m.is_nan() {0.0} else {m})
+        .collect();
+    let peak = magnitudes.iter().cloned().fold(0.0f32, f32::max);
+    let mut image = GrayImage::new(width, height);
+    if peak > 0.0 {
+        for (pixel, m) in image.pixels_mut().zip(magnitudes) {
+            pixel.0[0] = (m / peak * 255.0).round() as u8;
+        }
+    }
+    image
+}
+
+
+#[cfg(test)]
+mod tests {
+    use imageproc::gradients;
+
+    use super::*;
+    use std::fs;
+    // use std::io::{BufReader, BufRead};
+
+    fn create_dummy_asc_file(content: &str) -> String {
+        let temp_dir = std::env::temp_dir();
+        let file_path = temp_dir.join("dummy.asc");
+        fs::write(&file_path, content).expect("Failed to write dummy ASC file");
+        file_path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    /// Test the read_file function 
+    /// It creates a dummy ASC file and checks if the content is read correctly.
+    /// It also cleans up the dummy file after the test.
+
+    fn test_read_file_success() {
+        let content = "This is a test file.";
+        let file_path = create_dummy_asc_file(content);
+        let result = read_file(&file_path);
+        assert_eq!(result, content);
+        fs::remove_file(&file_path).unwrap();
+    }
+
+    #[test]
+    /// Given a know data set, it checks if the asc_to_image function parses the data correctly.
+    fn test_asc_to_image_valid() {
+        let content = "ncols 5\nnrows 2\nxllcorner 0\nyllcorner 0\ncellsize 1\nnodata_value -9999\n1 2 3 4 5\n6 7 8 9 10\n";
+        let result = asc_to_image(content.to_string());
+        assert!(result.is_ok());
+        let (data, width, height, cellsize) = result.unwrap();
+        assert_eq!(width, 5);
+        assert_eq!(height, 2);
+        assert_eq!(data.len(), 10);
+        assert_eq!(data, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0]);
+    }
+
+    #[test]
+    /// It checks that asc_to_image handle the nodata_value correctly.
+    fn test_asc_to_image_with_nodata() {
+        let content = "ncols 3\nnrows 2\nxllcorner 0\nyllcorner 0\ncellsize 1\nnodata_value -9999\n1 2 -9999\n-9999 5 6\n";
+        let result = asc_to_image(content.to_string());
+        assert!(result.is_ok());
+        let (data, width, height, cellsize) = result.unwrap();
+        assert_eq!(width, 3);
+        assert_eq!(height, 2);
+        assert_eq!(data.len(), 6);
+        assert!(data[2].is_nan());
+        assert!(data[3].is_nan());
+        assert_eq!(data[0], 1.0);
+        assert_eq!(data[1], 2.0);
+        assert_eq!(data[4], 5.0);
+        assert_eq!(data[5], 6.0);
+    }
+
+    #[test]
+    /// It checks that header keys are matched whatever their case, as ArcGIS writes `NODATA_value`.
+    fn test_asc_to_image_mixed_case_keys() {
+        let content = "NCOLS 3\nNRows 2\nXLLCORNER 10\nyllCenter 20\nCellSize 2\nNODATA_value -9999\n1 2 -9999\n-9999 5 6\n";
+        let header = parse_header(content).unwrap();
+        assert_eq!((header.ncols, header.nrows, header.cellsize, header.nodata_value), (3, 2, Some(2.0), Some(-9999.0)));
+        assert_eq!((header.xllcorner, header.yllcorner), (10.0, 19.0));
+        let (data, width, height, cellsize) = asc_to_image(content.to_string()).unwrap();
+        assert_eq!((width, height, cellsize), (3, 2, 2.0));
+        assert!(data[2].is_nan() && data[3].is_nan());
+        assert_eq!((data[0], data[1], data[4], data[5]), (1.0, 2.0, 5.0, 6.0));
+    }
+
+    #[test]
+    /// It checks that a `dx`/`dy` header is read with its seven lines, and that the slope and hillshade use both sizes.
+    fn test_asc_to_image_dx_dy() {
+        let content = "ncols 3\nnrows 3\nxllcorner 0\nyllcenter 0\ndx 1\ndy 2\nnodata_value -9999\n0 0 0\n2 2 2\n4 4 4\n";
+        let header = parse_header(content).unwrap();
+        assert_eq!((header.header_lines, header.cell_dims(), header.yllcorner), (7, (1.0, 2.0), -1.0));
+        let (data, _, _, cellsize) = asc_to_image(content.to_string()).unwrap();
+        assert_eq!((data.len(), cellsize), (9, 1.0));
+        assert_eq!(crate::grid::Grid::from_asc(content.to_string()).unwrap().cellsize_y, 2.0);
+
+        // the rows rise 2 per row, a 45° slope with 2 m rows instead of 63.4° with square cells
+        let slope = crate::relief::slope_degrees(&data, 3, 3, header.cell_dims());
+        let square = crate::relief::slope_degrees(&data, 3, 3, (1.0, 1.0));
+        assert!((slope[4] - 45.0).abs() < 1e-4 && (square[4] - 63.43).abs() < 0.01, "{} {}", slope[4], square[4]);
+        let colors = RgbaImage::new(3, 3);
+        let (shade, _) = hill_shading(&data, colors.clone(), 3, 3, header.cell_dims(), 315.0, 45.0, 0.0);
+        let (shade_square, _) = hill_shading(&data, colors, 3, 3, (1.0, 1.0), 315.0, 45.0, 0.0);
+        assert_ne!(shade.get_pixel(1, 1), shade_square.get_pixel(1, 1));
+    }
+
+    #[test]
+    /// It checks that parse_header keeps the corner coordinates and converts center coordinates.
+    fn test_parse_header_corners() {
+        let content = "ncols 2\nnrows 3\nxllcorner 925000\nyllcorner 6224000.5\ncellsize 0.5\nnodata_value -9999\n1 2\n3 4\n5 6\n";
+        let header = parse_header(content).unwrap();
+        assert_eq!((header.ncols, header.nrows), (2, 3));
+        assert_eq!(header.xllcorner, 925000.0);
+        assert_eq!(header.yllcorner, 6224000.5);
+        assert_eq!(header.cellsize, Some(0.5));
+        assert_eq!(header.nodata_value, Some(-9999.0));
+        assert_eq!(header.header_lines, 6);
+
+        let content = "ncols 2\nnrows 3\nxllcenter 10\nyllcenter 20\ncellsize 2\nnodata_value -9999\n";
+        let header = parse_header(content).unwrap();
+        assert_eq!(header.xllcorner, 9.0);
+        assert_eq!(header.yllcorner, 19.0);
+    }
+
+    #[test]
+    /// It checks that a file saved on Windows, with a BOM and CRLF line endings, parses like a Unix file.
+    fn test_asc_to_image_crlf_bom() {
+        let content = "\u{feff}ncols 3\r\nnrows 2\r\nxllcorner 0\r\nyllcorner 0\r\ncellsize 5\r\nnodata_value -9999\r\n1 2 3\r\n4 -9999 6\r\n";
+        let (data, width, height, cellsize) = asc_to_image(content.to_string()).unwrap();
+        assert_eq!((width, height, cellsize), (3, 2, 5.0));
+        assert_eq!(data.len(), 6);
+        assert_eq!(data[0], 1.0);
+        assert!(data[4].is_nan());
+        assert_eq!(data[5], 6.0);
+    }
+
+    #[test]
+    /// It checks that elevations in scientific notation are read.
+    fn test_asc_to_image_scientific_notation() {
+        let content = "ncols 3\nnrows 1\nxllcorner 0\nyllcorner 0\ncellsize 1\nnodata_value -9999\n1.2E3 -4.5e-1 7e0\n";
+        let (data, _, _, _) = asc_to_image(content.to_string()).unwrap();
+        assert_eq!(data, vec![1200.0, -0.45, 7.0]);
+    }
+
+    #[test]
+    /// It checks comma decimal separators: read with the option, counted as skipped without it.
+    fn test_asc_to_image_decimal_comma() {
+        let content = "ncols 3\nnrows 1\nxllcorner 0\nyllcorner 0\ncellsize 0,5\nnodata_value -9999\n1,25 2 3,5E1\n";
+        let options = ParseOptions { decimal_comma: true, ..Default::default() };
+        let (data, _, _, cellsize, skipped) = asc_to_image_with(content.to_string(), &options).unwrap();
+        assert_eq!(data, vec![1.25, 2.0, 35.0]);
+        assert_eq!(cellsize, 0.5);
+        assert_eq!(skipped, 0);
+
+        let content = content.replace("0,5", "0.5");
+        let (data, _, _, _, skipped) = asc_to_image_with(content, &ParseOptions::default()).unwrap();
+        assert_eq!(data, vec![2.0]);
+        assert_eq!(skipped, 2);
+    }
+
+    #[test]
+    /// It checks that the cellsize option replaces the header's cell sizes and must be positive.
+    fn test_parse_header_cellsize_override() {
+        let content = "ncols 2\nnrows 2\nxllcorner 0\nyllcorner 0\ndx 2\ndy 3\n1 2\n3 4\n";
+        let header = parse_header_with(content, &ParseOptions { cellsize: Some(5.0), ..Default::default() }).unwrap();
+        assert_eq!(header.cell_dims(), (5.0, 5.0));
+
+        for cellsize in [0.0, -1.0, f32::NAN] {
+            assert!(parse_header_with(content, &ParseOptions { cellsize: Some(cellsize), ..Default::default() }).is_err());
+        }
+    }
+
+    #[test]
+    /// It checks that the override value is masked next to the nodata value declared in the header.
+    fn test_asc_to_image_nodata_override() {
+        let content = "ncols 4\nnrows 1\nxllcorner 0\nyllcorner 0\ncellsize 1\nnodata_value -9999\n-32768 -9999 12.5 -32767\n";
+        let options = ParseOptions { nodata_override: Some(-32768.0), ..Default::default() };
+        let (data, _, _, _, _) = asc_to_image_with(content.to_string(), &options).unwrap();
+        assert!(data[0].is_nan() && data[1].is_nan());
+        assert_eq!(&data[2..], &[12.5, -32767.0]);
+
+        let (data, _, _, _) = asc_to_image(content.to_string()).unwrap();
+        assert_eq!(data[0], -32768.0);
+    }
+
+    #[test]
+    /// It checks that the function returns an error when the header is invalid.
+    fn test_asc_to_image_invalid_header() {
+        let content = "ncols abc\nnrows 2\nxllcorner 0\nyllcorner 0\ncellsize 1\nnodata_value -9999\n1 2 3\n4 5 6\n";
+        let result = asc_to_image(content.to_string());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    /// It checks if the fucntion data_to_grayscale maps the data correctly to grayscale.
+    fn test_data_to_grayscale_basic() {
+        let data = vec![0.0, 1.0, 2.0];
+        let width = 3;
+        let height = 1;
+        let image = data_to_grayscale(data, width, height, None, None, 1.0).unwrap();
+        assert_eq!(image.width(), width);
+        assert_eq!(image.height(), height);
+        assert_eq!(image.get_pixel(0, 0), &Luma([0]));
+        assert_eq!(image.get_pixel(1, 0), &Luma([127])); 
+        assert_eq!(image.get_pixel(2, 0), &Luma([255]));
+    }
+
+    #[test]
+    /// It checks how the function data_to_grayscale handles NaN values.
+    /// It should map NaN values to the minimum value of the grayscale range.
+    fn test_data_to_grayscale_with_nan() {
+        let data = vec![0.0, f32::NAN, 2.0];
+        let width = 3;
+        let height = 1;
+        let image = data_to_grayscale(data, width, height, None, None, 1.0).unwrap();
+        assert_eq!(image.width(), width);
+        assert_eq!(image.height(), height);
+        assert_eq!(image.get_pixel(0, 0), &Luma([0]));
+        assert_eq!(image.get_pixel(1, 0), &Luma([0])); // NaN should result in min value
+        assert_eq!(image.get_pixel(2, 0), &Luma([255]));
+    }
+
+    #[test]
+    /// It checks if the function data_to_grayscale handles constant values correctly.
+    fn test_data_to_grayscale_constant_value() {
+        let data = vec![5.0, 5.0, 5.0];
+        let width = 3;
+        let height = 1;
+        let image = data_to_grayscale(data, width, height, None, None, 1.0).unwrap();
+        assert_eq!(image.width(), width);
+        assert_eq!(image.height(), height);
+        assert_eq!(image.get_pixel(0, 0), &Luma([0]));
+        assert_eq!(image.get_pixel(1, 0), &Luma([0]));
+        assert_eq!(image.get_pixel(2, 0), &Luma([0]));
+    }
+
+    #[test]
+    /// It checks if the rgb function maps the data correctly to RGB image with width and height.
+    fn test_rgb_basic() {
+        let data = vec![0.0, 1.0, 2.0];
+        let width = 3;
+        let height = 1;
+        let image = rgb(data, width, height, None, None).unwrap();
+        assert_eq!(image.width(), width);
+        assert_eq!(image.height(), height);
+
+    }
+
+    #[test]
+    /// It checks if the rgb function handles NaN values correctly.
+    fn test_rgb_with_nan() {
+        let data = vec![0.0, f32::NAN, 2.0];
+        let width = 3;
+        let height = 1;
+        let image = rgb(data, width, height, None, None).unwrap();
+        assert_eq!(image.width(), width);
+        assert_eq!(image.height(), height);
+    }
+
+    #[test]
+    /// It checks that empty grids and grids whose size does not match the data are rejected.
+    fn test_image_conversion_dimension_errors() {
+        assert!(data_to_grayscale(Vec::new(), 0, 0, None, None, 1.0).is_err());
+        assert!(data_to_grayscale(vec![1.0, 2.0], 0, 2, None, None, 1.0).is_err());
+        assert!(data_to_grayscale(vec![1.0, 2.0, 3.0], 2, 2, None, None, 1.0).is_err());
+        assert!(rgb(Vec::new(), 3, 1, None, None).is_err());
+        assert!(rgb(vec![1.0, 2.0, 3.0, 4.0], 3, 1, None, None).is_err());
+    }
+
+    #[test]
+    /// It checks that a fully masked grid is reported as an error by both image conversions.
+    fn test_image_conversion_all_nan() {
+        let data = vec![f32::NAN; 4];
+        let gray_error = data_to_grayscale(data.clone(), 2, 2, None, None, 1.0).unwrap_err();
+        let rgb_error = rgb(data, 2, 2, None, None).unwrap_err();
+        assert_eq!(gray_error.to_string(), NO_VALID_DATA);
+        assert_eq!(rgb_error.to_string(), NO_VALID_DATA);
+        assert_eq!(elevation_range(&[f32::NAN, 3.0, -1.0]), Some((-1.0, 3.0)));
+    }
+
+    #[test]
+    /// It checks that an interior NaN cell is transparent while its neighbors stay opaque.
+    fn test_rgb_nan_transparent() {
+        let data = vec![
+            1.0, 2.0, 3.0,
+            4.0, f32::NAN, 6.0,
+            7.0, 8.0, 9.0,
+        ];
+        let image = rgb(data, 3, 3, None, None).unwrap();
+        assert_eq!(image.get_pixel(1, 1)[3], 0);
+        for (x, y) in [(0, 1), (2, 1), (1, 0), (1, 2)] {
+            assert_eq!(image.get_pixel(x, y)[3], 255);
+        }
+    }
+
+    #[test]
+    /// It checks that a configured NoData color is used for NaN cells in both images.
+    fn test_nodata_color() {
+        let data = vec![1.0, f32::NAN, 3.0];
+        let magenta = Rgba([255, 0, 255, 255]);
+        let image = rgb(data.clone(), 3, 1, Some(magenta), None).unwrap();
+        assert_eq!(image.get_pixel(1, 0), &magenta);
+        assert_ne!(image.get_pixel(0, 0), &magenta);
+
+        let gray = data_to_grayscale(data, 3, 1, Some(Luma([200])), None, 1.0).unwrap();
+        assert_eq!(gray.get_pixel(1, 0), &Luma([200]));
+        assert_eq!(gray.get_pixel(2, 0), &Luma([255]));
+    }
+
+    #[test]
+    /// It checks that two tiles with different ranges get the same colors for the same elevation under a fixed range.
+    fn test_fixed_value_range() {
+        let low_tile = vec![100.0, 150.0, 200.0];
+        let high_tile = vec![150.0, 300.0, 400.0];
+        let range = Some((100.0, 300.0));
+
+        let low = rgb(low_tile.clone(), 3, 1, None, range).unwrap();
+        let high = rgb(high_tile.clone(), 3, 1, None, range).unwrap();
+        assert_eq!(low.get_pixel(1, 0), high.get_pixel(0, 0)); // both 150
+        // 300 and the out-of-range 400 both clamp to the top of the gradient
+        assert_eq!(high.get_pixel(1, 0), high.get_pixel(2, 0));
+        // with their own ranges the same elevation gets different colors
+        let own_low = rgb(low_tile.clone(), 3, 1, None, None).unwrap();
+        let own_high = rgb(high_tile.clone(), 3, 1, None, None).unwrap();
+        assert_ne!(own_low.get_pixel(1, 0), own_high.get_pixel(0, 0));
+
+        let low_gray = data_to_grayscale(low_tile, 3, 1, None, range, 1.0).unwrap();
+        let high_gray = data_to_grayscale(high_tile, 3, 1, None, range, 1.0).unwrap();
+        assert_eq!(low_gray.get_pixel(1, 0), high_gray.get_pixel(0, 0));
+        assert_eq!(high_gray.get_pixel(2, 0), &Luma([255]));
+    }
+
+    #[test]
+    /// It checks if the hill_shading maps the data correctly to gray scale and RGB image with width and height.
+    fn test_hill_shading_basic() {
+        let data = vec![
+            1.0, 1.0, 1.0,
+            1.0, 2.0, 1.0,
+            1.0, 1.0, 1.0,
+        ];
+        let width = 3;
+        let height = 3;
+        let cellsize = 1.0;
+        let colored_image = RgbaImage::new(width, height);
+        let (shaded_gray, shaded_rgb) = hill_shading(&data, colored_image, width, height, (cellsize, cellsize), 315.0, 45.0, 0.0);
+        assert_eq!(shaded_gray.width(), width);
+        assert_eq!(shaded_gray.height(), height);
+        assert_eq!(shaded_rgb.width(), width);
+        assert_eq!(shaded_rgb.height(), height);
+
+    }
+
+    #[test]
+    /// Checks if the hill_shading function handles NaN values correctly.
+    fn test_hill_shading_with_nan() {
+        let data = vec![
+            1.0, 1.0, 1.0,
+            1.0, f32::NAN, 1.0,
+            1.0, 1.0, 1.0,
+        ];
+        let width = 3;
+        let height = 3;
+        let cellsize = 1.0;
+        let colored_image = RgbaImage::new(width, height); // Dummy colored image
+        let (shaded_gray, shaded_rgb) = hill_shading(&data, colored_image, width, height, (cellsize, cellsize), 315.0, 45.0, 0.0);
+        assert_eq!(shaded_gray.width(), width);
+        assert_eq!(shaded_gray.height(), height);
+        assert_eq!(shaded_rgb.width(), width);
+        assert_eq!(shaded_rgb.height(), height);
+        // Check if the center pixel (affected by NaN neighbor) is black
+        // assert_eq!(shaded_gray.get_pixel(1, 1), &Luma([0]));
+        assert_eq!(shaded_rgb.get_pixel(1, 1), &Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    /// It checks if the hill_shading function handles edge cases correctly.
+    fn test_hill_shading_edge_cases() {
+        let data = vec![
+            1.0, 2.0,
+            3.0, 4.0,
+        ];
+        let width = 2;
+        let height = 2;
+        let cellsize = 1.0;
+        let colored_image = RgbaImage::new(width, height);
+        let (shaded_gray, shaded_rgb) = hill_shading(&data, colored_image, width, height, (cellsize, cellsize), 315.0, 45.0, 0.0);
+        assert_eq!(shaded_gray.width(), width);
+        assert_eq!(shaded_gray.height(), height);
+        assert_eq!(shaded_rgb.width(), width);
+        assert_eq!(shaded_rgb.height(), height);
+        assert_eq!(shaded_gray.get_pixel(0, 0), &Luma([0]));
+        assert_eq!(shaded_gray.get_pixel(1, 0), &Luma([0]));
+        assert_eq!(shaded_gray.get_pixel(0, 1), &Luma([0]));
+        assert_eq!(shaded_gray.get_pixel(1, 1), &Luma([0]));
+        // no interior cell can be shaded, but the RGB image is opaque rather than a transparent hole
+        assert_eq!(shaded_rgb.get_pixel(1, 0), &Rgba([0, 0, 0, 255]));
+        assert_eq!(shaded_rgb.get_pixel(0, 0), &Rgba([0, 0, 0, 255]));
+        assert_eq!(shaded_rgb.get_pixel(0, 1), &Rgba([0, 0, 0, 255]));
+        assert_eq!(shaded_rgb.get_pixel(1, 1), &Rgba([0, 0, 0, 255]));
+
+        // on larger grids, the border of the RGB image repeats the nearest interior pixel
+        let data: Vec<f32> = (0..20).map(|i| ((i % 5) * (i / 5)) as f32).collect();
+        let colored_image = RgbaImage::from_pixel(5, 4, Rgba([255, 255, 255, 255]));
+        let (_, shaded_rgb) = hill_shading(&data, colored_image, 5, 4, (cellsize, cellsize), 315.0, 45.0, 0.0);
+        assert_eq!(shaded_rgb.get_pixel(0, 0), shaded_rgb.get_pixel(1, 1));
+        assert_eq!(shaded_rgb.get_pixel(2, 3), shaded_rgb.get_pixel(2, 2));
+        assert_eq!(shaded_rgb.get_pixel(4, 1), shaded_rgb.get_pixel(3, 1));
+        assert!(shaded_rgb.pixels().all(|p| p[3] == 255));
+    }
+
+    /// Serial reference of `hill_shading`, as it was before the rows were shaded in parallel.
+    fn hill_shading_serial(data: &[f32], colored_image: &RgbaImage, width: u32, height: u32, cellsize: f32, azimuth: f32, altitude: f32) -> (GrayImage, RgbaImage) {
+        let mut shaded_image = GrayImage::new(width, height);
+        let mut shaded_image_rgb = RgbaImage::new(width, height);
+        let (azimuth_rad, altitude_rad) = (azimuth.to_radians(), altitude.to_radians());
+        for y in 1..height - 1 {
+            for x in 1..width - 1 {
+                let z = |dx: i32, dy: i32| data[((y as i32 + dy) * width as i32 + (x as i32 + dx)) as usize];
+                let dz_dx = ((z(1, -1) + 2.0 * z(1, 0) + z(1, 1)) - (z(-1, -1) + 2.0 * z(-1, 0) + z(-1, 1))) / (8.0 * cellsize);
+                let dz_dy = ((z(-1, 1) + 2.0 * z(0, 1) + z(1, 1)) - (z(-1, -1) + 2.0 * z(0, -1) + z(1, -1))) / (8.0 * cellsize);
+                let slope = (dz_dx.powi(2) + dz_dy.powi(2)).sqrt().atan();
+                let aspect = dz_dy.atan2(dz_dx);
+                let intensity = 255.0 * (altitude_rad.cos() * slope.cos() + altitude_rad.sin() * slope.sin() * (azimuth_rad - aspect).cos());
+                let pixel_value = intensity.clamp(0.0, 255.0) as u8;
+                shaded_image.put_pixel(x, y, Luma([pixel_value]));
+                let color = colored_image.get_pixel(x, y);
+                let shade = |c: u8| (c as f32 * pixel_value as f32 / 255.0) as u8;
+                shaded_image_rgb.put_pixel(x, y, Rgba([shade(color[0]), shade(color[1]), shade(color[2]), 255]));
+            }
+        }
+        extend_border(&mut shaded_image_rgb);
+        (shaded_image, shaded_image_rgb)
+    }
+
+    #[test]
+    /// It checks that data_to_grayscale16 maps the midpoint near 32767 and tells apart values that collapse in 8 bits.
+    fn test_data_to_grayscale16() {
+        let data = vec![0.0, 50.0, 100.0, 50.1, f32::NAN];
+        let image = data_to_grayscale16(&data, 5, 1);
+        assert_eq!(image.get_pixel(0, 0), &Luma([0]));
+        assert!((image.get_pixel(1, 0)[0] as i32 - 32767).abs() <= 1);
+        assert_eq!(image.get_pixel(2, 0), &Luma([65535]));
+        assert_eq!(image.get_pixel(4, 0), &Luma([0]));
+
+        // 50.0 and 50.1 share an 8-bit level but not a 16-bit one
+        let gray = data_to_grayscale(data.clone(), 5, 1, None, None, 1.0).unwrap();
+        assert_eq!(gray.get_pixel(1, 0), gray.get_pixel(3, 0));
+        assert_ne!(image.get_pixel(1, 0), image.get_pixel(3, 0));
+    }
+
+    #[test]
+    /// It checks that the parallel hill_shading matches a serial reference pixel for pixel on a 1000x1000 grid.
+    fn test_hill_shading_parallel_matches_serial() {
+        let (width, height) = (1000, 1000);
+        let data: Vec<f32> = (0..width * height)
+            .map(|i| {
+                let (x, y) = ((i % width) as f32, (i / width) as f32);
+                (x / 37.0).sin() * 40.0 + (y / 23.0).cos() * 25.0 + (x * y).sqrt() * 0.1
+            })
+            .collect();
+        let colored_image = rgb(data.clone(), width, height, None, None).unwrap();
+        let (gray, color) = hill_shading(&data, colored_image.clone(), width, height, (2.0, 2.0), 315.0, 45.0, 0.0);
+        let (gray_serial, color_serial) = hill_shading_serial(&data, &colored_image, width, height, 2.0, 315.0, 45.0);
+        assert!(gray == gray_serial);
+        assert!(color == color_serial);
+    }
+
+    #[test]
+    /// It checks that ambient light lifts the shadowed slopes, and that 0 keeps the plain shading.
+    fn test_hill_shading_ambient() {
+        let (width, height) = (20, 20);
+        // a ridge with a slope facing the light and a slope in full shadow
+        let data: Vec<f32> = (0..width * height).map(|i| 10.0 - 4.0 * ((i % width) as f32 - 10.0).abs()).collect();
+        let colored_image = RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 255]));
+        let darkest = |ambient: f32| {
+            let (gray, _) = hill_shading(&data, colored_image.clone(), width, height, (1.0, 1.0), 315.0, 45.0, ambient);
+            (1..height - 1).flat_map(|y| (1..width - 1).map(move |x| (x, y))).map(|(x, y)| gray.get_pixel(x, y)[0]).min().unwrap()
+        };
+        assert_eq!(darkest(0.0), 0);
+        assert!(darkest(0.3) >= 76, "{}", darkest(0.3));
+    }
+
+    #[test]
+    /// It checks that the reversed gradient colors the lowest cell like the forward gradient colors the highest one.
+    fn test_rgb_reverse_gradient() {
+        let data = vec![10.0, 20.0, 30.0, 40.0];
+        let viridis = color::gradient_by_name("viridis").unwrap();
+        let forward = rgb_with(data.clone(), 4, 1, None, None, viridis.as_ref(), false).unwrap();
+        let reversed = rgb_with(data.clone(), 4, 1, None, None, viridis.as_ref(), true).unwrap();
+        assert_eq!(reversed.get_pixel(0, 0), forward.get_pixel(3, 0));
+        assert_eq!(reversed.get_pixel(3, 0), forward.get_pixel(0, 0));
+        assert_ne!(forward.get_pixel(0, 0), forward.get_pixel(3, 0));
+        assert_eq!(rgb(data.clone(), 4, 1, None, None).unwrap(), rgb_with(data, 4, 1, None, None, &preset::turbo(), false).unwrap());
+    }
+
+    #[test]
+    /// It checks that anti-aliased arrows blend partial intensities, while aliased ones are pure black or white.
+    fn test_draw_vector_field_antialiased() {
+        let (width, height) = (61, 61);
+        let gradients = vec![(1.0, 0.6); (width * height) as usize];
+        let levels = |antialiased: bool| {
+            let mut image = RgbaImage::from_pixel(width, height, Rgba([0, 0, 0, 255]));
+            draw_vector_field_with(&mut image, &gradients, width, height, antialiased);
+            image.pixels().map(|p| p[0]).collect::<std::collections::HashSet<u8>>()
+        };
+        let aliased = levels(false);
+        assert!(aliased.iter().all(|v| *v == 0 || *v == 255));
+        assert!(aliased.contains(&255));
+        assert!(levels(true).iter().any(|v| *v > 0 && *v < 255));
+    }
+
+    #[test]
+    /// It checks that percentile normalization ignores a single giant outlier that squashes min-max to black.
+    fn test_percentile_normalization_outlier() {
+        let mut data: Vec<f32> = (0..100).map(|v| v as f32).collect();
+        data[57] = 1.0e6;
+        let spread = |range: Option<(f32, f32)>| {
+            let gray = data_to_grayscale(data.clone(), 10, 10, None, range, 1.0).unwrap();
+            gray.pixels().map(|p| p[0]).collect::<std::collections::HashSet<u8>>().len()
+        };
+        let (low, high) = NormMode::Percentile(2.0, 98.0).value_range(&data).unwrap();
+        assert!((low - 2.0).abs() < 0.1 && high < 100.0, "{} {}", low, high);
+        assert!(spread(NormMode::MinMax.value_range(&data)) <= 2);
+        assert!(spread(Some((low, high))) > 80);
+
+        let (low, high) = NormMode::StdDev(2.0).value_range(&[1.0, 3.0, f32::NAN]).unwrap();
+        assert_eq!((low, high), (0.0, 4.0));
+        assert_eq!(NormMode::Percentile(2.0, 98.0).value_range(&[5.0; 4]), None);
+    }
+
+    #[test]
+    /// It checks that the Sobel gradient of a tilted plane is its slope, in elevation per distance.
+    fn test_compute_gradients_sobel_plane() {
+        let (width, height, cellsize) = (6, 5, (2.5, 2.0));
+        // rises 0.3 per meter to the east and 0.4 per meter to the south
+        let data: Vec<f32> = (0..width * height)
+            .map(|i| 0.3 * cellsize.0 * (i % width) as f32 + 0.4 * cellsize.1 * (i / width) as f32)
+            .collect();
+        let gradients = compute_gradients_sobel(&data, width, height, cellsize);
+        for y in 1..height - 1 {
+            for x in 1..width - 1 {
+                let (dx, dy) = gradients[(y * width + x) as usize];
+                assert!((dx - 0.3).abs() < 1e-5 && (dy - 0.4).abs() < 1e-5, "{} {}", dx, dy);
+                assert!(((dx.powi(2) + dy.powi(2)).sqrt() - 0.5).abs() < 1e-5);
+            }
+        }
+        assert_eq!(gradients[0], (0.0, 0.0));
+    }
+
+    #[test]
+    /// It checks that a uniform gradient field gives a uniform gray, with NaN and zero cells black.
+    fn test_gradient_magnitude_uniform() {
+        let (width, height) = (4, 3);
+        let mut gradients = vec![(3.0, -4.0); (width * height) as usize];
+        let image = gradient_magnitude_image(&gradients, width, height);
+        assert!(image.pixels().all(|p| p[0] == 255));
+
+        gradients[0] = (f32::NAN, 1.0);
+        gradients[1] = (0.0, 0.0);
+        let image = gradient_magnitude_image(&gradients, width, height);
+        assert_eq!((image.get_pixel(0, 0)[0], image.get_pixel(1, 0)[0]), (0, 0));
+        assert!(image.pixels().skip(2).all(|p| p[0] == 255));
+    }
+
+    #[test]
+    /// Streaming a file gives the same grid as parsing its content as a string, BOM and CRLF included.
+    fn test_streaming_matches_string_parser() {
+        let mut content = String::from("\u{feff}ncols 4\r\nnrows 3\r\nxllcorner 0\r\nyllcorner 0\r\ncellsize 2.5\r\nNODATA_value -9999\r\n");
+        content.push_str("1 2 3 4\r\n5 -9999 7 8\r\n9 10 x 11 12\r\n");
+        let path = std::env::temp_dir().join("dem_test_streaming.asc");
+        std::fs::write(&path, &content).unwrap();
+        let path = path.to_str().unwrap();
+
+        let (data, width, height, cellsize) = asc_to_image(content.clone()).unwrap();
+        let (streamed, streamed_width, streamed_height, streamed_cellsize) = asc_to_image_streaming(path).unwrap();
+        assert_eq!((streamed_width, streamed_height, streamed_cellsize), (width, height, cellsize));
+        assert_eq!(streamed.len(), 12);
+        assert!(streamed.iter().zip(data.iter()).all(|(a, b)| a == b || (a.is_nan() && b.is_nan())));
+
+        let (header, _, skipped) = read_asc_streaming(path, &ParseOptions::default()).unwrap();
+        assert_eq!(header, parse_header(&content).unwrap());
+        assert_eq!(skipped, asc_to_image_with(content, &ParseOptions::default()).unwrap().4);
+        assert_eq!(skipped, 1);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    /// A plane is shaded evenly at the flat-ground intensity, and matches the gray image of `hill_shading`.
+    fn test_hillshade_gray_plane() {
+        let (width, height) = (6, 5);
+        let data: Vec<f32> = vec![10.0; (width * height) as usize];
+        let shade = hillshade_gray(&data, width, height, (1.0, 1.0), 315.0, 45.0, 0.0);
+        let flat = (255.0 * 45.0f32.to_radians().cos()) as u8;
+        for (x, y, pixel) in shade.enumerate_pixels() {
+            let interior = x > 0 && y > 0 && x < width - 1 && y < height - 1;
+            assert_eq!(pixel[0], if interior { flat } else { 0 }, "({}, {})", x, y);
+        }
+        let (gray, _) = hill_shading(&data, RgbaImage::new(width, height), width, height, (1.0, 1.0), 315.0, 45.0, 0.0);
+        assert_eq!(gray, shade);
+    }
+
+    #[test]
+    /// Blending scales the colors by the shade, extends the interior onto the border and can reuse one shade for two color maps.
+    fn test_blend_hillshade() {
+        let (width, height) = (4, 3);
+        let shade = GrayImage::from_fn(width, height, |x, _| Luma([(x * 85) as u8]));
+        let white = RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 255]));
+        let red = RgbaImage::from_pixel(width, height, Rgba([200, 0, 0, 128]));
+
+        let gray = blend_hillshade(&white, &shade);
+        assert_eq!(gray.get_pixel(1, 1), &Rgba([85, 85, 85, 255]));
+        assert_eq!(gray.get_pixel(2, 1), &Rgba([170, 170, 170, 255]));
+        assert_eq!(gray.get_pixel(0, 1), &Rgba([85, 85, 85, 255]));
+        assert_eq!(gray.get_pixel(2, 0), &Rgba([170, 170, 170, 255]));
+        assert_eq!(blend_hillshade(&red, &shade).get_pixel(2, 1), &Rgba([133, 0, 0, 255]));
+    }
+
+    #[test]
+    /// A gamma of 2.2 brightens the midtones of a ramp while keeping its black and white ends.
+    fn test_data_to_grayscale_gamma() {
+        let data: Vec<f32> = (0..=10).map(|v| v as f32).collect();
+        let linear = data_to_grayscale(data.clone(), 11, 1, None, None, 1.0).unwrap();
+        let bright = data_to_grayscale(data, 11, 1, None, None, 2.2).unwrap();
+        assert_eq!(linear.get_pixel(5, 0)[0], 127);
+        assert_eq!(bright.get_pixel(5, 0)[0], (0.5f32.powf(1.0 / 2.2) * 255.0) as u8);
+        assert!((1..10).all(|x| bright.get_pixel(x, 0)[0] > linear.get_pixel(x, 0)[0]));
+        assert_eq!((bright.get_pixel(0, 0)[0], bright.get_pixel(10, 0)[0]), (0, 255));
+    }
+}
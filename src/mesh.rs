@@ -0,0 +1,126 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// Exports the elevation grid as a triangulated Wavefront OBJ mesh (Y up).
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `cellsize` - The size of each cell in the elevation data.
+/// * `path` - The `.obj` file to write.
+/// * `z_scale` - Vertical exaggeration applied to the elevations.
+///
+/// Every valid cell becomes a vertex at `(x * cellsize, elevation * z_scale, y * cellsize)` with a
+/// vertex normal, and every grid quad becomes two triangles. Quads touching a NaN cell are skipped.
+pub fn export_obj(data: &Vec<f32>, width: u32, height: u32, cellsize: f32, path: &str, z_scale: f32) -> Result<(), Box<dyn Error>> {
+    let mut out = BufWriter::new(File::create(path)?);
+    let (w, h) = (width as usize, height as usize);
+    let elevation = |x: usize, y: usize| data[y * w + x] * z_scale;
+    writeln!(out, "# DEM mesh {}x{} cells, cellsize {}, z scale {}", width, height, cellsize, z_scale)?;
+
+    // OBJ indices are 1-based; NaN cells get no vertex
+    let mut vertex_index = vec![0usize; w * h];
+    let mut count = 0;
+    for y in 0..h {
+        for x in 0..w {
+            let z = elevation(x, y);
+            if z.is_nan() {
+                continue;
+            }
+            count += 1;
+            vertex_index[y * w + x] = count;
+            writeln!(out, "v {} {} {}", x as f32 * cellsize, z, y as f32 * cellsize)?;
+        }
+    }
+
+    for y in 0..h {
+        for x in 0..w {
+            if vertex_index[y * w + x] == 0 {
+                continue;
+            }
+            let dz_dx = central_difference(|i| elevation(i, y), x, w, cellsize);
+            let dz_dy = central_difference(|i| elevation(x, i), y, h, cellsize);
+            let norm = (dz_dx * dz_dx + 1.0 + dz_dy * dz_dy).sqrt();
+            writeln!(out, "vn {} {} {}", -dz_dx / norm, 1.0 / norm, -dz_dy / norm)?;
+        }
+    }
+
+    for y in 0..h.saturating_sub(1) {
+        for x in 0..w.saturating_sub(1) {
+            let v00 = vertex_index[y * w + x];
+            let v10 = vertex_index[y * w + x + 1];
+            let v01 = vertex_index[(y + 1) * w + x];
+            let v11 = vertex_index[(y + 1) * w + x + 1];
+            if v00 == 0 || v10 == 0 || v01 == 0 || v11 == 0 {
+                continue;
+            }
+            // Counter-clockwise seen from above so the faces point up
+            writeln!(out, "f {0}//{0} {1}//{1} {2}//{2}", v00, v01, v10)?;
+            writeln!(out, "f {0}//{0} {1}//{1} {2}//{2}", v10, v01, v11)?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Slope along one axis, falling back to one-sided differences at the edges and next to NaN cells.
+fn central_difference(value: impl Fn(usize) -> f32, i: usize, len: usize, cellsize: f32) -> f32 {
+    let center = value(i);
+    let before = if i > 0 { value(i - 1) } else { f32::NAN };
+    let after = if i + 1 < len { value(i + 1) } else { f32::NAN };
+    match (before.is_nan(), after.is_nan()) {
+        (false, false) => (after - before) / (2.0 * cellsize),
+        (true, false) => (after - center) / cellsize,
+        (false, true) => (center - before) / cellsize,
+        (true, true) => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn count_lines(content: &str, prefix: &str) -> usize {
+        content.lines().filter(|line| line.starts_with(prefix)).count()
+    }
+
+    #[test]
+    /// A 3x3 grid has 9 vertices with normals and 2 triangles per each of its 4 quads.
+    fn test_export_obj_counts() {
+        let data = vec![
+            1.0, 2.0, 3.0,
+            2.0, 3.0, 4.0,
+            3.0, 4.0, 5.0,
+        ];
+        let path = std::env::temp_dir().join("dem_mesh_test.obj");
+        let path = path.to_str().unwrap();
+        export_obj(&data, 3, 3, 2.0, path, 1.0).unwrap();
+        let content = fs::read_to_string(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(count_lines(&content, "v "), 9);
+        assert_eq!(count_lines(&content, "vn "), 9);
+        assert_eq!(count_lines(&content, "f "), 8);
+        assert!(content.contains("v 4 5 4"));
+    }
+
+    #[test]
+    /// Every quad of a 3x3 grid touches the center cell, so a NaN center removes all faces.
+    fn test_export_obj_skips_nan_quads() {
+        let data = vec![
+            1.0, 1.0, 1.0,
+            1.0, f32::NAN, 1.0,
+            1.0, 1.0, 1.0,
+        ];
+        let path = std::env::temp_dir().join("dem_mesh_nan_test.obj");
+        let path = path.to_str().unwrap();
+        export_obj(&data, 3, 3, 1.0, path, 1.0).unwrap();
+        let content = fs::read_to_string(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(count_lines(&content, "v "), 8);
+        assert_eq!(count_lines(&content, "f "), 0);
+    }
+}
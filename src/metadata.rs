@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+use crate::elevation_range;
+use crate::grid::Grid;
+
+/// Machine-readable record of one pipeline run, written by `--metadata-json`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProcessingMetadata {
+    /// The input file (`stdin` when read from standard input, comma-separated tiles with `--merge`).
+    pub input: String,
+    pub width: u32,
+    pub height: u32,
+    pub cellsize: f32,
+    /// Lowest valid elevation, `None` when every cell is NoData.
+    pub min_elevation: Option<f32>,
+    /// Highest valid elevation, `None` when every cell is NoData.
+    pub max_elevation: Option<f32>,
+    /// The color gradient of the RGB images (`turbo`, or the `--color-ramp` file).
+    pub gradient: String,
+    /// Azimuth of the hillshade light source, in degrees.
+    pub azimuth: f32,
+    /// Altitude of the hillshade light source, in degrees.
+    pub altitude: f32,
+    /// Every file written, in order.
+    pub outputs: Vec<String>,
+}
+
+impl ProcessingMetadata {
+    /// Describes a rendered grid; the outputs are filled in as they are written.
+    pub fn new(input: &str, grid: &Grid, gradient: &str, azimuth: f32, altitude: f32) -> Self {
+        let range = elevation_range(&grid.data);
+        ProcessingMetadata {
+            input: input.to_string(),
+            width: grid.width,
+            height: grid.height,
+            cellsize: grid.cellsize,
+            min_elevation: range.map(|(min, _)| min),
+            max_elevation: range.map(|(_, max)| max),
+            gradient: gradient.to_string(),
+            azimuth,
+            altitude,
+            outputs: Vec::new(),
+        }
+    }
+
+    /// Serializes the record as pretty-printed JSON.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
@@ -0,0 +1,442 @@
+use image::{GrayImage, Rgba, RgbaImage};
+use rayon::prelude::*;
+
+use crate::progress;
+use crate::relief::slope_degrees;
+
+/// Computes the profile curvature of every interior cell with the Zevenbergen-Thorne quadratic surface.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `cellsize` - The `(dx, dy)` size of each cell in the elevation data.
+/// # Returns
+/// * The curvature along the steepest slope (1 / length unit), positive on convex and negative on
+///   concave slopes. Edge cells and cells with a NaN neighbor are NaN, perfectly flat cells are 0.
+pub fn profile_curvature(data: &Vec<f32>, width: u32, height: u32, cellsize: (f32, f32)) -> Vec<f32> {
+    let mut curvature = vec![f32::NAN; data.len()];
+    let (lx, ly) = cellsize;
+    for y in 1..height.saturating_sub(1) {
+        for x in 1..width.saturating_sub(1) {
+            let z = |dx: i32, dy: i32| data[((y as i32 + dy) * width as i32 + (x as i32 + dx)) as usize];
+            let (z1, z2, z3) = (z(-1, -1), z(0, -1), z(1, -1));
+            let (z4, z5, z6) = (z(-1, 0), z(0, 0), z(1, 0));
+            let (z7, z8, z9) = (z(-1, 1), z(0, 1), z(1, 1));
+
+            let d = ((z4 + z6) / 2.0 - z5) / (lx * lx);
+            let e = ((z2 + z8) / 2.0 - z5) / (ly * ly);
+            let f = (-z1 + z3 + z7 - z9) / (4.0 * lx * ly);
+            let g = (-z4 + z6) / (2.0 * lx);
+            let h = (z2 - z8) / (2.0 * ly);
+
+            let gradient = g * g + h * h;
+            curvature[(y * width + x) as usize] = if gradient == 0.0 {
+                0.0
+            } else {
+                -2.0 * (d * g * g + e * h * h + f * g * h) / gradient
+            };
+        }
+    }
+    curvature
+}
+
+/// Renders the profile curvature with a diverging blue-white-red ramp.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `cellsize` - The `(dx, dy)` size of each cell in the elevation data.
+/// # Returns
+/// * An `RgbaImage` where concave cells are blue, convex cells red and straight slopes white.
+///   The ramp is symmetric around zero and scaled to the strongest curvature of the map.
+///   Edge cells and NaN neighborhoods are transparent.
+pub fn curvature_map(data: &Vec<f32>, width: u32, height: u32, cellsize: (f32, f32)) -> RgbaImage {
+    let curvature = profile_curvature(data, width, height, cellsize);
+    let max_abs = curvature.iter().filter(|c| !c.is_nan()).fold(0.0f32, |m, c| m.max(c.abs()));
+    let mut image = RgbaImage::new(width, height);
+    for (i, &c) in curvature.iter().enumerate() {
+        if c.is_nan() {
+            continue;
+        }
+        let t = if max_abs > 0.0 { c / max_abs } else { 0.0 };
+        // fade from white toward red (t > 0) or blue (t < 0)
+        let fade = (255.0 * (1.0 - t.abs())) as u8;
+        let color = if t >= 0.0 { Rgba([255, fade, fade, 255]) } else { Rgba([fade, fade, 255, 255]) };
+        image.put_pixel(i as u32 % width, i as u32 / width, color);
+    }
+    image
+}
+
+/// Computes the GDAL roughness: the range between the highest and lowest cell of each 3x3 neighborhood.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// # Returns
+/// * A `GrayImage` with the roughness scaled so the most rugged cell of the grid is 255.
+///   NaN neighbors are ignored; edge cells and cells without any valid neighbor are 0.
+pub fn roughness_map(data: &Vec<f32>, width: u32, height: u32) -> GrayImage {
+    let mut roughness = vec![0.0f32; data.len()];
+    for y in 1..height.saturating_sub(1) {
+        for x in 1..width.saturating_sub(1) {
+            let (mut min, mut max) = (f32::INFINITY, f32::NEG_INFINITY);
+            for ny in y - 1..=y + 1 {
+                for nx in x - 1..=x + 1 {
+                    let z = data[(ny * width + nx) as usize];
+                    if !z.is_nan() {
+                        min = min.min(z);
+                        max = max.max(z);
+                    }
+                }
+            }
+            if min <= max {
+                roughness[(y * width + x) as usize] = max - min;
+            }
+        }
+    }
+
+    let peak = roughness.iter().cloned().fold(0.0f32, f32::max);
+    let mut image = GrayImage::new(width, height);
+    if peak > 0.0 {
+        for (pixel, r) in image.pixels_mut().zip(roughness) {
+            pixel.0[0] = (r / peak * 255.0).round() as u8;
+        }
+    }
+    image
+}
+
+/// Slope bands (in degrees) used by avalanche and ski-touring maps: 0-25, 25-30, 30-35, 35-45 and 45+.
+pub const AVALANCHE_THRESHOLDS: [f32; 4] = [25.0, 30.0, 35.0, 45.0];
+
+/// Colors of the slope bands, from the flattest to the steepest: green, yellow, orange, red, purple.
+pub const SLOPE_CLASS_COLORS: [Rgba<u8>; 5] = [
+    Rgba([76, 175, 80, 255]),
+    Rgba([255, 235, 59, 255]),
+    Rgba([255, 152, 0, 255]),
+    Rgba([229, 57, 53, 255]),
+    Rgba([142, 36, 170, 255]),
+];
+
+/// Colors every cell by its slope band.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `cellsize` - The `(dx, dy)` size of each cell in the elevation data.
+/// * `thresholds` - Increasing band limits in degrees, e.g. `AVALANCHE_THRESHOLDS`.
+/// # Returns
+/// * An `RgbaImage` where band `i` (slopes from `thresholds[i - 1]` up to, but excluding,
+///   `thresholds[i]`) gets `SLOPE_CLASS_COLORS[i]`. Bands beyond the palette reuse its last color.
+///   NaN cells are transparent.
+pub fn slope_classified(data: &Vec<f32>, width: u32, height: u32, cellsize: (f32, f32), thresholds: &[f32]) -> RgbaImage {
+    let slope = slope_degrees(data, width, height, cellsize);
+    let mut image = RgbaImage::new(width, height);
+    for (i, &s) in slope.iter().enumerate() {
+        if s.is_nan() {
+            continue;
+        }
+        let band = thresholds.partition_point(|&t| t <= s);
+        let color = SLOPE_CLASS_COLORS[band.min(SLOPE_CLASS_COLORS.len() - 1)];
+        image.put_pixel(i as u32 % width, i as u32 / width, color);
+    }
+    image
+}
+
+/// Approximates the sky-view factor: the fraction of the sky hemisphere visible from each cell.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `cellsize` - The `(dx, dy)` size of each cell in the elevation data.
+/// * `num_directions` - Number of azimuths the horizon is searched in.
+/// * `max_radius` - Search distance in cells along each azimuth.
+/// # Returns
+/// * A `GrayImage` where an open plain is 255 and enclosed valleys or pits get darker. NaN cells are 0.
+///
+/// For each direction the highest horizon angle `h` within `max_radius` is found, and the factor is
+/// `1 - mean(sin(h))`. NaN cells along a ray and terrain beyond the grid edge are ignored.
+/// Rows are processed in parallel with rayon.
+pub fn sky_view_factor(data: &Vec<f32>, width: u32, height: u32, cellsize: (f32, f32), num_directions: u32, max_radius: u32) -> GrayImage {
+    // unit pixel step along each azimuth and the ground distance it covers
+    let directions: Vec<(f32, f32, f32)> = (0..num_directions.max(1))
+        .map(|i| {
+            let azimuth = i as f32 * std::f32::consts::TAU / num_directions.max(1) as f32;
+            let (step_x, step_y) = (azimuth.sin(), -azimuth.cos());
+            (step_x, step_y, (step_x * cellsize.0).hypot(step_y * cellsize.1))
+        })
+        .collect();
+    let mut buffer = vec![0u8; (width * height) as usize];
+    let progress = progress::row_progress(height, "sky view");
+
+    buffer.par_chunks_mut(width.max(1) as usize).enumerate().for_each(|(y, row)| {
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let z0 = data[y * width as usize + x];
+            if z0.is_nan() {
+                continue;
+            }
+            let mut sin_sum = 0.0;
+            for &(step_x, step_y, step_distance) in &directions {
+                let mut max_angle: f32 = 0.0;
+                for r in 1..=max_radius {
+                    let px = (x as f32 + r as f32 * step_x).round();
+                    let py = (y as f32 + r as f32 * step_y).round();
+                    if px < 0.0 || py < 0.0 || px >= width as f32 || py >= height as f32 {
+                        break;
+                    }
+                    let z = data[py as usize * width as usize + px as usize];
+                    if !z.is_nan() {
+                        max_angle = max_angle.max(((z - z0) / (r as f32 * step_distance)).atan());
+                    }
+                }
+                sin_sum += max_angle.sin();
+            }
+            let svf = 1.0 - sin_sum / directions.len() as f32;
+            *pixel = (svf * 255.0).round().clamp(0.0, 255.0) as u8;
+        }
+        progress.inc(1);
+    });
+    progress.finish();
+    GrayImage::from_raw(width, height, buffer).expect("buffer matches the image size")
+}
+
+/// Pixel value of the observer cell in `viewshed`.
+pub const VIEWSHED_OBSERVER: u8 = 128;
+
+/// Computes which cells can be seen from an observer standing on the terrain.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `cellsize` - The `(dx, dy)` size of each cell in the elevation data.
+/// * `obs_x` - Column of the observer cell.
+/// * `obs_y` - Row of the observer cell (0 at the top). The cell must hold valid data.
+/// * `obs_height` - Height of the observer's eye (e.g. an antenna) above the ground.
+/// # Returns
+/// * A `GrayImage` with visible cells white, hidden cells black and the observer cell `VIEWSHED_OBSERVER`.
+///   NaN cells are black.
+///
+/// Uses the R3 algorithm: the line of sight to every cell is checked against the terrain at each
+/// column or row crossed on the way, interpolating linearly between the two cells straddling the ray.
+/// A cell is visible when no sample rises above the straight line from the eye to its ground.
+/// NaN samples do not block the view. Rows are processed in parallel with rayon.
+pub fn viewshed(data: &Vec<f32>, width: u32, height: u32, cellsize: (f32, f32), obs_x: u32, obs_y: u32, obs_height: f32) -> GrayImage {
+    let w = width as usize;
+    let eye = data[obs_y as usize * w + obs_x as usize] + obs_height;
+    let (ox, oy) = (obs_x as f32, obs_y as f32);
+    let mut buffer = vec![0u8; (width * height) as usize];
+    let progress = progress::row_progress(height, "viewshed");
+
+    buffer.par_chunks_mut(w.max(1)).enumerate().for_each(|(y, row)| {
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let target = data[y * w + x];
+            if target.is_nan() {
+                continue;
+            }
+            let (dx, dy) = (x as f32 - ox, y as f32 - oy);
+            let steps = dx.abs().max(dy.abs()) as u32;
+            if steps == 0 {
+                *pixel = VIEWSHED_OBSERVER;
+                continue;
+            }
+            // tangent of the sight line from the eye to the target ground
+            let distance = (dx * cellsize.0).hypot(dy * cellsize.1);
+            let sight = (target - eye) / distance;
+            let blocked = (1..steps).any(|i| {
+                let t = i as f32 / steps as f32;
+                let z = sample_along(data, w, ox + dx * t, oy + dy * t);
+                !z.is_nan() && (z - eye) / (distance * t) > sight + 1e-6
+            });
+            *pixel = if blocked { 0 } else { 255 };
+        }
+        progress.inc(1);
+    });
+    progress.finish();
+    GrayImage::from_raw(width, height, buffer).expect("buffer matches the image size")
+}
+
+/// Samples the elevation along a straight line between two cells.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `cellsize` - The `(dx, dy)` size of each cell in the elevation data.
+/// * `x0`, `y0` - Column and row of the start cell (0 at the top).
+/// * `x1`, `y1` - Column and row of the end cell. Both cells must lie inside the grid.
+/// # Returns
+/// * The `(distance, elevation)` samples from the start, the distance in the units of `cellsize`.
+///
+/// The line is stepped like Bresenham's, one sample per column or row along its major axis, and
+/// each sample is interpolated bilinearly between the four surrounding cells. NaN samples are skipped.
+#[allow(clippy::too_many_arguments)]
+pub fn transect(data: &Vec<f32>, width: u32, height: u32, cellsize: (f32, f32), x0: u32, y0: u32, x1: u32, y1: u32) -> Vec<(f32, f32)> {
+    let (dx, dy) = (x1 as f32 - x0 as f32, y1 as f32 - y0 as f32);
+    let steps = dx.abs().max(dy.abs()) as u32;
+    let length = (dx * cellsize.0).hypot(dy * cellsize.1);
+    (0..=steps)
+        .filter_map(|i| {
+            let t = if steps == 0 { 0.0 } else { i as f32 / steps as f32 };
+            let z = sample_bilinear(data, width, height, x0 as f32 + dx * t, y0 as f32 + dy * t);
+            (!z.is_nan()).then_some((length * t, z))
+        })
+        .collect()
+}
+
+/// Bilinear elevation at a fractional cell position, clamped to the last row and column.
+fn sample_bilinear(data: &[f32], width: u32, height: u32, x: f32, y: f32) -> f32 {
+    let (xa, ya) = (x.floor() as u32, y.floor() as u32);
+    let (xb, yb) = ((xa + 1).min(width - 1), (ya + 1).min(height - 1));
+    let (fx, fy) = (x - xa as f32, y - ya as f32);
+    let at = |x: u32, y: u32| data[(y * width + x) as usize];
+    let top = at(xa, ya) * (1.0 - fx) + at(xb, ya) * fx;
+    let bottom = at(xa, yb) * (1.0 - fx) + at(xb, yb) * fx;
+    top * (1.0 - fy) + bottom * fy
+}
+
+/// Elevation at a point of a ray whose x or y is a whole cell index, interpolating along the other axis.
+fn sample_along(data: &[f32], width: usize, x: f32, y: f32) -> f32 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let (x0, y0) = (x0 as usize, y0 as usize);
+    let at = |x: usize, y: usize| data[y * width + x];
+    if fx < 1e-6 && fy < 1e-6 {
+        at(x0, y0)
+    } else if fx < 1e-6 {
+        at(x0, y0) * (1.0 - fy) + at(x0, y0 + 1) * fy
+    } else {
+        at(x0, y0) * (1.0 - fx) + at(x0 + 1, y0) * fx
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paraboloid(size: u32, sign: f32) -> Vec<f32> {
+        let center = (size / 2) as f32;
+        (0..size * size)
+            .map(|i| {
+                let (x, y) = ((i % size) as f32 - center, (i / size) as f32 - center);
+                sign * (x * x + y * y)
+            })
+            .collect()
+    }
+
+    #[test]
+    /// A dome is convex (red) and a bowl concave (blue) away from the center, and the edges are transparent.
+    fn test_curvature_map_paraboloid() {
+        let size = 11;
+        let dome = curvature_map(&paraboloid(size, -1.0), size, size, (1.0, 1.0));
+        let bowl = curvature_map(&paraboloid(size, 1.0), size, size, (1.0, 1.0));
+        for (x, y) in [(2, 5), (8, 3), (5, 8)] {
+            let (d, b) = (dome.get_pixel(x, y), bowl.get_pixel(x, y));
+            assert!(d[0] > d[2], "dome at ({}, {}) is {:?}", x, y, d);
+            assert!(b[2] > b[0], "bowl at ({}, {}) is {:?}", x, y, b);
+        }
+        assert_eq!(dome.get_pixel(0, 4)[3], 0);
+        assert_eq!(bowl.get_pixel(10, 10)[3], 0);
+
+        let curvature = profile_curvature(&paraboloid(size, -1.0), size, size, (1.0, 1.0));
+        assert!((curvature[(3 * size + 5) as usize] - 2.0).abs() < 1e-4);
+        // rows twice as far apart halve the north-south slope and quarter the curvature along it
+        let curvature = profile_curvature(&paraboloid(size, -1.0), size, size, (1.0, 2.0));
+        assert!((curvature[(3 * size + 5) as usize] - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    /// A flat plain sees the whole sky, while the bottom of a narrow trench sees less of it.
+    fn test_sky_view_factor_plain_and_trench() {
+        let (width, height) = (20, 20);
+        let plain = vec![100.0; (width * height) as usize];
+        let svf = sky_view_factor(&plain, width, height, (1.0, 1.0), 16, 5);
+        assert!(svf.pixels().all(|p| p[0] == 255));
+
+        let trench: Vec<f32> = (0..width * height).map(|i| if i % width == 10 { 90.0 } else { 100.0 }).collect();
+        let svf = sky_view_factor(&trench, width, height, (1.0, 1.0), 16, 5);
+        assert!(svf.get_pixel(10, 10)[0] < 200);
+        assert_eq!(svf.get_pixel(3, 10)[0], 255);
+    }
+
+    #[test]
+    /// On a ramp steepening from ~27 to ~33 degrees the color switches from yellow to orange where the slope crosses 30.
+    fn test_slope_classified_ramp() {
+        let (width, height) = (12, 3);
+        // columns 0..=5 rise by 0.5 per cell, the next ones by 0.65
+        let profile: Vec<f32> = (0..width)
+            .map(|x| if x <= 5 { x as f32 * 0.5 } else { 2.5 + (x - 5) as f32 * 0.65 })
+            .collect();
+        let data: Vec<f32> = (0..width * height).map(|i| profile[(i % width) as usize]).collect();
+        let image = slope_classified(&data, width, height, (1.0, 1.0), &AVALANCHE_THRESHOLDS);
+        let (yellow, orange) = (SLOPE_CLASS_COLORS[1], SLOPE_CLASS_COLORS[2]);
+        // Horn's slope at column 5 averages both sides: atan(0.575) is just under 30 degrees
+        for x in 1..=5 {
+            assert_eq!(image.get_pixel(x, 1), &yellow, "column {}", x);
+        }
+        for x in 6..width - 1 {
+            assert_eq!(image.get_pixel(x, 1), &orange, "column {}", x);
+        }
+    }
+
+    #[test]
+    /// A flat grid has no roughness at all.
+    fn test_roughness_flat() {
+        let data = vec![7.0; 25];
+        let image = roughness_map(&data, 5, 5);
+        assert!(image.pixels().all(|p| p.0[0] == 0));
+    }
+
+    #[test]
+    /// On a checkerboard every interior cell sees the full range, while edge cells stay 0.
+    fn test_roughness_checkerboard() {
+        let (width, height) = (6, 5);
+        let mut data: Vec<f32> = (0..width * height).map(|i| ((i % width + i / width) % 2) as f32 * 10.0).collect();
+        data[(2 * width + 2) as usize] = f32::NAN;
+        let image = roughness_map(&data, width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let interior = x > 0 && y > 0 && x < width - 1 && y < height - 1;
+                assert_eq!(image.get_pixel(x, y).0[0], if interior { 255 } else { 0 }, "({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    /// From anywhere on a flat plain the whole plain is in view.
+    fn test_viewshed_flat_plain() {
+        let (width, height) = (15, 11);
+        let data = vec![100.0; (width * height) as usize];
+        let view = viewshed(&data, width, height, (10.0, 10.0), 3, 7, 1.5);
+        assert_eq!(view.get_pixel(3, 7)[0], VIEWSHED_OBSERVER);
+        assert!(view.enumerate_pixels().all(|(x, y, p)| (x, y) == (3, 7) || p[0] == 255));
+    }
+
+    #[test]
+    /// A ridge hides the low ground behind it, but not its own crest or the ground in front of it.
+    fn test_viewshed_ridge() {
+        let (width, height) = (21, 11);
+        let data: Vec<f32> = (0..width * height).map(|i| if i % width == 10 { 50.0 } else { 0.0 }).collect();
+        let view = viewshed(&data, width, height, (1.0, 1.0), 2, 5, 2.0);
+        for y in 0..height {
+            assert_eq!(view.get_pixel(5, y)[0], 255, "front ({}, {})", 5, y);
+            assert_eq!(view.get_pixel(10, y)[0], 255, "crest (10, {})", y);
+            assert_eq!(view.get_pixel(15, y)[0], 0, "behind (15, {})", y);
+        }
+    }
+
+    #[test]
+    /// Along a ramp rising to the east, a diagonal transect climbs steadily and ends at the far cell's distance.
+    fn test_transect_ramp_monotonic() {
+        let (width, height) = (12, 8);
+        let data: Vec<f32> = (0..width * height).map(|i| (i % width) as f32 * 2.0).collect();
+        let samples = transect(&data, width, height, (10.0, 10.0), 1, 6, 10, 1);
+        assert_eq!(samples.len(), 10);
+        assert!(samples.windows(2).all(|w| w[1].0 > w[0].0 && w[1].1 > w[0].1), "{:?}", samples);
+        assert_eq!(samples[0], (0.0, 2.0));
+        let (distance, elevation) = samples[samples.len() - 1];
+        assert!((distance - (81.0f32 + 25.0).sqrt() * 10.0).abs() < 1e-3);
+        assert!((elevation - 20.0).abs() < 1e-4);
+
+        // rectangular cells: 9 columns of 10 and 5 rows of 20
+        let (distance, _) = *transect(&data, width, height, (10.0, 20.0), 1, 6, 10, 1).last().unwrap();
+        assert!((distance - (90.0f32 * 90.0 + 100.0 * 100.0).sqrt()).abs() < 1e-3);
+    }
+}
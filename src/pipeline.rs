@@ -0,0 +1,1317 @@
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::Local;
+use image::{DynamicImage, GrayImage, Luma, Rgba, RgbaImage};
+use log::{debug, info, warn};
+
+use crate::animate;
+use crate::annotate;
+#[cfg(feature = "hdf5")]
+use crate::archive::{save_hdf5, Metadata};
+use crate::batch;
+use crate::cli::{Args, DEFAULT_FILE_PATH, DEFAULT_GRADIENT};
+use crate::color;
+use crate::contours;
+use crate::filters::{gaussian_smooth, median_filter, unsharp_mask};
+use crate::georef;
+use crate::geotiff::save_geotiff;
+use crate::formats::{read_flt_header, read_geojson_ring};
+use crate::grid::{apply_mask, autocrop, crop, diff_dems, flip_vertical, mask_by_polygon, mask_elevation_band, merge_tiles, Grid};
+use crate::hydrology;
+use crate::mesh::export_obj;
+use crate::metadata::ProcessingMetadata;
+use crate::morphometry;
+use crate::preprocess::{clamp_elevation, fill_sinks, mask_values};
+use crate::qa::{self, QaReport};
+use crate::relief;
+use crate::resample::{downsample_mean, resample_with};
+use crate::sink::{self, make_thumbnail, thumbnail_name, OutputFormat, OutputSink};
+use crate::tiles;
+use crate::zonal;
+use crate::{blend_hillshade, compute_gradients, compute_gradients_sobel, data_to_grayscale, data_to_grayscale16, draw_vector_field_with, elevation_range, gradient_magnitude_image, hillshade_gray_with, parse_header_with, read_asc_header, AscHeader, ParseOptions, read_stdin, rgb_with};
+
+/// Azimuth of the light source used for the hillshade, in degrees clockwise from north.
+pub const AZIMUTH: f32 = 315.0;
+/// Altitude of the light source used for the hillshade, in degrees above the horizon.
+pub const ALTITUDE: f32 = 45.0;
+/// Number of azimuths searched for the horizon by `--sky-view`.
+pub const SKY_VIEW_DIRECTIONS: u32 = 16;
+
+/// Runs the whole pipeline for the input selected on the command line.
+/// # Arguments
+/// * `args` - The parsed command-line options.
+/// * `sink` - Where the produced images are written.
+/// # Returns
+/// * The QA report of the input, printed and checked against `--strict` by the caller.
+pub fn run(args: &Args, sink: &mut dyn OutputSink) -> Result<QaReport> {
+    let (grid, report) = load(args)?;
+    let mut tracked = TrackedSink { inner: sink, names: Vec::new() };
+    let sink = &mut tracked;
+    if let Some(path) = &args.geotiff_out {
+        save_geotiff(&grid.data, grid.width, grid.height, (grid.cellsize, grid.cellsize_y), grid.xllcorner as f32, grid.yllcorner as f32, path)
+            .map_err(|e| anyhow!("Failed to export GeoTIFF {}: {}", path, e))?;
+        sink.names.push(path.clone());
+        info!("GeoTIFF saved as {}", path);
+    }
+    if let Some(path) = &args.hdf5_out {
+        export_layers(&grid, args, path)?;
+        sink.names.push(path.clone());
+        info!("HDF5 layers saved as {}", path);
+    }
+    if let Some(path) = &args.mesh_out {
+        export_obj(&grid.data, grid.width, grid.height, grid.cellsize, path, args.mesh_z_scale)
+            .map_err(|e| anyhow!("Failed to export mesh {}: {}", path, e))?;
+        sink.names.push(path.clone());
+        info!("Mesh saved as {}", path);
+    }
+    if let Some(threshold) = args.drainage_density {
+        let directions = hydrology::flow_direction_d8(&grid.data, grid.width, grid.height);
+        let accumulation = hydrology::accumulate_flow(&grid.data, &directions, grid.width, grid.height);
+        let density = hydrology::drainage_density(&accumulation, &directions, grid.width, grid.height, grid.cellsize, threshold);
+        println!("Drainage density: {} (channels with >= {} upstream cells)", density, threshold);
+    }
+    if let Some(path) = &args.zones {
+        let (mut zone_grid, _) = read_path(path, args).with_context(|| format!("Failed to read the zone grid {}", path))?;
+        if args.flip_y {
+            flip_vertical(&mut zone_grid);
+        }
+        if (zone_grid.width, zone_grid.height) != (grid.width, grid.height) {
+            bail!("The zone grid is {}x{}, the DEM {}x{}", zone_grid.width, zone_grid.height, grid.width, grid.height);
+        }
+        let zones = zone_grid.data.iter().map(|&id| if id.is_nan() { zonal::NO_ZONE } else { id.round() as i32 }).collect();
+        let stats = zonal::zonal_stats(&grid.data, &zones, grid.width, grid.height);
+        print!("{}", zonal::format_table(&stats));
+    }
+    let tag = OutputTag::from_args(args);
+    render_with(&grid, args, &tag, sink)?;
+    if let Some(path) = &args.diff {
+        let (mut other, _) = read_path(path, args).with_context(|| format!("Failed to read {}", path))?;
+        if args.flip_y {
+            flip_vertical(&mut other);
+        }
+        mask_values(&mut other.data, &args.ignore_values);
+        let diff = diff_dems(&grid, &other).map_err(|e| anyhow!("Failed to compare with {}: {}", path, e))?;
+        let image = color::diverging_map(&diff.data, diff.width, diff.height);
+        Writer::new(sink, args)?.write(&tag.file("diff", "png"), &DynamicImage::ImageRgba8(image), &diff)?;
+        info!("Difference image saved as diff.png");
+    }
+    if args.metadata_json {
+        let gradient = match &args.color_ramp {
+            Some(path) => path.clone(),
+            None if args.reverse_gradient => format!("{} (reversed)", args.gradient),
+            None => args.gradient.clone(),
+        };
+        let mut metadata = ProcessingMetadata::new(&input_name(args), &grid, &gradient, AZIMUTH, ALTITUDE);
+        metadata.outputs = std::mem::take(&mut sink.names);
+        let json = metadata.to_json().context("Failed to serialize the metadata")?;
+        let name = tag.metadata_file();
+        sink.inner.write_text(&name, &json)?;
+        info!("Metadata saved as {}", name);
+    }
+    Ok(report)
+}
+
+/// The part of the output file names that tells the runs apart.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputTag {
+    /// The time of the run, as in `hillshade_gray_20250402_153924.png`.
+    Timestamp(String),
+    /// The stem of the input file, as in `foo_hillshade_gray.png` (`--no-timestamp`).
+    Stem(String),
+}
+
+impl OutputTag {
+    /// The tag selected on the command line: the input stem with `--no-timestamp`, otherwise the
+    /// current time in `--timestamp-format`.
+    pub fn from_args(args: &Args) -> Self {
+        if args.no_timestamp {
+            OutputTag::Stem(input_stem(args))
+        } else {
+            OutputTag::Timestamp(Local::now().format(&args.timestamp_format).to_string())
+        }
+    }
+
+    /// File name of a product, e.g. `tag.file("hillshade_gray", "png")`. With a stem, the `output`
+    /// prefix of the main images is replaced by the stem: `output` gives `foo.png`.
+    pub fn file(&self, product: &str, extension: &str) -> String {
+        match self {
+            OutputTag::Timestamp(timestamp) => format!("{}_{}.{}", product, timestamp, extension),
+            OutputTag::Stem(stem) => match product.strip_prefix("output") {
+                Some(rest) => format!("{}{}.{}", stem, rest, extension),
+                None => format!("{}_{}.{}", stem, product, extension),
+            },
+        }
+    }
+
+    /// File name of the RGB image. Timestamped names always end with the gradient; stem names only
+    /// when it is not the default turbo, so the usual `foo_rgb.png` stays short.
+    pub fn rgb_file(&self, gradient: &str) -> String {
+        match self {
+            OutputTag::Timestamp(timestamp) => format!("output_rgb_{}_{}.png", timestamp, gradient),
+            OutputTag::Stem(_) if gradient == DEFAULT_GRADIENT => self.file("output_rgb", "png"),
+            OutputTag::Stem(_) => self.file(&format!("output_rgb_{}", gradient), "png"),
+        }
+    }
+
+    /// File name of the `--metadata-json` record.
+    pub fn metadata_file(&self) -> String {
+        match self {
+            OutputTag::Timestamp(tag) | OutputTag::Stem(tag) => format!("{}.json", tag),
+        }
+    }
+}
+
+/// The file stem of the input, made safe for file names: characters other than ASCII letters,
+/// digits, `-`, `_` and `.` become `_`. Tiles of `--merge` and frames of `--animate` use the first
+/// one, stdin gives `stdin`.
+fn input_stem(args: &Args) -> String {
+    let path = args.merge.first()
+        .or(args.animate.first())
+        .map(String::as_str)
+        .or(args.file_path.as_deref())
+        .unwrap_or("stdin");
+    let path = if path == "-" { "stdin" } else { path };
+    let stem = Path::new(path).file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let safe: String = stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' })
+        .collect();
+    if safe.is_empty() { "output".to_string() } else { safe }
+}
+
+/// Sink forwarding to another one while remembering the name of every written file.
+struct TrackedSink<'a> {
+    inner: &'a mut dyn OutputSink,
+    names: Vec<String>,
+}
+
+impl OutputSink for TrackedSink<'_> {
+    fn write(&mut self, name: &str, img: &DynamicImage) -> Result<()> {
+        self.names.push(name.to_string());
+        self.inner.write(name, img)
+    }
+
+    fn write_with_resolution(&mut self, name: &str, img: &DynamicImage, pixels_per_meter: u32) -> Result<()> {
+        self.names.push(name.to_string());
+        self.inner.write_with_resolution(name, img, pixels_per_meter)
+    }
+
+    fn write_text(&mut self, name: &str, contents: &str) -> Result<()> {
+        self.names.push(name.to_string());
+        self.inner.write_text(name, contents)
+    }
+}
+
+/// Describes where the input grid comes from, for the metadata record.
+fn input_name(args: &Args) -> String {
+    if !args.merge.is_empty() {
+        return args.merge.join(",");
+    }
+    match args.file_path.as_deref() {
+        Some("-") => "stdin".to_string(),
+        Some(path) => path.to_string(),
+        None if !std::io::stdin().is_terminal() => "stdin".to_string(),
+        None => DEFAULT_FILE_PATH.to_string(),
+    }
+}
+
+/// Parses only the header of the input selected on the command line, for `--info`.
+/// # Arguments
+/// * `args` - The parsed command-line options.
+/// # Returns
+/// * The header as read from the file, before any preprocessing.
+pub fn header_info(args: &Args) -> Result<AscHeader> {
+    let content = match args.file_path.as_deref() {
+        Some("-") => read_input_stdin()?,
+        None if !std::io::stdin().is_terminal() => read_input_stdin()?,
+        None => return read_header(DEFAULT_FILE_PATH, args),
+        Some(path) => return read_header(path, args),
+    };
+    let options = parse_options(args);
+    parse_header_with(&content, &options).map_err(|e| anyhow!("Failed to read ASC header: {}", e))
+}
+
+/// Reads the header of one input file, dispatching on its extension as `read_path` does: `.flt`
+/// grids are described by their `.hdr` sidecar and ASC files by their first lines, without reading
+/// the data. JSON grids and XYZ points have no header of their own, so they are loaded.
+fn read_header(path: &str, args: &Args) -> Result<AscHeader> {
+    let lower = path.to_ascii_lowercase();
+    if lower.ends_with(".json") || lower.ends_with(".xyz") {
+        let (grid, _) = read_path(path, args)?;
+        let dy = (grid.cellsize_y != grid.cellsize).then_some(grid.cellsize_y);
+        return Ok(AscHeader {
+            ncols: grid.width, nrows: grid.height, xllcorner: grid.xllcorner, yllcorner: grid.yllcorner,
+            cellsize: Some(grid.cellsize), dy, ..Default::default()
+        });
+    }
+    if lower.ends_with(".flt") {
+        let hdr_path = Path::new(path).with_extension("hdr").to_string_lossy().into_owned();
+        let header = read_flt_header(&read_input_file(&hdr_path)?)
+            .map_err(|e| anyhow!("Failed to read FLT header {}: {}", hdr_path, e))?;
+        return Ok(AscHeader {
+            ncols: header.ncols, nrows: header.nrows, xllcorner: header.xllcorner, yllcorner: header.yllcorner,
+            cellsize: Some(header.cellsize), nodata_value: header.nodata_value, ..Default::default()
+        });
+    }
+    read_asc_header(path, &parse_options(args)).map_err(|e| anyhow!("Failed to read ASC header of {}: {}", path, e))
+}
+
+/// Looks up the elevation of the cell selected by `--query` or `--query-geo`, without rendering.
+/// # Arguments
+/// * `args` - The parsed command-line options.
+/// # Returns
+/// * The line to print: the cell and its elevation, or `NoData`.
+///
+/// The grid is loaded with the usual preprocessing, so the value is the one the images would show.
+pub fn query(args: &Args) -> Result<String> {
+    let (grid, _) = load(args)?;
+    match (args.query, args.query_geo) {
+        (Some((column, row)), _) => query_cell(&grid, column, row),
+        (None, Some((x, y))) => match grid.cell_at(x, y) {
+            Some((column, row)) => Ok(format!("({}, {}) -> {}", x, y, query_cell(&grid, column, row)?)),
+            None => bail!("The point ({}, {}) is outside the grid, which spans x {} to {} and y {} to {}",
+                x, y, grid.xllcorner, grid.xllcorner + grid.width as f64 * grid.cellsize as f64,
+                grid.yllcorner, grid.yllcorner + grid.height as f64 * grid.cellsize_y as f64),
+        },
+        (None, None) => bail!("No --query or --query-geo point given"),
+    }
+}
+
+/// Formats the elevation of one cell for `--query`.
+/// # Arguments
+/// * `grid` - The elevation grid.
+/// * `column` - The column of the cell.
+/// * `row` - The row of the cell, counted from the top.
+/// # Returns
+/// * `Cell X,Y: <elevation>`, with `NoData` for NaN cells, or an error when the cell is outside the grid.
+pub fn query_cell(grid: &Grid, column: u32, row: u32) -> Result<String> {
+    if column >= grid.width || row >= grid.height {
+        bail!("The cell {},{} is outside the {}x{} grid", column, row, grid.width, grid.height);
+    }
+    let value = grid.data[(row * grid.width + column) as usize];
+    let elevation = if value.is_nan() { "NoData".to_string() } else { value.to_string() };
+    Ok(format!("Cell {},{}: {}", column, row, elevation))
+}
+
+/// Checks the input header and lists the files a run would write, for `--dry-run`.
+/// # Arguments
+/// * `args` - The parsed command-line options.
+/// * `output_dir` - The directory the images would be written into.
+/// # Returns
+/// * The path of every output: each image followed by its sidecars, the text files, then the exports.
+///   With `--batch` every tile lists its outputs in its own subfolder, with `--animate` the GIF alone.
+///
+/// Only the headers are read (every tile with `--merge` or `--batch`, every frame with `--animate`):
+/// nothing is rendered and nothing is written. A batch tile whose header fails is skipped with a
+/// warning, as `run_batch` does.
+pub fn dry_run(args: &Args, output_dir: &Path) -> Result<Vec<PathBuf>> {
+    if !args.animate.is_empty() {
+        for path in &args.animate {
+            let header = read_header(path, args)?;
+            info!("Frame {}: {}x{} grid", path, header.ncols, header.nrows);
+        }
+        return Ok(vec![output_dir.join(animate::animation_file(args))]);
+    }
+    if let Some(dir) = &args.batch {
+        let mut paths = Vec::new();
+        for file in batch::find_asc_files(Path::new(dir))? {
+            let tile_args = batch::tile_args(args, &file);
+            match header_info(&tile_args) {
+                Ok(header) => info!("Tile {}: {}x{} grid", file.display(), header.ncols, header.nrows),
+                Err(e) => {
+                    warn!("Skipping {}: {:#}", file.display(), e);
+                    continue;
+                }
+            }
+            paths.extend(planned_paths(&tile_args, &batch::tile_output_dir(&file, output_dir)));
+        }
+        return Ok(paths);
+    }
+    if args.merge.is_empty() {
+        let header = header_info(args)?;
+        info!("Input: {}x{} grid", header.ncols, header.nrows);
+    } else {
+        for path in &args.merge {
+            let header = read_header(path, args)?;
+            info!("Tile {}: {}x{} grid", path, header.ncols, header.nrows);
+        }
+    }
+    Ok(planned_paths(args, output_dir))
+}
+
+/// The files `run` writes for these options: the sink outputs inside `output_dir`, then the exports.
+fn planned_paths(args: &Args, output_dir: &Path) -> Vec<PathBuf> {
+    let tag = OutputTag::from_args(args);
+    let mut paths: Vec<PathBuf> = planned_outputs(args, &tag).iter().map(|name| output_dir.join(name)).collect();
+    let exports = [&args.geotiff_out, &args.hdf5_out, &args.mesh_out, &args.tiles];
+    paths.extend(exports.into_iter().flatten().map(PathBuf::from));
+    paths
+}
+
+/// Names of the files `run` writes into the sink for these options, following the same rules as
+/// `render_images` and `Writer` (output format, thumbnails, world files). `test_planned_outputs_match_run`
+/// keeps the two in step.
+fn planned_outputs(args: &Args, tag: &OutputTag) -> Vec<String> {
+    let flagged = |enabled: bool, product: &str| enabled.then(|| tag.file(product, "png"));
+    let images = [
+        flagged(true, "output"),
+        Some(tag.rgb_file(&args.gradient)),
+        flagged(args.band_size.is_some(), "hypsometric"),
+        flagged(args.curvature, "curvature"),
+        flagged(args.flow_accumulation, "flow_accumulation"),
+        flagged(args.terrain_rgb, "terrain_rgb"),
+        flagged(args.roughness, "roughness"),
+        flagged(args.slope_classes, "slope_classes"),
+        flagged(args.sky_view.is_some(), "sky_view"),
+        flagged(args.viewshed.is_some(), "viewshed"),
+        flagged(args.swiss_relief, "swiss_relief"),
+        flagged(args.contours.is_some() || args.contour_count.is_some(), "contours"),
+        flagged(args.shade_overlay.is_some(), "shade_overlay"),
+        flagged(true, "hillshade_gray"),
+        flagged(true, "hillshade_rgb"),
+        flagged(args.gradient_magnitude, "gradient_magnitude"),
+        flagged(true, "hillshade_rgb_gradient"),
+        flagged(args.diff.is_some(), "diff"),
+    ];
+    let mut names = Vec::new();
+    let images = images.into_iter().flatten().filter(|name| {
+        // --hillshade-only renders the gray hillshade alone, the difference image comes from `run`
+        !args.hillshade_only || *name == tag.file("hillshade_gray", "png") || *name == tag.file("diff", "png")
+    });
+    for name in images {
+        let name = args.output_format.rename(&name);
+        names.push(name.clone());
+        if args.thumbnail.is_some() {
+            names.push(thumbnail_name(&name));
+        }
+        if args.world_file {
+            names.push(georef::world_file_name(&name));
+            if args.crs.is_some() {
+                names.push(georef::prj_file_name(&name));
+            }
+        }
+    }
+    if args.transect.is_some() && !args.hillshade_only {
+        names.push(tag.file("transect", "csv"));
+    }
+    if args.metadata_json {
+        names.push(tag.metadata_file());
+    }
+    names
+}
+
+/// The ASC parse options selected on the command line.
+fn parse_options(args: &Args) -> ParseOptions {
+    ParseOptions { decimal_comma: args.decimal_comma, nodata_override: args.nodata_override, cellsize: args.cellsize }
+}
+
+/// Reads and parses the input grid and applies the requested preprocessing.
+/// # Arguments
+/// * `args` - The parsed command-line options.
+/// # Returns
+/// * The georeferenced elevation `Grid` and its QA report.
+///
+/// With `--merge`, every listed tile is read and the tiles are mosaicked into a single grid.
+pub fn load(args: &Args) -> Result<(Grid, QaReport)> {
+    let started = Instant::now();
+    let (mut grid, report) = if args.merge.is_empty() {
+        let stdin_piped = !std::io::stdin().is_terminal();
+        let (mut grid, report) = match args.file_path.as_deref() {
+            Some("-") => parse_input(None, read_input_stdin()?, args)?,
+            None if stdin_piped => parse_input(None, read_input_stdin()?, args)?,
+            None => {
+                info!("No file path provided, using the default dataset {}", DEFAULT_FILE_PATH);
+                parse_input(None, read_input_file(DEFAULT_FILE_PATH)?, args)?
+            }
+            Some(path) => {
+                debug!("Reading file path: {}", path);
+                read_path(path, args)?
+            }
+        };
+        if args.flip_y {
+            flip_vertical(&mut grid);
+        }
+        (grid, report)
+    } else {
+        let mut tiles = Vec::new();
+        let mut report = QaReport::new();
+        for path in &args.merge {
+            debug!("Reading file path: {}", path);
+            let (mut tile, tile_report) = read_path(path, args)
+                .with_context(|| format!("Failed to read tile {}", path))?;
+            // flip each tile before the mosaic, so tiles keep their place
+            if args.flip_y {
+                flip_vertical(&mut tile);
+            }
+            report.extend(path, tile_report);
+            tiles.push(tile);
+        }
+        let grid = merge_tiles(&tiles).map_err(|e| anyhow!("Failed to merge tiles: {}", e))?;
+        debug!("Merged {} tiles", tiles.len());
+        (grid, report)
+    };
+    debug!("Parsed a {}x{} grid in {:?}", grid.width, grid.height, started.elapsed());
+
+    if let Some(path) = &args.mask_grid {
+        let (mut mask, _) = read_path(path, args).with_context(|| format!("Failed to read the mask grid {}", path))?;
+        if args.flip_y {
+            flip_vertical(&mut mask);
+        }
+        apply_mask(&mut grid, &mask, args.mask_valid).map_err(|e| anyhow!("Failed to apply the mask grid {}: {}", path, e))?;
+    }
+
+    if let Some(bounds) = &args.crop {
+        grid = crop(&grid, bounds[0], bounds[1], bounds[2], bounds[3]);
+        if grid.width == 0 || grid.height == 0 {
+            bail!("The --crop box does not overlap the grid");
+        }
+        debug!("Cropped to bounding box: {}x{}", grid.width, grid.height);
+    }
+    if let Some(path) = &args.mask {
+        let polygon = read_geojson_ring(&read_input_file(path)?).map_err(|e| anyhow!("Failed to read the --mask polygon {}: {}", path, e))?;
+        mask_by_polygon(&mut grid, &polygon);
+        debug!("Masked by a polygon of {} vertices", polygon.len());
+    }
+    mask_values(&mut grid.data, &args.ignore_values);
+    if let Some((min, max)) = args.clamp_elevation {
+        if min > max {
+            bail!("--clamp-elevation needs MIN <= MAX, got {},{}", min, max);
+        }
+        clamp_elevation(&mut grid.data, min, max);
+    }
+    if let Some(radius) = args.despike {
+        grid.data = median_filter(&grid.data, grid.width, grid.height, radius);
+    }
+    if let Some(sigma) = args.smooth {
+        grid.data = gaussian_smooth(&grid.data, grid.width, grid.height, sigma);
+    }
+    if let Some((sigma, amount)) = args.unsharp {
+        grid.data = unsharp_mask(&grid.data, grid.width, grid.height, sigma, amount);
+    }
+    if args.autocrop {
+        grid = autocrop(&grid);
+        debug!("Cropped to valid data: {}x{}", grid.width, grid.height);
+    }
+    if let Some(cellsize) = args.resample {
+        if cellsize <= 0.0 {
+            bail!("--resample needs a positive cellsize, got {}", cellsize);
+        }
+        grid = resample_with(&grid, cellsize, args.resample_method);
+        debug!("Resampled to {}x{} ({:?})", grid.width, grid.height, args.resample_method);
+    }
+    if let Some(factor) = args.overview {
+        let top = grid.yllcorner + grid.height as f64 * grid.cellsize_y as f64;
+        let (data, width, height) = downsample_mean(&grid.data, grid.width, grid.height, factor);
+        let (cellsize, cellsize_y) = (grid.cellsize * factor as f32, grid.cellsize_y * factor as f32);
+        // the top-left corner stays in place, blocks cut by the bottom edge extend the extent down
+        grid = Grid { data, width, height, cellsize, cellsize_y, yllcorner: top - height as f64 * cellsize_y as f64, ..grid };
+        debug!("Overview by block mean: {}x{}", grid.width, grid.height);
+    }
+    if args.fill_sinks {
+        fill_sinks(&mut grid.data, grid.width, grid.height);
+    }
+    if let Some((low, high)) = args.band {
+        if low > high {
+            bail!("--band needs LOW <= HIGH, got {},{}", low, high);
+        }
+        mask_elevation_band(&mut grid, low, high);
+        if elevation_range(&grid.data).is_none() {
+            bail!("No cell has an elevation within --band {},{}", low, high);
+        }
+    }
+    Ok((grid, report))
+}
+
+/// Computes the derivative layers of the grid and archives them in one HDF5 file.
+#[cfg(feature = "hdf5")]
+fn export_layers(grid: &Grid, args: &Args, path: &str) -> Result<()> {
+    let (data, width, height, cellsize) = (&grid.data, grid.width, grid.height, grid.cellsize);
+    let slope = relief::slope_degrees_with(data, width, height, (cellsize, grid.cellsize_y), args.slope_algo);
+    let aspect = relief::aspect_degrees(data, width, height, (cellsize, grid.cellsize_y));
+    let shade = hillshade_gray_with(data, width, height, (cellsize, grid.cellsize_y), AZIMUTH, ALTITUDE, 0.0, args.slope_algo);
+    let hillshade: Vec<f32> = shade.pixels().map(|p| p[0] as f32).collect();
+    let attrs = Metadata { cellsize, xllcorner: grid.xllcorner, yllcorner: grid.yllcorner, crs: args.crs.clone() };
+    let layers: [(&str, &[f32]); 4] = [("elevation", data), ("slope", &slope), ("aspect", &aspect), ("hillshade", &hillshade)];
+    save_hdf5(&layers, width, height, &attrs, path).with_context(|| format!("Failed to export HDF5 {}", path))
+}
+
+#[cfg(not(feature = "hdf5"))]
+fn export_layers(_grid: &Grid, _args: &Args, _path: &str) -> Result<()> {
+    bail!("--hdf5-out needs HDF5 support, rebuild with `cargo build --features hdf5`")
+}
+
+/// Reads a grid file, dispatching on its extension: `.flt` binary grids are read with their
+/// `.hdr` sidecar, `.json` and `.xyz` files are parsed as text by `parse_input` and everything
+/// else is streamed as ASC.
+pub(crate) fn read_path(path: &str, args: &Args) -> Result<(Grid, QaReport)> {
+    let lower = path.to_ascii_lowercase();
+    if lower.ends_with(".json") || lower.ends_with(".xyz") {
+        return parse_input(Some(path), read_input_file(path)?, args);
+    }
+    if !lower.ends_with(".flt") {
+        // ASC files are parsed line by line so large grids are not held twice in memory
+        let (grid, header, skipped) = Grid::from_asc_file(path, &parse_options(args))
+            .map_err(|e| anyhow!("Failed to read ASC file: {}", e))?;
+        debug!("ASC header: {} lines, nodata value {:?}, cellsize {:?}", header.header_lines, header.nodata_value, header.cellsize);
+        let report = qa_report(&header, &grid.data, skipped);
+        return Ok((grid, report));
+    }
+    let grid = Grid::from_flt(path).map_err(|e| anyhow!("Failed to read FLT grid: {}", e))?;
+    let header = AscHeader { ncols: grid.width, nrows: grid.height, cellsize: Some(grid.cellsize), ..Default::default() };
+    let report = qa::check_grid(&header, &grid.data);
+    Ok((grid, report))
+}
+
+/// Parses the content of one input file, as a JSON grid when the path ends in `.json`, as an XYZ
+/// point list when it ends in `.xyz` and as ASC otherwise.
+fn parse_input(path: Option<&str>, file_content: String, args: &Args) -> Result<(Grid, QaReport)> {
+    let extension = path.map(|path| path.rsplit('.').next().unwrap_or_default().to_ascii_lowercase());
+    let (grid, header, skipped) = if let Some(format @ ("json" | "xyz")) = extension.as_deref() {
+        let grid = if format == "json" {
+            Grid::from_json(&file_content).map_err(|e| anyhow!("Failed to read JSON grid: {}", e))?
+        } else {
+            Grid::from_xyz(&file_content).map_err(|e| anyhow!("Failed to read XYZ points: {}", e))?
+        };
+        let header = AscHeader { ncols: grid.width, nrows: grid.height, cellsize: Some(grid.cellsize), ..Default::default() };
+        (grid, header, 0)
+    } else {
+        // use the asc_to_image function to open the file
+        let options = parse_options(args);
+        let header = parse_header_with(&file_content, &options)
+            .map_err(|e| anyhow!("Failed to read ASC file: {}", e))?;
+        debug!("ASC header: {} lines, nodata value {:?}, cellsize {:?}", header.header_lines, header.nodata_value, header.cellsize);
+        let (grid, skipped) = Grid::from_asc_with(file_content, &options)
+            .map_err(|e| anyhow!("Failed to read ASC file: {}", e))?;
+        (grid, header, skipped)
+    };
+    let report = qa_report(&header, &grid.data, skipped);
+    Ok((grid, report))
+}
+
+/// Runs the quality checks on a parsed grid, warning about the tokens the parser skipped.
+fn qa_report(header: &AscHeader, data: &[f32], skipped: usize) -> QaReport {
+    let mut report = qa::check_grid(header, data);
+    if skipped > 0 {
+        report.warn(format!("{} unparseable values were skipped", skipped));
+    }
+    report
+}
+
+fn read_input_stdin() -> Result<String> {
+    debug!("Reading ASC data from stdin");
+    read_stdin().context("Failed to read ASC data from stdin")
+}
+
+/// Reads an input file, returning an error instead of panicking when it is missing or unreadable
+/// so a batch can report the tile and carry on.
+fn read_input_file(path: &str) -> Result<String> {
+    std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))
+}
+
+/// Writes images through the sink, adding the georeferencing sidecars requested on the command line.
+struct Writer<'a> {
+    sink: &'a mut dyn OutputSink,
+    /// Whether `--world-file` is set.
+    world_file: bool,
+    /// WKT of the `--crs`, written as `.prj` next to every world file.
+    prj: Option<&'static str>,
+    /// Format of the written images, see `--output-format`.
+    format: OutputFormat,
+    /// Background of flattened JPEG images.
+    background: Rgba<u8>,
+    /// Longest side of the thumbnails, see `--thumbnail`.
+    thumbnail: Option<u32>,
+    /// Resolution declared in the PNGs instead of the ground resolution, see `--print-dpi`.
+    print_dpi: Option<f32>,
+}
+
+impl<'a> Writer<'a> {
+    fn new(sink: &'a mut dyn OutputSink, args: &Args) -> Result<Self> {
+        let prj = match &args.crs {
+            None => None,
+            Some(crs) => match georef::crs_wkt(crs) {
+                Some(wkt) => Some(wkt),
+                None => bail!("Unknown CRS {} (supported EPSG codes: {:?})", crs, georef::known_crs_codes()),
+            },
+        };
+        if let Some(dpi) = args.print_dpi.filter(|dpi| *dpi <= 0.0) {
+            bail!("--print-dpi must be positive, got {}", dpi);
+        }
+        Ok(Writer { sink, world_file: args.world_file, prj, format: args.output_format, background: args.jpeg_background, thumbnail: args.thumbnail, print_dpi: args.print_dpi })
+    }
+
+    /// Writes an image rendered from `grid` in the selected format, followed by its sidecars.
+    /// The extension of `name` is replaced by the one of the format, and PNGs declare their resolution.
+    fn write(&mut self, name: &str, img: &DynamicImage, grid: &Grid) -> Result<()> {
+        let name = self.format.rename(name);
+        let prepared = self.format.prepare(img, self.background);
+        match sink::pixels_per_meter(grid.cellsize, self.print_dpi) {
+            Some(pixels_per_meter) => self.sink.write_with_resolution(&name, &prepared, pixels_per_meter)?,
+            None => self.sink.write(&name, &prepared)?,
+        }
+        if let Some(max_dim) = self.thumbnail {
+            let thumbnail = DynamicImage::ImageRgba8(make_thumbnail(&img.to_rgba8(), max_dim));
+            self.sink.write(&thumbnail_name(&name), &self.format.prepare(&thumbnail, self.background))?;
+        }
+        if self.world_file {
+            let world_file = georef::world_file_text((grid.cellsize, grid.cellsize_y), grid.xllcorner, grid.yllcorner, grid.height);
+            self.sink.write_text(&georef::world_file_name(&name), &world_file)?;
+            if let Some(prj) = self.prj {
+                self.sink.write_text(&georef::prj_file_name(&name), prj)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Renders every output image for one elevation grid and writes them through a sink.
+/// # Arguments
+/// * `grid` - The elevation grid to render.
+/// * `args` - The parsed command-line options.
+/// * `timestamp` - The tag appended to every output name, see `OutputTag::Timestamp`.
+/// * `sink` - Where the produced images are written.
+///
+/// With `--progressive`, a subsampled preview of the main images is written first
+/// under the same names and then overwritten by the full-resolution result.
+pub fn render(grid: &Grid, args: &Args, timestamp: &str, sink: &mut dyn OutputSink) -> Result<()> {
+    render_with(grid, args, &OutputTag::Timestamp(timestamp.to_string()), sink)
+}
+
+/// Same as `render`, naming the outputs after `tag`.
+pub fn render_with(grid: &Grid, args: &Args, tag: &OutputTag, sink: &mut dyn OutputSink) -> Result<()> {
+    let mut writer = Writer::new(sink, args)?;
+
+    if args.progressive {
+        let factor = grid.width.max(grid.height).div_ceil(args.preview_size.max(1));
+        if factor > 1 {
+            let preview = grid.subsample(factor);
+            debug!("Writing {}x{} preview", preview.width, preview.height);
+            render_images(&preview, args, tag, &mut writer, false)?;
+        }
+    }
+    render_images(grid, args, tag, &mut writer, true)
+}
+
+/// The grayscale hillshade of the grid as written to `hillshade_gray`: adaptive, `--multiscale` or
+/// single-scale, then masked on flats and darkened by cast shadows when asked for. The RGB
+/// hillshades are blended from it, and `--hillshade-only` writes it alone.
+fn gray_hillshade(grid: &Grid, args: &Args) -> GrayImage {
+    let (data, width, height, cellsize) = (&grid.data, grid.width, grid.height, (grid.cellsize, grid.cellsize_y));
+    let mut shade = match (args.adaptive_hillshade, args.multiscale) {
+        (Some(window), _) => relief::adaptive_hillshade_gray(data, width, height, cellsize, AZIMUTH, ALTITUDE, window, args.ambient, args.slope_algo),
+        (None, Some(sigma)) => relief::multiscale_hillshade(data, width, height, cellsize, AZIMUTH, ALTITUDE, args.ambient, args.slope_algo, sigma, args.multiscale_blend),
+        (None, None) => hillshade_gray_with(data, width, height, cellsize, AZIMUTH, ALTITUDE, args.ambient, args.slope_algo),
+    };
+    if let Some(threshold) = args.mask_hillshade_by_slope {
+        let slope = relief::slope_degrees_with(data, width, height, cellsize, args.slope_algo);
+        relief::mask_flat_shading(&mut shade, &slope, threshold);
+    }
+    if args.shadows {
+        let mask = relief::cast_shadows(data, width, height, cellsize, AZIMUTH, ALTITUDE);
+        relief::apply_shadows(&mut shade, &mask);
+    }
+    shade
+}
+
+/// Renders the image products of a grid.
+/// `full_resolution` is false for previews, which skip the gradient vector field (its window needs
+/// the full grid), the viewshed (its observer is a cell of the full grid) and the map tiles.
+fn render_images(grid: &Grid, args: &Args, tag: &OutputTag, writer: &mut Writer, full_resolution: bool) -> Result<()> {
+    let (data_elevation, width, height, cell_size) = (&grid.data, grid.width, grid.height, (grid.cellsize, grid.cellsize_y));
+
+    if args.hillshade_only {
+        let shade = gray_hillshade(grid, args);
+        writer.write(&tag.file("hillshade_gray", "png"), &DynamicImage::ImageLuma8(shade), grid)?;
+        info!("Hillshade image saved as hillshade_gray.png");
+        return Ok(());
+    }
+
+    let value_range = value_range(data_elevation, args)?;
+    if args.gamma <= 0.0 {
+        bail!("--gamma must be positive, got {}", args.gamma);
+    }
+
+    // Generate grayscale image
+    let started = Instant::now();
+    let image_gray = if args.bit_depth == 16 {
+        DynamicImage::ImageLuma16(data_to_grayscale16(data_elevation, width, height))
+    } else if args.equalize {
+        DynamicImage::ImageLuma8(color::data_to_grayscale_equalized(data_elevation, width, height))
+    } else {
+        let gray = data_to_grayscale(data_elevation.clone(), width, height, args.nodata_color.map(to_gray), value_range, args.gamma)
+            .map_err(|e| anyhow!("Failed to create grayscale image: {}", e))?;
+        DynamicImage::ImageLuma8(gray)
+    };
+    debug!("Grayscale rendered in {:?}", started.elapsed());
+    writer.write(&tag.file("output", "png"), &image_gray, grid)?;
+    info!("Image saved as output.png");
+
+    // Generate RGB image
+    let started = Instant::now();
+    let gradient = color::gradient_by_name(&args.gradient).ok_or_else(|| anyhow!("Unknown gradient {}", args.gradient))?;
+    let mut img_rgb = match &args.color_ramp {
+        Some(path) => {
+            let mut ramp = color::load_color_ramp(path).map_err(|e| anyhow!("Failed to load color ramp: {}", e))?;
+            if let (Some(nodata), false) = (args.nodata_color, ramp.iter().any(|(z, _)| z.is_nan())) {
+                ramp.push((f32::NAN, nodata.0));
+            }
+            color::rgb_with_ramp(data_elevation, width, height, &ramp)
+        }
+        None => rgb_with(data_elevation.clone(), width, height, args.nodata_color, value_range, gradient.as_ref(), args.reverse_gradient)
+            .map_err(|e| anyhow!("Failed to create RGB image: {}", e))?,
+    };
+    if let Some(sea_level) = args.sea_level {
+        color::overlay_water(&mut img_rgb, data_elevation, width, height, sea_level, color::WATER_COLOR);
+    }
+    debug!("RGB rendered in {:?}", started.elapsed());
+    let mut figure = img_rgb.clone();
+    annotate_figure(&mut figure, args, data_elevation, grid.cellsize);
+    if let (Some(corner), Some((min_val, max_val))) = (args.colorbar, value_range.or_else(|| elevation_range(data_elevation))) {
+        // a reversed gradient puts the lowest elevation at the top of the strip
+        let (bottom, top) = if args.reverse_gradient { (max_val, min_val) } else { (min_val, max_val) };
+        annotate::overlay_colorbar(&mut figure, gradient.as_ref(), bottom, top, corner);
+    }
+    writer.write(&tag.rgb_file(&args.gradient), &DynamicImage::ImageRgba8(figure), grid)?;
+    info!("Image saved as output_rgb.png");
+
+    if let Some(band_size) = args.band_size {
+        if band_size <= 0.0 || !band_size.is_finite() {
+            bail!("--band-size must be a positive number of meters, got {}", band_size);
+        }
+        let tinted = color::hypsometric_tint(data_elevation, width, height, band_size, gradient.as_ref(), args.reverse_gradient);
+        writer.write(&tag.file("hypsometric", "png"), &DynamicImage::ImageRgba8(tinted), grid)?;
+        info!("Hypsometric image saved as hypsometric.png");
+    }
+
+    if args.curvature {
+        let curvature = morphometry::curvature_map(data_elevation, width, height, cell_size);
+        writer.write(&tag.file("curvature", "png"), &DynamicImage::ImageRgba8(curvature), grid)?;
+        info!("Curvature image saved as curvature.png");
+    }
+
+    if args.flow_accumulation {
+        let flow = hydrology::flow_accumulation(data_elevation, width, height);
+        writer.write(&tag.file("flow_accumulation", "png"), &DynamicImage::ImageLuma8(flow), grid)?;
+        info!("Flow accumulation image saved as flow_accumulation.png");
+    }
+
+    if args.terrain_rgb {
+        let encoded = color::terrain_rgb(data_elevation, width, height);
+        writer.write(&tag.file("terrain_rgb", "png"), &DynamicImage::ImageRgba8(encoded), grid)?;
+        info!("Terrain-RGB image saved as terrain_rgb.png");
+    }
+
+    if args.roughness {
+        let roughness = morphometry::roughness_map(data_elevation, width, height);
+        writer.write(&tag.file("roughness", "png"), &DynamicImage::ImageLuma8(roughness), grid)?;
+        info!("Roughness image saved as roughness.png");
+    }
+
+    if args.slope_classes {
+        let classes = morphometry::slope_classified(data_elevation, width, height, cell_size, &args.slope_thresholds);
+        writer.write(&tag.file("slope_classes", "png"), &DynamicImage::ImageRgba8(classes), grid)?;
+        info!("Slope classes image saved as slope_classes.png");
+    }
+
+    if let Some(radius) = args.sky_view {
+        let svf = morphometry::sky_view_factor(data_elevation, width, height, cell_size, SKY_VIEW_DIRECTIONS, radius);
+        writer.write(&tag.file("sky_view", "png"), &DynamicImage::ImageLuma8(svf), grid)?;
+        info!("Sky-view factor image saved as sky_view.png");
+    }
+
+    if let (Some((x, y, eye_height)), true) = (args.viewshed, full_resolution) {
+        if x >= width || y >= height || data_elevation[(y * width + x) as usize].is_nan() {
+            bail!("The --viewshed observer ({}, {}) is outside the grid or on NoData", x, y);
+        }
+        let view = morphometry::viewshed(data_elevation, width, height, cell_size, x, y, eye_height);
+        writer.write(&tag.file("viewshed", "png"), &DynamicImage::ImageLuma8(view), grid)?;
+        info!("Viewshed image saved as viewshed.png");
+    }
+
+    if let (Some((x0, y0, x1, y1)), true) = (args.transect, full_resolution) {
+        if x0.max(x1) >= width || y0.max(y1) >= height {
+            bail!("The --transect ({}, {}) to ({}, {}) leaves the {}x{} grid", x0, y0, x1, y1, width, height);
+        }
+        let samples = morphometry::transect(data_elevation, width, height, cell_size, x0, y0, x1, y1);
+        let csv: String = std::iter::once("distance,elevation\n".to_string())
+            .chain(samples.iter().map(|(distance, elevation)| format!("{},{}\n", distance, elevation)))
+            .collect();
+        writer.sink.write_text(&tag.file("transect", "csv"), &csv)?;
+        info!("Transect profile saved as transect.csv");
+    }
+
+    if args.swiss_relief {
+        let relief = relief::swiss_relief(data_elevation, width, height, cell_size, AZIMUTH, ALTITUDE);
+        writer.write(&tag.file("swiss_relief", "png"), &DynamicImage::ImageRgba8(relief), grid)?;
+        info!("Swiss relief image saved as swiss_relief.png");
+    }
+
+    // create a hillshade image
+    let started = Instant::now();
+    let hillshade_gray = gray_hillshade(grid, args);
+    let mut hillshade_rgb = match args.blend {
+        Some(mode) => relief::composite_relief_with(&img_rgb, &hillshade_gray, args.shade_opacity, mode),
+        None => blend_hillshade(&img_rgb, &hillshade_gray),
+    };
+    debug!("Hillshade rendered in {:?}", started.elapsed());
+
+    if let (Some(dir), true) = (&args.tiles, full_resolution) {
+        tiles::write_tiles(&hillshade_rgb, args.tile_size, dir).map_err(|e| anyhow!("Failed to write tiles: {}", e))?;
+        info!("Map tiles saved in {}", dir);
+    }
+
+    let contour_interval = args.contours.or_else(|| {
+        let (min, max) = elevation_range(data_elevation)?;
+        args.contour_count.map(|count| contours::nice_interval(min, max, count))
+    });
+    if let Some(interval) = contour_interval {
+        if interval <= 0.0 {
+            bail!("--contours needs a positive interval, got {}", interval);
+        }
+        let mut map = hillshade_rgb.clone();
+        contours::draw_labeled_contours(&mut map, data_elevation, width, height, interval, args.contour_label_every);
+        annotate_figure(&mut map, args, data_elevation, grid.cellsize);
+        writer.write(&tag.file("contours", "png"), &DynamicImage::ImageRgba8(map), grid)?;
+        info!("Contour map saved as contours.png");
+    }
+
+    annotate_figure(&mut hillshade_rgb, args, data_elevation, grid.cellsize);
+
+    if let Some(strength) = args.shade_overlay {
+        let overlay = relief::hillshade_alpha(&hillshade_gray, strength.unwrap_or(1.0));
+        writer.write(&tag.file("shade_overlay", "png"), &DynamicImage::ImageRgba8(overlay), grid)?;
+        info!("Shade overlay saved as shade_overlay.png");
+    }
+
+    //  save the hillshade images
+    writer.write(&tag.file("hillshade_gray", "png"), &DynamicImage::ImageLuma8(hillshade_gray), grid)?;
+    info!("Hillshade image saved as hillshade_gray.png");
+
+    // save the hillshade image in RGB
+    writer.write(&tag.file("hillshade_rgb", "png"), &DynamicImage::ImageRgba8(hillshade_rgb.clone()), grid)?;
+    info!("Hillshade image saved as hillshade_rgb.png");
+
+    if full_resolution {
+        let gradients = if args.sobel_gradients {
+            compute_gradients_sobel(data_elevation, width, height, cell_size)
+        } else {
+            compute_gradients(data_elevation, width, height, 61)
+        };
+        if args.gradient_magnitude {
+            let magnitude = gradient_magnitude_image(&gradients, width, height);
+            writer.write(&tag.file("gradient_magnitude", "png"), &DynamicImage::ImageLuma8(magnitude), grid)?;
+            info!("Gradient magnitude image saved as gradient_magnitude.png");
+        }
+        let mut grad_img = hillshade_rgb;
+        draw_vector_field_with(&mut grad_img, &gradients, width, height, args.aa_arrows);
+        writer.write(&tag.file("hillshade_rgb_gradient", "png"), &DynamicImage::ImageRgba8(grad_img), grid)?;
+        info!("Hillshade image saved as hillshade_grad_img.png");
+    }
+
+    Ok(())
+}
+
+/// Resolves `--normalize` and `--vmin`/`--vmax` into a fixed value range. `--vmin`/`--vmax` override
+/// the bounds chosen by the normalization mode, missing bounds falling back to the data.
+pub(crate) fn value_range(data: &[f32], args: &Args) -> Result<Option<(f32, f32)>> {
+    let normalized = args.normalize.value_range(data);
+    if args.vmin.is_none() && args.vmax.is_none() {
+        return Ok(normalized);
+    }
+    let (min, max) = normalized.or_else(|| elevation_range(data)).unwrap_or((0.0, 0.0));
+    let (vmin, vmax) = (args.vmin.unwrap_or(min), args.vmax.unwrap_or(max));
+    if vmin >= vmax {
+        bail!("--vmin ({}) must be lower than --vmax ({})", vmin, vmax);
+    }
+    Ok(Some((vmin, vmax)))
+}
+
+/// Draws the map annotations requested on the command line (elevation labels, scale bar, north arrow) onto a color figure.
+fn annotate_figure(image: &mut RgbaImage, args: &Args, data: &Vec<f32>, cellsize: f32) {
+    if let Some(step) = args.label_grid {
+        let (width, height) = image.dimensions();
+        annotate::overlay_elevation_labels(image, data, width, height, step);
+    }
+    if let Some(length) = args.scale_bar {
+        annotate::draw_scale_bar(image, cellsize, length.unwrap_or(0.0));
+    }
+    if let Some(corner) = args.north_arrow {
+        let size = annotate::NORTH_ARROW_SIZE;
+        let (x, y) = corner.position(image, size, size);
+        annotate::draw_north_arrow(image, x, y, size);
+    }
+}
+
+/// Converts a NoData color to the gray level used in the grayscale image (ITU-R BT.601 luma).
+fn to_gray(color: Rgba<u8>) -> Luma<u8> {
+    let [r, g, b, _] = color.0;
+    Luma([(0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32).round() as u8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::MemorySink;
+    use clap::Parser;
+    use std::fs;
+
+    fn write_temp_asc(name: &str, content: &str) -> String {
+        let file_path = std::env::temp_dir().join(name);
+        fs::write(&file_path, content).expect("Failed to write dummy ASC file");
+        file_path.to_str().unwrap().to_string()
+    }
+
+    /// Builds a smooth synthetic ramp large enough for the 61-cell gradient window.
+    fn ramp_grid(width: u32, height: u32, cellsize: f32) -> Grid {
+        let data = (0..width * height).map(|i| (i % width + i / width) as f32).collect();
+        Grid { data, width, height, cellsize, cellsize_y: cellsize, xllcorner: 925000.0, yllcorner: 6224000.0 }
+    }
+
+    #[test]
+    /// It checks that every product of the pipeline reaches the sink under its file name.
+    fn test_render_into_memory_sink() {
+        let grid = ramp_grid(64, 64, 1.0);
+        let args = Args::parse_from(["dem"]);
+        let mut sink = MemorySink::new();
+        render(&grid, &args, "test", &mut sink).unwrap();
+
+        let mut names: Vec<&String> = sink.images.keys().collect();
+        names.sort();
+        assert_eq!(names, vec![
+            "hillshade_gray_test.png",
+            "hillshade_rgb_gradient_test.png",
+            "hillshade_rgb_test.png",
+            "output_rgb_test_turbo.png",
+            "output_test.png",
+        ]);
+        let gray = &sink.images["output_test.png"];
+        assert_eq!((gray.width(), gray.height()), (64, 64));
+        assert!(sink.texts.is_empty());
+    }
+
+    #[test]
+    /// It checks that --world-file writes a world file with the grid cellsize and a .prj with the CRS.
+    fn test_render_world_file_and_prj() {
+        let grid = ramp_grid(64, 64, 0.5);
+        let args = Args::parse_from(["dem", "--world-file", "--crs", "EPSG:2154"]);
+        let mut sink = MemorySink::new();
+        render(&grid, &args, "test", &mut sink).unwrap();
+
+        let world_file = &sink.texts["output_rgb_test_turbo.pgw"];
+        let lines: Vec<f64> = world_file.lines().map(|l| l.parse().unwrap()).collect();
+        assert_eq!(lines[0], 0.5);
+        assert_eq!(lines[3], -0.5);
+        let prj = &sink.texts["output_rgb_test_turbo.prj"];
+        assert!(prj.contains(r#"AUTHORITY["EPSG","2154"]"#));
+        // one world file and one prj per image
+        assert_eq!(sink.texts.len(), 2 * sink.images.len());
+    }
+
+    #[test]
+    /// It checks that the dx and dy of the header reach the world files: dy sets the pixel height and the top edge.
+    fn test_world_file_rectangular_cells() {
+        let row: Vec<String> = (0..64).map(|i| i.to_string()).collect();
+        let rows = vec![row.join(" "); 64].join("\n");
+        let content = format!("ncols 64\nnrows 64\nxllcorner 1000\nyllcorner 5000\ndx 10\ndy 20\n{}\n", rows);
+        let path = write_temp_asc("world_file_dxdy.asc", &content);
+        let (grid, _) = load(&Args::parse_from(["dem", path.as_str()])).unwrap();
+        let mut sink = MemorySink::new();
+        render(&grid, &Args::parse_from(["dem", "--world-file"]), "test", &mut sink).unwrap();
+
+        let lines: Vec<f64> = sink.texts["output_rgb_test_turbo.pgw"].lines().map(|l| l.parse().unwrap()).collect();
+        assert_eq!(lines, vec![10.0, 0.0, 0.0, -20.0, 1005.0, 5000.0 + 64.0 * 20.0 - 10.0]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    /// It checks that an unknown CRS is reported instead of writing a wrong .prj.
+    fn test_render_unknown_crs() {
+        let grid = ramp_grid(64, 64, 1.0);
+        let args = Args::parse_from(["dem", "--world-file", "--crs", "EPSG:1"]);
+        let mut sink = MemorySink::new();
+        assert!(render(&grid, &args, "test", &mut sink).is_err());
+    }
+
+    #[test]
+    /// It checks that a dry run lists the outputs, with the requested ones, without creating any file.
+    fn test_dry_run_writes_nothing() {
+        let input = write_temp_asc("dry_run_input.asc", "ncols 2\nnrows 2\nxllcorner 0\nyllcorner 0\ncellsize 1\nNODATA_value -9999\n1 2\n3 4\n");
+        let output_dir = std::env::temp_dir().join("dem_dry_run_output");
+        let _ = fs::remove_dir_all(&output_dir);
+        fs::create_dir_all(&output_dir).unwrap();
+        let args = Args::parse_from(["dem", &input, "--curvature", "--world-file", "--dry-run"]);
+
+        let paths = dry_run(&args, &output_dir).unwrap();
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 0);
+        assert!(paths.iter().all(|path| path.parent() == Some(output_dir.as_path())));
+        let names: Vec<String> = paths.iter().map(|path| path.file_name().unwrap().to_string_lossy().into_owned()).collect();
+        assert!(names.iter().any(|name| name.starts_with("curvature_") && name.ends_with(".png")));
+        assert!(names.iter().any(|name| name.starts_with("output_rgb_") && name.ends_with("_turbo.pgw")));
+        assert!(!names.iter().any(|name| name.starts_with("roughness_")));
+    }
+
+    #[test]
+    /// It checks that the outputs planned for a dry run are exactly the files a real run writes.
+    fn test_planned_outputs_match_run() {
+        let row: Vec<String> = (0..64).map(|i| i.to_string()).collect();
+        let content = format!("ncols 64\nnrows 64\nxllcorner 0\nyllcorner 0\ncellsize 1\n{}\n", vec![row.join(" "); 64].join("\n"));
+        let input = write_temp_asc("planned_outputs.asc", &content);
+        let everything = [
+            "--band-size", "10", "--curvature", "--flow-accumulation", "--terrain-rgb", "--roughness", "--slope-classes",
+            "--sky-view", "3", "--viewshed", "2,2,1.5", "--transect", "0,0,10,10", "--swiss-relief", "--contours", "5",
+            "--shade-overlay", "--gradient-magnitude", "--diff", input.as_str(), "--world-file", "--crs", "EPSG:2154",
+            "--thumbnail", "16", "--metadata-json",
+        ];
+        let option_sets: [&[&str]; 4] = [
+            &[],
+            &everything,
+            &["--hillshade-only", "--world-file", "--transect", "0,0,10,10", "--metadata-json"],
+            &["--output-format", "jpeg", "--gradient", "viridis", "--progressive", "--preview-size", "16"],
+        ];
+        for options in option_sets {
+            let args = Args::parse_from(["dem", input.as_str(), "--no-timestamp"].iter().chain(options));
+            let mut sink = MemorySink::new();
+            run(&args, &mut sink).unwrap();
+            let mut written: Vec<&String> = sink.images.keys().chain(sink.texts.keys()).collect();
+            written.sort();
+            let mut planned = planned_outputs(&args, &OutputTag::from_args(&args));
+            planned.sort();
+            assert_eq!(written, planned.iter().collect::<Vec<_>>(), "options {:?}", options);
+        }
+        fs::remove_file(&input).unwrap();
+    }
+
+    #[test]
+    /// It checks that a batch dry run lists the outputs of every tile in its subfolder, and an animation its GIF.
+    fn test_dry_run_batch_and_animate() {
+        let root = std::env::temp_dir().join("dem_dry_run_modes");
+        let _ = fs::remove_dir_all(&root);
+        let tiles = root.join("tiles");
+        fs::create_dir_all(&tiles).unwrap();
+        let content = "ncols 2\nnrows 2\nxllcorner 0\nyllcorner 0\ncellsize 1\n1 2\n3 4\n";
+        for tile in ["a.asc", "b.asc"] {
+            fs::write(tiles.join(tile), content).unwrap();
+        }
+        let output_dir = root.join("out");
+
+        let args = Args::parse_from(["dem", "--batch", tiles.to_str().unwrap(), "--no-timestamp", "--dry-run"]);
+        let paths = dry_run(&args, &output_dir).unwrap();
+        assert!(paths.contains(&output_dir.join("a").join("a_hillshade_gray.png")));
+        assert!(paths.contains(&output_dir.join("b").join("b_hillshade_gray.png")));
+        assert_eq!(paths.len(), 10);
+
+        let (a, b) = (tiles.join("a.asc"), tiles.join("b.asc"));
+        let args = Args::parse_from(["dem", "--animate", a.to_str().unwrap(), b.to_str().unwrap(), "--timestamp-format", "x", "--dry-run"]);
+        assert_eq!(dry_run(&args, &output_dir).unwrap(), vec![output_dir.join("animation_x.gif")]);
+        let args = Args::parse_from(["dem", "--animate", a.to_str().unwrap(), b.to_str().unwrap(), "--no-timestamp", "--dry-run"]);
+        assert_eq!(dry_run(&args, &output_dir).unwrap(), vec![output_dir.join("a_animation.gif")]);
+        assert!(!output_dir.exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    /// It checks that --hillshade-only writes the grayscale hillshade and nothing else.
+    fn test_render_hillshade_only() {
+        let grid = ramp_grid(64, 64, 1.0);
+        let args = Args::parse_from(["dem", "--hillshade-only", "--curvature"]);
+        let mut sink = MemorySink::new();
+        render(&grid, &args, "test", &mut sink).unwrap();
+
+        let names: Vec<&String> = sink.images.keys().collect();
+        assert_eq!(names, vec!["hillshade_gray_test.png"]);
+        let shade = &sink.images["hillshade_gray_test.png"];
+        assert_eq!((shade.width(), shade.height()), (64, 64));
+
+        // the same shading options give the same gray hillshade as a full run
+        let options = ["--shadows", "--mask-hillshade-by-slope", "30", "--adaptive-hillshade", "4"];
+        let mut only = MemorySink::new();
+        render(&grid, &Args::parse_from(["dem", "--hillshade-only"].iter().chain(&options)), "test", &mut only).unwrap();
+        let mut full = MemorySink::new();
+        render(&grid, &Args::parse_from(["dem"].iter().chain(&options)), "test", &mut full).unwrap();
+        assert_eq!(only.images["hillshade_gray_test.png"], full.images["hillshade_gray_test.png"]);
+    }
+
+    #[test]
+    /// It checks that a zero, negative or NaN --band-size is reported instead of collapsing or flipping the bands.
+    fn test_render_invalid_band_size() {
+        let grid = ramp_grid(64, 64, 1.0);
+        for invalid in ["0", "-50", "NaN"] {
+            let args = Args::parse_from(["dem".to_string(), format!("--band-size={}", invalid)]);
+            assert!(render(&grid, &args, "test", &mut MemorySink::new()).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    /// It checks that --ambient takes a share of light from 0 to 1: larger values would wash out the
+    /// hillshade and negative ones invert it.
+    fn test_ambient_range() {
+        for valid in ["0", "0.25", "1"] {
+            assert!(Args::try_parse_from(["dem", "--ambient", valid]).is_ok(), "{}", valid);
+        }
+        for invalid in ["2", "-1", "NaN", "abc"] {
+            assert!(Args::try_parse_from(["dem", "--ambient", invalid]).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    /// It checks that --tile-size 0 is rejected by the CLI parser, before any image is rendered.
+    fn test_tile_size_range() {
+        assert!(Args::try_parse_from(["dem", "--tile-size", "0"]).is_err());
+        assert_eq!(Args::try_parse_from(["dem", "--tile-size", "1"]).unwrap().tile_size, 1);
+    }
+
+    #[test]
+    /// It checks that --no-timestamp names the outputs after the sanitized input stem.
+    fn test_no_timestamp_uses_input_stem() {
+        let grid = ramp_grid(64, 64, 1.0);
+        let args = Args::parse_from(["dem", "data/foo.asc", "--no-timestamp", "--curvature"]);
+        let tag = OutputTag::from_args(&args);
+        assert_eq!(tag, OutputTag::Stem("foo".to_string()));
+        let mut sink = MemorySink::new();
+        render_with(&grid, &args, &tag, &mut sink).unwrap();
+
+        let mut names: Vec<&String> = sink.images.keys().collect();
+        names.sort();
+        assert_eq!(names, vec![
+            "foo.png",
+            "foo_curvature.png",
+            "foo_hillshade_gray.png",
+            "foo_hillshade_rgb.png",
+            "foo_hillshade_rgb_gradient.png",
+            "foo_rgb.png",
+        ]);
+        assert_eq!(tag.rgb_file("viridis"), "foo_rgb_viridis.png");
+
+        let args = Args::parse_from(["dem", "my map (v2).asc", "--no-timestamp"]);
+        assert_eq!(OutputTag::from_args(&args), OutputTag::Stem("my_map__v2_".to_string()));
+    }
+
+    /// Sink remembering the order and width of every written image.
+    #[derive(Default)]
+    struct RecordingSink {
+        writes: Vec<(String, u32)>,
+    }
+
+    impl OutputSink for RecordingSink {
+        fn write(&mut self, name: &str, img: &DynamicImage) -> Result<()> {
+            self.writes.push((name.to_string(), img.width()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    /// It checks that progressive mode writes a coarse preview before the full-resolution image.
+    fn test_render_progressive_preview_first() {
+        let grid = ramp_grid(64, 64, 1.0);
+        let args = Args::parse_from(["dem", "--progressive", "--preview-size", "16"]);
+        let mut sink = RecordingSink::default();
+        render(&grid, &args, "test", &mut sink).unwrap();
+
+        let rgb_writes: Vec<(usize, u32)> = sink.writes.iter().enumerate()
+            .filter(|(_, (name, _))| name == "output_rgb_test_turbo.png")
+            .map(|(i, (_, width))| (i, *width))
+            .collect();
+        assert_eq!(rgb_writes.len(), 2);
+        let (preview_index, preview_width) = rgb_writes[0];
+        let (full_index, full_width) = rgb_writes[1];
+        assert!(preview_index < full_index);
+        assert_eq!(preview_width, 16);
+        assert_eq!(full_width, 64);
+        // the last write of every name is the full-resolution image
+        assert!(sink.writes.iter().rev().take(5).all(|(_, width)| *width == 64));
+    }
+
+    #[test]
+    /// It checks that --info describes JSON, XYZ and FLT inputs by their grid instead of parsing them as ASC.
+    fn test_header_info_formats() {
+        let info = |path: &str| header_info(&Args::parse_from(["dem", path, "--info"])).unwrap();
+        let json = write_temp_asc("header_info.json", r#"{"ncols": 3, "nrows": 2, "cellsize": 5, "xll": 10, "yll": 20, "data": [[1, 2, 3], [4, 5, 6]]}"#);
+        let header = info(&json);
+        assert_eq!((header.ncols, header.nrows, header.cellsize, header.xllcorner), (3, 2, Some(5.0), 10.0));
+
+        let xyz = write_temp_asc("header_info.xyz", "0.5 0.5 1\n1.5 0.5 2\n0.5 1.5 3\n1.5 1.5 4\n");
+        let header = info(&xyz);
+        assert_eq!((header.ncols, header.nrows, header.cellsize), (2, 2, Some(1.0)));
+
+        // the binary .flt data is never read as text, only its .hdr sidecar
+        let flt = std::env::temp_dir().join("header_info.flt");
+        fs::write(&flt, [0xffu8; 16]).unwrap();
+        fs::write(flt.with_extension("hdr"), "NCOLS 2\nNROWS 2\nXLLCORNER 10\nYLLCORNER 20\nCELLSIZE 5\nNODATA_VALUE -9999\n").unwrap();
+        let header = info(flt.to_str().unwrap());
+        assert_eq!((header.ncols, header.nrows, header.cellsize, header.nodata_value), (2, 2, Some(5.0), Some(-9999.0)));
+
+        for path in [json, xyz, flt.to_string_lossy().into_owned(), flt.with_extension("hdr").to_string_lossy().into_owned()] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    /// It checks that a query prints the elevation of the cell, NoData for masked cells and rejects cells off the grid.
+    fn test_query_cell() {
+        let mut grid = ramp_grid(4, 3, 1.0);
+        grid.data[5] = f32::NAN;
+        assert_eq!(query_cell(&grid, 2, 1).unwrap(), "Cell 2,1: 3");
+        assert_eq!(query_cell(&grid, 1, 1).unwrap(), "Cell 1,1: NoData");
+        assert!(query_cell(&grid, 4, 0).is_err());
+        assert_eq!(grid.cell_at(925002.5, 6224000.5), Some((2, 2)));
+    }
+
+    #[test]
+    /// It checks that --cellsize supplies the cellsize missing from the header, which otherwise defaults to 1.
+    fn test_load_cellsize_flag() {
+        let content = "ncols 2\nnrows 2\nxllcorner 0\nyllcorner 0\nnodata_value -9999\n1 2\n3 4\n";
+        let path = write_temp_asc("missing_cellsize.asc", content);
+        let (grid, report) = load(&Args::parse_from(["dem", path.as_str(), "--cellsize", "2.0"])).unwrap();
+        assert_eq!((grid.cellsize, grid.cellsize_y), (2.0, 2.0));
+        assert!(report.is_clean());
+        let (grid, report) = load(&Args::parse_from(["dem", path.as_str()])).unwrap();
+        assert_eq!(grid.cellsize, 1.0);
+        assert!(!report.is_clean());
+        for invalid in ["0", "-2", "NaN", "inf"] {
+            assert!(Args::try_parse_from(["dem", path.as_str(), "--cellsize", invalid]).is_err(), "{}", invalid);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    /// It checks that --ignore-values masks the listed elevations and keeps everything else.
+    fn test_load_ignore_values() {
+        let content = "ncols 3\nnrows 2\nxllcorner 0\nyllcorner 0\ncellsize 1\nnodata_value -9999\n0 2 0\n-9999 5 0\n";
+        let path = write_temp_asc("ignore_values.asc", content);
+        let args = Args::parse_from(["dem", path.as_str(), "--ignore-values", "0"]);
+        let (grid, _) = load(&args).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let data = &grid.data;
+        assert_eq!((grid.width, grid.height), (3, 2));
+        assert!(data[0].is_nan());
+        assert!(data[2].is_nan());
+        assert!(data[5].is_nan());
+        assert!(data[3].is_nan()); // header nodata still masked
+        assert_eq!(data[1], 2.0);
+        assert_eq!(data[4], 5.0);
+    }
+
+    #[test]
+    /// It checks that --metadata-json records the grid and every written file in a JSON sidecar.
+    fn test_run_metadata_json() {
+        let rows: Vec<String> = (0..64).map(|y| (0..65).map(|x| (x + y).to_string()).collect::<Vec<_>>().join(" ")).collect();
+        let content = format!("ncols 65\nnrows 64\nxllcorner 0\nyllcorner 0\ncellsize 2\nnodata_value -9999\n{}\n", rows.join("\n"));
+        let path = write_temp_asc("metadata.asc", &content);
+        let args = Args::parse_from(["dem", path.as_str(), "--metadata-json", "--world-file"]);
+        let mut sink = MemorySink::new();
+        run(&args, &mut sink).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let (_, json) = sink.texts.iter().find(|(name, _)| name.ends_with(".json")).expect("metadata written");
+        let metadata: ProcessingMetadata = serde_json::from_str(json).unwrap();
+        assert_eq!((metadata.width, metadata.height, metadata.cellsize), (65, 64, 2.0));
+        assert_eq!((metadata.min_elevation, metadata.max_elevation), (Some(0.0), Some(127.0)));
+        assert_eq!(metadata.input, path);
+        assert_eq!(metadata.gradient, "turbo");
+        assert_eq!(metadata.outputs.len(), sink.images.len() + sink.texts.len() - 1);
+        assert!(sink.images.keys().all(|name| metadata.outputs.contains(name)));
+    }
+}
@@ -0,0 +1,144 @@
+/// Replaces every elevation exactly equal to one of `values` with NaN.
+/// # Arguments
+/// * `data` - The elevation data, modified in place.
+/// * `values` - Magic elevations to treat as NoData (e.g. `0` for unmeasured water).
+///
+/// The comparison is the same exact equality `asc_to_image` uses for the header `nodata_value`,
+/// so cells already masked by the header stay NaN.
+pub fn mask_values(data: &mut [f32], values: &[f32]) {
+    if values.is_empty() {
+        return;
+    }
+    for value in data.iter_mut() {
+        if values.contains(value) {
+            *value = f32::NAN;
+        }
+    }
+}
+
+/// Clamps every valid elevation into a range, e.g. to flatten erroneous spikes that would crush
+/// the color scale.
+/// # Arguments
+/// * `data` - The elevation data, modified in place.
+/// * `min` - The lowest elevation kept.
+/// * `max` - The highest elevation kept, at least `min`.
+///
+/// Out-of-range cells take the nearest bound instead of becoming NoData; NaN cells stay NaN.
+pub fn clamp_elevation(data: &mut Vec<f32>, min: f32, max: f32) {
+    for value in data.iter_mut().filter(|v| !v.is_nan()) {
+        *value = value.clamp(min, max);
+    }
+}
+
+/// Fills the depressions of a DEM so every cell can drain to the edge (Planchon-Darboux).
+/// # Arguments
+/// * `data` - The elevation data, modified in place.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+///
+/// Each pit is raised to the elevation of its lowest pour point, leaving flat areas that
+/// `hydrology::flow_direction_d8` routes to the lowest neighbor. The grid border and NaN cells
+/// are the outer boundary water spills to; NaN cells themselves are left untouched.
+pub fn fill_sinks(data: &mut Vec<f32>, width: u32, height: u32) {
+    let (w, h) = (width as i32, height as i32);
+    let neighbors = |x: i32, y: i32| {
+        (-1..=1)
+            .flat_map(move |dy| (-1..=1).map(move |dx| (x + dx, y + dy)))
+            .filter(move |&(nx, ny)| (nx, ny) != (x, y))
+    };
+    let inside = |nx: i32, ny: i32| nx >= 0 && ny >= 0 && nx < w && ny < h;
+
+    // Start with the water surface at +inf everywhere except on the boundary
+    let mut water: Vec<f32> = vec![f32::INFINITY; data.len()];
+    for y in 0..h {
+        for x in 0..w {
+            let idx = (y * w + x) as usize;
+            let on_boundary = neighbors(x, y).any(|(nx, ny)| !inside(nx, ny) || data[(ny * w + nx) as usize].is_nan());
+            if data[idx].is_nan() || on_boundary {
+                water[idx] = data[idx];
+            }
+        }
+    }
+
+    // Drain the surface until every cell sits on its terrain or on a neighbor's water level,
+    // sweeping alternately forward and backward so drainage propagates in both directions
+    let cells: Vec<(i32, i32)> = (0..h).flat_map(|y| (0..w).map(move |x| (x, y))).collect();
+    let mut forward = true;
+    loop {
+        let mut changed = false;
+        let order: Box<dyn Iterator<Item = &(i32, i32)>> = if forward { Box::new(cells.iter()) } else { Box::new(cells.iter().rev()) };
+        for &(x, y) in order {
+            let idx = (y * w + x) as usize;
+            if water[idx] <= data[idx] || data[idx].is_nan() {
+                continue;
+            }
+            for (nx, ny) in neighbors(x, y) {
+                let neighbor = water[(ny * w + nx) as usize];
+                if data[idx] >= neighbor {
+                    water[idx] = data[idx];
+                    changed = true;
+                    break;
+                }
+                if water[idx] > neighbor {
+                    water[idx] = neighbor;
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+        forward = !forward;
+    }
+    *data = water;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// A one-cell pit is raised to its lowest rim cell, and the rest of the DEM is left alone.
+    fn test_fill_sinks_single_pit() {
+        let (width, height) = (5, 5);
+        let mut data = vec![10.0; 25];
+        data[12] = 2.0;
+        data[7] = 7.0; // lowest cell of the rim, north of the pit
+        data[2] = 5.0; // where the rim spills out of the grid
+        data[17] = 8.0;
+        let original = data.clone();
+        fill_sinks(&mut data, width, height);
+        assert_eq!(data[12], 7.0);
+        for (i, (filled, raw)) in data.iter().zip(original.iter()).enumerate() {
+            if i != 12 {
+                assert_eq!(filled, raw, "cell {}", i);
+            }
+        }
+    }
+
+    #[test]
+    /// NaN cells stay NaN and let the cells next to them drain out.
+    fn test_fill_sinks_nan_boundary() {
+        let nan = f32::NAN;
+        let mut data = vec![
+            9.0, 9.0, 9.0, 9.0,
+            9.0, 1.0, nan, 9.0,
+            9.0, 9.0, 9.0, 9.0,
+        ];
+        fill_sinks(&mut data, 4, 3);
+        assert_eq!(data[5], 1.0);
+        assert!(data[6].is_nan());
+    }
+
+    #[test]
+    /// Spikes above the range take the maximum, pits the minimum, and NaN stays NaN.
+    fn test_clamp_elevation() {
+        let mut data = vec![30000.0, 120.0, f32::NAN, -500.0, 2000.0];
+        clamp_elevation(&mut data, 0.0, 2000.0);
+        assert_eq!(data[0], 2000.0);
+        assert_eq!(data[1], 120.0);
+        assert!(data[2].is_nan());
+        assert_eq!(data[3], 0.0);
+        assert_eq!(data[4], 2000.0);
+    }
+}
@@ -0,0 +1,46 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// Global switch used by `--quiet` to hide every progress bar.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Enables or disables progress bars for the whole program.
+/// # Arguments
+/// * `quiet` - When `true`, `row_progress` returns hidden bars.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+/// Creates a progress bar that is advanced once per processed image row.
+/// # Arguments
+/// * `rows` - The number of rows the loop will process.
+/// * `label` - A short name for the stage shown next to the bar.
+/// # Returns
+/// * A `ProgressBar` reporting elapsed time and ETA, or a hidden bar in quiet mode.
+///
+/// Callers should update it per row, never per pixel, to keep the inner loops fast.
+pub fn row_progress(rows: u32, label: &str) -> ProgressBar {
+    item_progress(rows as u64, label, "rows")
+}
+
+/// Creates a progress bar counting arbitrary work items (rows, tiles, ...).
+/// # Arguments
+/// * `count` - The number of items to process.
+/// * `label` - A short name for the stage shown next to the bar.
+/// * `unit` - The item name shown after the counter.
+pub fn item_progress(count: u64, label: &str, unit: &str) -> ProgressBar {
+    if QUIET.load(Ordering::Relaxed) {
+        return ProgressBar::hidden();
+    }
+    let bar = ProgressBar::new(count);
+    let template = format!(
+        "{{msg:>10}} [{{bar:40.cyan/blue}}] {{pos}}/{{len}} {}  elapsed {{elapsed_precise}}  eta {{eta_precise}}",
+        unit
+    );
+    let style = ProgressStyle::with_template(&template)
+        .expect("Invalid progress bar template")
+        .progress_chars("=> ");
+    bar.set_style(style);
+    bar.set_message(label.to_string());
+    bar
+}
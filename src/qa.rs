@@ -0,0 +1,75 @@
+use crate::AscHeader;
+
+/// Fraction of nodata cells above which the grid is reported as mostly empty.
+pub const HIGH_NODATA_FRACTION: f32 = 0.5;
+
+/// Data-quality warnings gathered while processing a DEM.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QaReport {
+    pub warnings: Vec<String>,
+}
+
+impl QaReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a warning.
+    pub fn warn(&mut self, message: impl Into<String>) {
+        self.warnings.push(message.into());
+    }
+
+    /// Returns `true` when no warning was recorded.
+    pub fn is_clean(&self) -> bool {
+        self.warnings.is_empty()
+    }
+
+    /// Appends the warnings of another report, prefixed with where they come from (e.g. a batch tile).
+    pub fn extend(&mut self, source: &str, other: QaReport) {
+        self.warnings.extend(other.warnings.into_iter().map(|w| format!("{}: {}", source, w)));
+    }
+
+    /// Prints every warning on stderr.
+    pub fn print(&self) {
+        for warning in &self.warnings {
+            eprintln!("QA warning: {}", warning);
+        }
+    }
+}
+
+/// Checks a freshly parsed grid for common data problems.
+/// # Arguments
+/// * `header` - The parsed ASC header.
+/// * `data` - The elevation data, with nodata cells already converted to NaN.
+/// # Returns
+/// * A `QaReport` warning about a defaulted cellsize or a high nodata fraction.
+pub fn check_grid(header: &AscHeader, data: &[f32]) -> QaReport {
+    let mut report = QaReport::new();
+    if header.cellsize.is_none() {
+        report.warn("no cellsize in the header, defaulting to 1.0 (set the true value with --cellsize)");
+    }
+    if !data.is_empty() {
+        let nodata = data.iter().filter(|v| v.is_nan()).count();
+        let fraction = nodata as f32 / data.len() as f32;
+        if fraction > HIGH_NODATA_FRACTION {
+            report.warn(format!("{:.1}% of the cells are nodata", fraction * 100.0));
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// It checks that a defaulted cellsize and a mostly empty grid are both reported.
+    fn test_check_grid_warnings() {
+        let header = AscHeader { ncols: 2, nrows: 2, cellsize: Some(5.0), ..Default::default() };
+        assert!(check_grid(&header, &[1.0, 2.0, f32::NAN, 4.0]).is_clean());
+
+        let header = AscHeader { cellsize: None, ..header };
+        let report = check_grid(&header, &[1.0, f32::NAN, f32::NAN, f32::NAN]);
+        assert_eq!(report.warnings.len(), 2);
+    }
+}
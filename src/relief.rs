@@ -0,0 +1,695 @@
+use clap::ValueEnum;
+use image::{GrayImage, Luma, Rgba, RgbaImage};
+
+use crate::filters::gaussian_smooth;
+use crate::progress;
+use crate::{blend_hillshade, elevation_range, hillshade_gray_with, illumination, illumination_with};
+
+/// Computes a cast-shadow mask by marching a ray from every cell toward the light source.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `cellsize` - The `(dx, dy)` size of each cell in the elevation data.
+/// * `azimuth` - The azimuth angle for the light source, clockwise from north.
+/// * `altitude` - The altitude angle for the light source.
+/// # Returns
+/// * A `GrayImage` where lit cells are 255 and shadowed cells are 0.
+///
+/// A cell is shadowed when any terrain along the ray toward the sun rises above the ray.
+/// NaN cells are left lit so they don't darken the hillshade, and NaN cells along a ray are ignored.
+pub fn cast_shadows(data: &Vec<f32>, width: u32, height: u32, cellsize: (f32, f32), azimuth: f32, altitude: f32) -> GrayImage {
+    let mut mask = GrayImage::from_pixel(width, height, Luma([255]));
+    let max_elevation = data.iter().cloned().filter(|v| !v.is_nan()).fold(f32::NEG_INFINITY, f32::max);
+    let azimuth_rad = azimuth.to_radians();
+    // Direction of the sun in pixel coordinates (north is up, so y decreases), scaled to a unit
+    // step: with rectangular cells one step covers a different ground distance in x and y
+    let (pixel_x, pixel_y) = (azimuth_rad.sin() / cellsize.0, -azimuth_rad.cos() / cellsize.1);
+    let pixels_per_distance = (pixel_x * pixel_x + pixel_y * pixel_y).sqrt();
+    let (step_x, step_y) = (pixel_x / pixels_per_distance, pixel_y / pixels_per_distance);
+    let rise_per_step = altitude.to_radians().tan() / pixels_per_distance;
+
+    for y in 0..height {
+        for x in 0..width {
+            let z0 = data[(y * width + x) as usize];
+            if z0.is_nan() {
+                continue;
+            }
+            let mut t = 1.0;
+            loop {
+                let ray_z = z0 + t * rise_per_step;
+                if ray_z > max_elevation {
+                    break;
+                }
+                let px = (x as f32 + t * step_x).round();
+                let py = (y as f32 + t * step_y).round();
+                if px < 0.0 || py < 0.0 || px >= width as f32 || py >= height as f32 {
+                    break;
+                }
+                let terrain = data[(py as u32 * width + px as u32) as usize];
+                if terrain > ray_z {
+                    mask.put_pixel(x, y, Luma([0]));
+                    break;
+                }
+                t += 1.0;
+            }
+        }
+    }
+    mask
+}
+
+/// Darkens a grayscale hillshade with a shadow mask by multiplying intensities.
+pub fn apply_shadows(shade: &mut GrayImage, mask: &GrayImage) {
+    for (pixel, m) in shade.pixels_mut().zip(mask.pixels()) {
+        pixel[0] = (pixel[0] as u16 * m[0] as u16 / 255) as u8;
+    }
+}
+
+/// Darkens the color channels of an RGB hillshade with a shadow mask, keeping alpha.
+pub fn apply_shadows_rgb(shade: &mut RgbaImage, mask: &GrayImage) {
+    for (pixel, m) in shade.pixels_mut().zip(mask.pixels()) {
+        for channel in 0..3 {
+            pixel[channel] = (pixel[channel] as u16 * m[0] as u16 / 255) as u8;
+        }
+    }
+}
+
+/// Turns a hillshade into a transparent shading layer, to drop over any base map in a GIS.
+/// # Arguments
+/// * `shade` - The grayscale hillshade.
+/// * `strength` - Opacity of the deepest shadow, from `0.0` (invisible) to `1.0`.
+/// # Returns
+/// * Black pixels with alpha `(255 - shade) * strength`: shadows are opaque, lit slopes transparent.
+pub fn hillshade_alpha(shade: &GrayImage, strength: f32) -> RgbaImage {
+    let strength = strength.clamp(0.0, 1.0);
+    RgbaImage::from_fn(shade.width(), shade.height(), |x, y| {
+        let alpha = (255 - shade.get_pixel(x, y)[0]) as f32 * strength;
+        Rgba([0, 0, 0, alpha.round() as u8])
+    })
+}
+
+/// Blends the hillshade of a smoothed copy of the terrain (broad landforms) with the hillshade of
+/// the raw terrain (fine detail), as for large relief maps.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `cellsize` - The `(dx, dy)` size of each cell in the elevation data.
+/// * `azimuth` - The azimuth angle for the light source.
+/// * `altitude` - The altitude angle for the light source.
+/// * `ambient` - Share of ambient light (0 to 1), as in `hillshade_gray`.
+/// * `algo` - The finite differences estimating the surface normal.
+/// * `coarse_sigma` - Standard deviation, in cells, of the Gaussian smoothing of the overview.
+/// * `blend` - Share of the overview, from `0.0` (detail only) to `1.0` (overview only).
+/// # Returns
+/// * The blended hillshade; each pixel lies between the two single-scale shadings.
+#[allow(clippy::too_many_arguments)]
+pub fn multiscale_hillshade(data: &Vec<f32>, width: u32, height: u32, cellsize: (f32, f32), azimuth: f32, altitude: f32, ambient: f32, algo: SlopeAlgo, coarse_sigma: f32, blend: f32) -> GrayImage {
+    let blend = blend.clamp(0.0, 1.0);
+    let fine = hillshade_gray_with(data, width, height, cellsize, azimuth, altitude, ambient, algo);
+    let smoothed = gaussian_smooth(data, width, height, coarse_sigma);
+    let coarse = hillshade_gray_with(&smoothed, width, height, cellsize, azimuth, altitude, ambient, algo);
+    GrayImage::from_fn(width, height, |x, y| {
+        let (detail, overview) = (fine.get_pixel(x, y)[0] as f32, coarse.get_pixel(x, y)[0] as f32);
+        Luma([(detail * (1.0 - blend) + overview * blend).round() as u8])
+    })
+}
+
+/// Gray level of a perfectly neutral hillshade, used for shading masked out on flats.
+pub const MID_GRAY: u8 = 128;
+
+/// Finite-difference scheme estimating the surface derivatives of a cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum SlopeAlgo {
+    /// Horn (1981): the 3x3 window weighted 1-2-1, smooth on noisy data (ESRI, GDAL default).
+    #[default]
+    Horn,
+    /// Zevenbergen and Thorne (1987): the four orthogonal neighbors only, closer to the local
+    /// surface on smooth data.
+    ZevenbergenThorne,
+}
+
+impl SlopeAlgo {
+    /// The `(dz/dx, dz/dy)` derivatives of a cell from its 3x3 window.
+    /// # Arguments
+    /// * `z` - Elevation of the neighbor at a `(dx, dy)` offset, `y` growing downward.
+    /// * `cellsize` - The `(dx, dy)` size of each cell.
+    pub fn derivatives(self, z: impl Fn(i32, i32) -> f32, (cell_dx, cell_dy): (f32, f32)) -> (f32, f32) {
+        match self {
+            SlopeAlgo::Horn => (
+                ((z(1, -1) + 2.0 * z(1, 0) + z(1, 1)) - (z(-1, -1) + 2.0 * z(-1, 0) + z(-1, 1))) / (8.0 * cell_dx),
+                ((z(-1, 1) + 2.0 * z(0, 1) + z(1, 1)) - (z(-1, -1) + 2.0 * z(0, -1) + z(1, -1))) / (8.0 * cell_dy),
+            ),
+            SlopeAlgo::ZevenbergenThorne => ((z(1, 0) - z(-1, 0)) / (2.0 * cell_dx), (z(0, 1) - z(0, -1)) / (2.0 * cell_dy)),
+        }
+    }
+}
+
+/// Computes the slope of every cell with Horn's 3x3 kernel.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `cellsize` - The `(dx, dy)` size of each cell in the elevation data.
+/// # Returns
+/// * The slope in degrees for each cell. Border cells reuse their nearest inner neighbors
+///   (edge clamping) and NaN propagates to the cells whose window touches it.
+pub fn slope_degrees(data: &Vec<f32>, width: u32, height: u32, cellsize: (f32, f32)) -> Vec<f32> {
+    slope_degrees_with(data, width, height, cellsize, SlopeAlgo::Horn)
+}
+
+/// Same as `slope_degrees`, with the derivatives estimated by `algo`.
+pub fn slope_degrees_with(data: &Vec<f32>, width: u32, height: u32, cellsize: (f32, f32), algo: SlopeAlgo) -> Vec<f32> {
+    let (w, h) = (width as i32, height as i32);
+    let mut slopes = vec![0.0; data.len()];
+    for y in 0..h {
+        for x in 0..w {
+            let z = |dx: i32, dy: i32| {
+                let nx = (x + dx).clamp(0, w - 1);
+                let ny = (y + dy).clamp(0, h - 1);
+                data[(ny * w + nx) as usize]
+            };
+            let (dz_dx, dz_dy) = algo.derivatives(z, cellsize);
+            slopes[(y * w + x) as usize] = (dz_dx.powi(2) + dz_dy.powi(2)).sqrt().atan().to_degrees();
+        }
+    }
+    slopes
+}
+
+/// Computes the aspect of every cell with Horn's 3x3 kernel.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `cellsize` - The `(dx, dy)` size of each cell in the elevation data.
+/// # Returns
+/// * The downslope direction in degrees clockwise from north (0..360), NaN on perfectly flat
+///   cells. Borders are handled by edge clamping like `slope_degrees`.
+pub fn aspect_degrees(data: &Vec<f32>, width: u32, height: u32, cellsize: (f32, f32)) -> Vec<f32> {
+    let (w, h) = (width as i32, height as i32);
+    let mut aspects = vec![f32::NAN; data.len()];
+    for y in 0..h {
+        for x in 0..w {
+            let z = |dx: i32, dy: i32| {
+                let nx = (x + dx).clamp(0, w - 1);
+                let ny = (y + dy).clamp(0, h - 1);
+                data[(ny * w + nx) as usize]
+            };
+            // rows go south, so a positive dz_dy means the terrain rises toward the south
+            let (dz_dx, dz_dy) = SlopeAlgo::Horn.derivatives(z, cellsize);
+            if dz_dx != 0.0 || dz_dy != 0.0 {
+                // downslope vector is (-dz_dx east, dz_dy north)
+                aspects[(y * w + x) as usize] = (-dz_dx).atan2(dz_dy).to_degrees().rem_euclid(360.0);
+            }
+        }
+    }
+    aspects
+}
+
+/// Replaces the hillshade of flat cells with a uniform mid gray.
+/// # Arguments
+/// * `shade` - The grayscale hillshade to modify.
+/// * `slope` - The slope of every cell in degrees, see `slope_degrees`.
+/// * `threshold` - Cells flatter than this many degrees lose their shading.
+///
+/// The deviation from `MID_GRAY` is scaled by 0 below the threshold and 1 above it, so the
+/// noise of nearly flat terrain disappears while slopes keep their full contrast.
+pub fn mask_flat_shading(shade: &mut GrayImage, slope: &[f32], threshold: f32) {
+    for (pixel, &s) in shade.pixels_mut().zip(slope.iter()) {
+        if s < threshold {
+            pixel[0] = MID_GRAY;
+        }
+    }
+}
+
+/// Same as `mask_flat_shading` for the RGB hillshade: flat cells get their base color
+/// shaded with `MID_GRAY`.
+/// # Arguments
+/// * `shade` - The RGB hillshade to modify.
+/// * `colors` - The unshaded color image the hillshade was built from.
+/// * `slope` - The slope of every cell in degrees.
+/// * `threshold` - Cells flatter than this many degrees lose their shading.
+pub fn mask_flat_shading_rgb(shade: &mut RgbaImage, colors: &RgbaImage, slope: &[f32], threshold: f32) {
+    for ((pixel, color), &s) in shade.pixels_mut().zip(colors.pixels()).zip(slope.iter()) {
+        if s < threshold {
+            for channel in 0..3 {
+                pixel[channel] = (color[channel] as u16 * MID_GRAY as u16 / 255) as u8;
+            }
+        }
+    }
+}
+
+/// Range of the sun zenith angle (90 - altitude) the adaptive hillshade may pick, in degrees.
+const ADAPTIVE_ZENITH_RANGE: (f32, f32) = (15.0, 80.0);
+
+/// Computes a hillshade whose sun altitude adapts to the local steepness of the terrain.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `colored_image` - The color image to shade, as in `hill_shading`.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `cellsize` - The `(dx, dy)` size of each cell in the elevation data.
+/// * `azimuth` - The azimuth angle for the light source.
+/// * `altitude` - The sun altitude used where the local slope equals the median slope of the map.
+/// * `window` - Standard deviation, in cells, of the neighborhood the local slope is averaged over.
+/// # Returns
+/// * A tuple containing the grayscale and the RGB hillshade, like `hill_shading`.
+#[allow(clippy::too_many_arguments)]
+pub fn adaptive_hill_shading(data: &Vec<f32>, colored_image: RgbaImage, width: u32, height: u32, cellsize: (f32, f32), azimuth: f32, altitude: f32, window: f32) -> (GrayImage, RgbaImage) {
+    let shade = adaptive_hillshade_gray(data, width, height, cellsize, azimuth, altitude, window, 0.0, SlopeAlgo::Horn);
+    let colored = blend_hillshade(&colored_image, &shade);
+    (shade, colored)
+}
+
+/// Computes the grayscale hillshade of `adaptive_hill_shading`.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `cellsize` - The `(dx, dy)` size of each cell in the elevation data.
+/// * `azimuth` - The azimuth angle for the light source.
+/// * `altitude` - The sun altitude used where the local slope equals the median slope of the map.
+/// * `window` - Standard deviation, in cells, of the neighborhood the local slope is averaged over.
+/// * `ambient` - Share of ambient light (0 to 1), as in `hillshade_gray`.
+/// * `algo` - The finite differences estimating the slope and the surface normal.
+/// # Returns
+/// * The hillshade, 255 facing the sun. Border pixels and NaN cells are black.
+///
+/// The contrast between sunny and shaded faces grows with `sin(zenith) * sin(slope)`. Each cell
+/// gets the zenith that keeps this product equal to its value at the median slope, so gentle
+/// regions get a lower sun and steep regions a higher one (a local tone mapping of the relief).
+#[allow(clippy::too_many_arguments)]
+pub fn adaptive_hillshade_gray(data: &Vec<f32>, width: u32, height: u32, cellsize: (f32, f32), azimuth: f32, altitude: f32, window: f32, ambient: f32, algo: SlopeAlgo) -> GrayImage {
+    let mut shaded_image = GrayImage::new(width, height);
+    let slope = slope_degrees_with(data, width, height, cellsize, algo);
+    let local_slope = gaussian_smooth(&slope, width, height, window);
+
+    let mut valid: Vec<f32> = slope.iter().cloned().filter(|s| !s.is_nan()).collect();
+    valid.sort_by(f32::total_cmp);
+    let median_slope = valid.get(valid.len() / 2).copied().unwrap_or(0.0);
+    let target = (90.0 - altitude).to_radians().sin() * median_slope.to_radians().sin();
+    let (min_zenith, max_zenith) = (ADAPTIVE_ZENITH_RANGE.0.to_radians(), ADAPTIVE_ZENITH_RANGE.1.to_radians());
+
+    let azimuth_rad = azimuth.to_radians();
+    let progress = progress::row_progress(height.saturating_sub(2), "adaptive");
+    for y in 1..height.saturating_sub(1) {
+        for x in 1..width.saturating_sub(1) {
+            let local = local_slope[(y * width + x) as usize].to_radians().sin();
+            let zenith = if local > 1e-6 {
+                (target / local).clamp(min_zenith.sin(), max_zenith.sin()).asin()
+            } else {
+                max_zenith
+            };
+            // `illumination` weighs the flat term by the cosine of this angle: it is the zenith here
+            let lambert = (255.0 * illumination_with(data, width, x, y, cellsize, azimuth_rad, zenith, algo)).clamp(0.0, 255.0);
+            let intensity = ambient * 255.0 + (1.0 - ambient) * lambert;
+            shaded_image.put_pixel(x, y, Luma([intensity.clamp(0.0, 255.0) as u8]));
+        }
+        progress.inc(1);
+    }
+    progress.finish();
+    shaded_image
+}
+
+/// Length of one degree of latitude in meters (WGS84 mean).
+pub const METERS_PER_DEGREE: f32 = 111_320.0;
+
+/// Computes a hillshade of a geographic grid, whose cells are in degrees of longitude and latitude.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data, in meters.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `lat_origin` - Latitude of the southern edge of the grid (the `yllcorner`), in degrees.
+/// * `deg_per_cell` - The cellsize in degrees.
+/// * `azimuth` - The azimuth angle for the light source.
+/// * `altitude` - The altitude angle for the light source.
+/// # Returns
+/// * The grayscale hillshade, with the border left black as in `hill_shading`.
+///
+/// A degree of longitude shrinks with `cos(latitude)`, so every row gets its own x-cellsize in
+/// meters while the y-cellsize stays constant. A constant cellsize stretches the relief east-west
+/// and flattens east and west facing slopes far from the equator.
+pub fn hill_shading_geographic(data: &Vec<f32>, width: u32, height: u32, lat_origin: f32, deg_per_cell: f32, azimuth: f32, altitude: f32) -> GrayImage {
+    let mut shaded_image = GrayImage::new(width, height);
+    let (azimuth_rad, altitude_rad) = (azimuth.to_radians(), altitude.to_radians());
+    let cell_dy = deg_per_cell * METERS_PER_DEGREE;
+    let progress = progress::row_progress(height.saturating_sub(2), "geographic");
+    for y in 1..height.saturating_sub(1) {
+        // latitude of the row center, rows count from the north
+        let latitude = lat_origin + (height - y) as f32 * deg_per_cell - deg_per_cell / 2.0;
+        let cell_dx = cell_dy * latitude.to_radians().cos();
+        for x in 1..width.saturating_sub(1) {
+            let intensity = 255.0 * illumination(data, width, x, y, (cell_dx, cell_dy), azimuth_rad, altitude_rad);
+            shaded_image.put_pixel(x, y, Luma([intensity.clamp(0.0, 255.0) as u8]));
+        }
+        progress.inc(1);
+    }
+    progress.finish();
+    shaded_image
+}
+
+/// How the hillshade is combined with the color image in `composite_relief_with`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BlendMode {
+    /// Gentle contrast: mid gray leaves the color untouched, light and dark shading tint it.
+    SoftLight,
+    /// Stronger contrast than soft light, also neutral at mid gray.
+    Overlay,
+    /// Scales the color by the shading, as `hill_shading` does; darkens the whole map.
+    Multiply,
+}
+
+/// Blends a hillshade over a color image with a soft-light blend.
+/// # Arguments
+/// * `color` - The color image, e.g. the RGB or hypsometric rendering.
+/// * `shade` - The hillshade of the same size.
+/// * `opacity` - Strength of the shading, from `0.0` (color only) to `1.0`.
+/// # Returns
+/// * The shaded relief, keeping the alpha channel of `color`.
+pub fn composite_relief(color: &RgbaImage, shade: &GrayImage, opacity: f32) -> RgbaImage {
+    composite_relief_with(color, shade, opacity, BlendMode::SoftLight)
+}
+
+/// Same as `composite_relief`, with the blend mode of choice.
+pub fn composite_relief_with(color: &RgbaImage, shade: &GrayImage, opacity: f32, mode: BlendMode) -> RgbaImage {
+    let opacity = opacity.clamp(0.0, 1.0);
+    let mut composite = color.clone();
+    for (pixel, s) in composite.pixels_mut().zip(shade.pixels()) {
+        let s = s[0] as f32;
+        for c in pixel.0[..3].iter_mut() {
+            let base = *c as f32;
+            let blended = blend_channel(mode, base, s);
+            *c = (blended * opacity + base * (1.0 - opacity)) as u8;
+        }
+    }
+    composite
+}
+
+/// Hypsometric colors of the Swiss-style relief, from the lowest to the highest elevation.
+pub const SWISS_PALETTE: [[f32; 3]; 5] = [
+    [122.0, 160.0, 104.0],
+    [184.0, 196.0, 130.0],
+    [222.0, 204.0, 152.0],
+    [184.0, 156.0, 128.0],
+    [244.0, 242.0, 236.0],
+];
+/// Tint mixed into sunlit slopes.
+const WARM_TINT: [f32; 3] = [255.0, 214.0, 140.0];
+/// Tint mixed into slopes turned away from the light.
+const COOL_TINT: [f32; 3] = [92.0, 122.0, 186.0];
+/// Largest share of the warm or cool tint in a pixel.
+const TINT_STRENGTH: f32 = 0.35;
+
+/// Renders a Swiss-style color relief, where the orientation of the slopes changes both
+/// their brightness and the warmth of their hue.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `cellsize` - The `(dx, dy)` size of each cell in the elevation data.
+/// * `azimuth` - The azimuth angle for the light source.
+/// * `altitude` - The altitude angle for the light source.
+/// # Returns
+/// * The shaded relief, with NaN cells transparent.
+///
+/// The base color follows `SWISS_PALETTE` over the elevation range. Slopes lit more than flat
+/// ground are shifted towards a warm yellow, slopes lit less towards a cool blue, in proportion to
+/// the difference, and the result is darkened by the hillshade. The grid border is shaded as flat.
+pub fn swiss_relief(data: &Vec<f32>, width: u32, height: u32, cellsize: (f32, f32), azimuth: f32, altitude: f32) -> RgbaImage {
+    let (min, max) = elevation_range(data).unwrap_or((0.0, 0.0));
+    let span = if max > min { max - min } else { 1.0 };
+    let (azimuth_rad, altitude_rad) = (azimuth.to_radians(), altitude.to_radians());
+    let flat = altitude_rad.cos();
+    RgbaImage::from_fn(width, height, |x, y| {
+        let value = data[(y * width + x) as usize];
+        if value.is_nan() {
+            return Rgba([0, 0, 0, 0]);
+        }
+        let interior = x > 0 && y > 0 && x + 1 < width && y + 1 < height;
+        let light = if interior { illumination(data, width, x, y, cellsize, azimuth_rad, altitude_rad) } else { flat };
+        let light = if light.is_nan() { flat } else { light.clamp(0.0, 1.0) };
+
+        let base = palette_color((value - min) / span);
+        // positive when the slope faces the light more than flat ground does
+        let warmth = ((light - flat) / flat.max(1e-3)).clamp(-1.0, 1.0);
+        let tint = if warmth >= 0.0 { WARM_TINT } else { COOL_TINT };
+        let share = warmth.abs() * TINT_STRENGTH;
+        let brightness = 0.4 + 0.6 * light;
+        let channel = |c: usize| ((base[c] * (1.0 - share) + tint[c] * share) * brightness).clamp(0.0, 255.0) as u8;
+        Rgba([channel(0), channel(1), channel(2), 255])
+    })
+}
+
+/// Interpolates `SWISS_PALETTE` at `t` in 0..1.
+fn palette_color(t: f32) -> [f32; 3] {
+    let scaled = t.clamp(0.0, 1.0) * (SWISS_PALETTE.len() - 1) as f32;
+    let i = (scaled.floor() as usize).min(SWISS_PALETTE.len() - 2);
+    let f = scaled - i as f32;
+    let (a, b) = (SWISS_PALETTE[i], SWISS_PALETTE[i + 1]);
+    [a[0] + (b[0] - a[0]) * f, a[1] + (b[1] - a[1]) * f, a[2] + (b[2] - a[2]) * f]
+}
+
+/// Blends one color channel `c` with the shading `s`, both in 0..255.
+fn blend_channel(mode: BlendMode, c: f32, s: f32) -> f32 {
+    let (a, b) = (c / 255.0, s / 255.0);
+    match mode {
+        // same expression as hill_shading so a full-opacity multiply matches it exactly
+        BlendMode::Multiply => c * s / 255.0,
+        BlendMode::Overlay => {
+            let v = if a < 0.5 { 2.0 * a * b } else { 1.0 - 2.0 * (1.0 - a) * (1.0 - b) };
+            v * 255.0
+        }
+        BlendMode::SoftLight => {
+            // W3C compositing formula, with the shading as the blend layer
+            let v = if b <= 0.5 {
+                a - (1.0 - 2.0 * b) * a * (1.0 - a)
+            } else {
+                let d = if a <= 0.25 { ((16.0 * a - 12.0) * a + 4.0) * a } else { a.sqrt() };
+                a + (2.0 * b - 1.0) * (d - a)
+            };
+            v * 255.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hill_shading;
+
+    #[test]
+    /// A wall lit from the west must shadow the plain east of it, up to its height / tan(altitude).
+    fn test_cast_shadows_wall() {
+        let (width, height) = (30, 5);
+        let mut data = vec![0.0; (width * height) as usize];
+        for y in 0..height {
+            data[(y * width + 10) as usize] = 5.0;
+        }
+        let mask = cast_shadows(&data, width, height, (1.0, 1.0), 270.0, 45.0);
+        assert_eq!(mask.get_pixel(12, 2), &Luma([0]));
+        assert_eq!(mask.get_pixel(14, 2), &Luma([0]));
+        assert_eq!(mask.get_pixel(18, 2), &Luma([255])); // beyond the shadow length
+        assert_eq!(mask.get_pixel(5, 2), &Luma([255])); // sun side of the wall
+        assert_eq!(mask.get_pixel(10, 2), &Luma([255])); // the wall itself
+
+        // cells twice as wide shorten the shadow to half as many columns
+        let mask = cast_shadows(&data, width, height, (2.0, 1.0), 270.0, 45.0);
+        assert_eq!(mask.get_pixel(12, 2), &Luma([0]));
+        assert_eq!(mask.get_pixel(14, 2), &Luma([255]));
+    }
+
+    #[test]
+    /// A noisy flat renders as uniform gray while a steep ramp keeps its hillshade contrast.
+    fn test_mask_flat_shading() {
+        let (width, height) = (20, 10);
+        let data: Vec<f32> = (0..width * height)
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                if x < 10 { ((x * 7 + y * 3) % 5) as f32 * 0.005 } else { (x as f32 - 10.0) * 2.0 + y as f32 }
+            })
+            .collect();
+        let colors = RgbaImage::from_pixel(width, height, image::Rgba([200, 100, 50, 255]));
+        let (mut gray, _) = hill_shading(&data, colors, width, height, (1.0, 1.0), 315.0, 45.0, 0.0);
+        let original = gray.clone();
+        let slope = slope_degrees(&data, width, height, (1.0, 1.0));
+        mask_flat_shading(&mut gray, &slope, 2.0);
+
+        for y in 1..height - 1 {
+            for x in 1..8 {
+                assert_eq!(gray.get_pixel(x, y)[0], MID_GRAY);
+            }
+            for x in 12..width - 1 {
+                assert_eq!(gray.get_pixel(x, y), original.get_pixel(x, y));
+            }
+        }
+    }
+
+    #[test]
+    /// Terrain rising toward the east faces west, terrain rising toward the north faces south.
+    fn test_aspect_degrees() {
+        let east_rise: Vec<f32> = (0..25).map(|i| (i % 5) as f32).collect();
+        assert!((aspect_degrees(&east_rise, 5, 5, (1.0, 1.0))[12] - 270.0).abs() < 1e-3);
+        let north_rise: Vec<f32> = (0..25).map(|i| (4 - i / 5) as f32).collect();
+        assert!((aspect_degrees(&north_rise, 5, 5, (1.0, 1.0))[12] - 180.0).abs() < 1e-3);
+        assert!(aspect_degrees(&vec![3.0; 25], 5, 5, (1.0, 1.0))[12].is_nan());
+        // rising by 1 per unit of distance both east and north once dy is taken into account
+        let north_east_rise: Vec<f32> = (0..25).map(|i| (i % 5) as f32 + 2.0 * (4 - i / 5) as f32).collect();
+        assert!((aspect_degrees(&north_east_rise, 5, 5, (1.0, 2.0))[12] - 225.0).abs() < 1e-3);
+    }
+
+    #[test]
+    /// Gentle and steep ripples end up with closer hillshade contrast in adaptive mode than with a fixed sun.
+    fn test_adaptive_hill_shading_balances_contrast() {
+        let (width, height) = (80, 40);
+        let data: Vec<f32> = (0..width * height)
+            .map(|i| {
+                let (x, y) = ((i % width) as f32, (i / width) as f32);
+                let amplitude = if x < 40.0 { 0.3 } else { 6.0 };
+                amplitude * (x / 3.0).sin() * (y / 3.0).cos()
+            })
+            .collect();
+        let colors = RgbaImage::new(width, height);
+        let std_dev = |shade: &GrayImage, x_range: std::ops::Range<u32>| {
+            let values: Vec<f32> = (5..height - 5)
+                .flat_map(|y| x_range.clone().map(move |x| (x, y)))
+                .map(|(x, y)| shade.get_pixel(x, y)[0] as f32)
+                .collect();
+            let mean = values.iter().sum::<f32>() / values.len() as f32;
+            (values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / values.len() as f32).sqrt()
+        };
+        let imbalance = |shade: &GrayImage| (std_dev(shade, 5..35) / std_dev(shade, 45..75)).ln().abs();
+
+        let (fixed, _) = crate::hill_shading(&data, colors.clone(), width, height, (1.0, 1.0), 315.0, 45.0, 0.0);
+        let (adaptive, _) = adaptive_hill_shading(&data, colors, width, height, (1.0, 1.0), 315.0, 45.0, 4.0);
+        assert!(imbalance(&adaptive) < imbalance(&fixed));
+    }
+
+    #[test]
+    /// The adaptive RGB hillshade goes through `blend_hillshade`, so its border is filled and opaque,
+    /// and empty grids don't underflow the loops.
+    fn test_adaptive_hill_shading_border_and_empty() {
+        let (width, height) = (6, 5);
+        let data: Vec<f32> = (0..width * height).map(|i| ((i % width) * (i / width)) as f32).collect();
+        let colors = RgbaImage::from_pixel(width, height, Rgba([200, 100, 50, 255]));
+        let (shade, shaded_rgb) = adaptive_hill_shading(&data, colors, width, height, (1.0, 1.0), 315.0, 45.0, 1.0);
+        assert!(shaded_rgb.pixels().all(|p| p[3] == 255));
+        assert_eq!(shaded_rgb.get_pixel(0, 2), shaded_rgb.get_pixel(1, 2));
+        assert_eq!(shade.get_pixel(0, 0)[0], 0);
+
+        let lit = adaptive_hillshade_gray(&data, width, height, (1.0, 1.0), 315.0, 45.0, 1.0, 1.0, SlopeAlgo::Horn);
+        assert!(lit.enumerate_pixels().filter(|(x, y, _)| (1..5).contains(x) && (1..4).contains(y)).all(|(_, _, p)| p[0] == 255));
+        assert_eq!(adaptive_hillshade_gray(&Vec::new(), 0, 3, (1.0, 1.0), 315.0, 45.0, 1.0, 0.0, SlopeAlgo::Horn).dimensions(), (0, 3));
+    }
+
+    #[test]
+    /// A full-opacity multiply composite reproduces the colored hillshade of `hill_shading`.
+    fn test_composite_multiply_matches_hill_shading() {
+        let (width, height) = (12, 10);
+        let data: Vec<f32> = (0..width * height).map(|i| ((i % width) as f32 * 0.7).sin() * 3.0 + (i / width) as f32).collect();
+        let colors = crate::rgb(data.clone(), width, height, None, None).unwrap();
+        let (shade, shaded_rgb) = hill_shading(&data, colors.clone(), width, height, (1.0, 1.0), 315.0, 45.0, 0.0);
+        let composite = composite_relief_with(&colors, &shade, 1.0, BlendMode::Multiply);
+        for y in 1..height - 1 {
+            for x in 1..width - 1 {
+                assert_eq!(composite.get_pixel(x, y), shaded_rgb.get_pixel(x, y), "({}, {})", x, y);
+            }
+        }
+
+        // mid gray barely touches the colors in soft light, and zero opacity leaves them alone
+        let flat = GrayImage::from_pixel(width, height, Luma([MID_GRAY]));
+        let soft = composite_relief(&colors, &flat, 1.0);
+        assert!(soft.pixels().zip(colors.pixels()).all(|(a, b)| (0..3).all(|c| (a[c] as i32 - b[c] as i32).abs() <= 1)));
+        assert_eq!(composite_relief_with(&colors, &shade, 0.0, BlendMode::Overlay), colors);
+    }
+
+    #[test]
+    /// On a symmetric ridge, the lit flank is brighter and warmer (redder) than the flank in shadow.
+    fn test_swiss_relief_warm_and_cool_flanks() {
+        let (width, height) = (21, 9);
+        let data: Vec<f32> = (0..width * height).map(|i| 50.0 - ((i % width) as f32 - 10.0).abs() * 2.0).collect();
+        let relief = swiss_relief(&data, width, height, (1.0, 1.0), 315.0, 45.0);
+        let (west, east) = (relief.get_pixel(5, 4), relief.get_pixel(15, 4));
+        let (gray, _) = hill_shading(&data, RgbaImage::new(width, height), width, height, (1.0, 1.0), 315.0, 45.0, 0.0);
+        let (lit, shadowed) = if gray.get_pixel(5, 4)[0] > gray.get_pixel(15, 4)[0] { (west, east) } else { (east, west) };
+        let warmth = |p: &Rgba<u8>| p[0] as f32 / p[2].max(1) as f32;
+        assert!(warmth(lit) > warmth(shadowed) * 1.2, "lit {:?} shadowed {:?}", lit, shadowed);
+        assert!(lit[1] > shadowed[1]);
+        assert_eq!(relief.get_pixel(0, 0)[3], 255);
+    }
+
+    #[test]
+    /// At 60°N a degree of longitude is half as long as at the equator, so an east-west ramp is twice as steep.
+    fn test_hill_shading_geographic_latitude() {
+        let (width, height, deg) = (7, 5, 0.001);
+        // rises 20 m per cell toward the east
+        let data: Vec<f32> = (0..width * height).map(|i| (i % width) as f32 * 20.0).collect();
+        let equator = hill_shading_geographic(&data, width, height, -0.0025, deg, 315.0, 45.0);
+        let north = hill_shading_geographic(&data, width, height, 59.9975, deg, 315.0, 45.0);
+        assert_ne!(equator.get_pixel(3, 2), north.get_pixel(3, 2));
+
+        let cell = deg * METERS_PER_DEGREE;
+        let (square, _) = hill_shading(&data, RgbaImage::new(width, height), width, height, (cell, cell), 315.0, 45.0, 0.0);
+        let (half, _) = hill_shading(&data, RgbaImage::new(width, height), width, height, (cell * 0.5, cell), 315.0, 45.0, 0.0);
+        let close = |a: u8, b: u8| (a as i32 - b as i32).abs() <= 1;
+        assert!(close(equator.get_pixel(3, 2)[0], square.get_pixel(3, 2)[0]));
+        assert!(close(north.get_pixel(3, 2)[0], half.get_pixel(3, 2)[0]), "{:?} {:?}", north.get_pixel(3, 2), half.get_pixel(3, 2));
+
+        // an empty-width grid with rows has nothing to shade
+        assert_eq!(hill_shading_geographic(&Vec::new(), 0, 4, 45.0, deg, 315.0, 45.0).dimensions(), (0, 4));
+    }
+
+    #[test]
+    /// A raised corner tilts the Horn window but not the orthogonal neighbors Zevenbergen-Thorne reads.
+    fn test_slope_algorithms_differ() {
+        let data = vec![
+            0.0, 0.0, 8.0,
+            0.0, 1.0, 0.0,
+            0.0, 0.0, 0.0,
+        ];
+        let horn = slope_degrees_with(&data, 3, 3, (1.0, 1.0), SlopeAlgo::Horn);
+        let zt = slope_degrees_with(&data, 3, 3, (1.0, 1.0), SlopeAlgo::ZevenbergenThorne);
+        assert!((horn[4] - 2f32.sqrt().atan().to_degrees()).abs() < 1e-4, "{}", horn[4]);
+        assert_eq!(zt[4], 0.0);
+        assert_eq!(slope_degrees(&data, 3, 3, (1.0, 1.0)), horn);
+
+        // both agree on a plane
+        let plane: Vec<f32> = (0..25).map(|i| (i % 5) as f32 * 2.0 + (i / 5) as f32).collect();
+        let horn = slope_degrees_with(&plane, 5, 5, (1.0, 1.0), SlopeAlgo::Horn);
+        let zt = slope_degrees_with(&plane, 5, 5, (1.0, 1.0), SlopeAlgo::ZevenbergenThorne);
+        assert!((horn[12] - zt[12]).abs() < 1e-4);
+    }
+
+    #[test]
+    /// Lit pixels are fully transparent, black shadows take the strength as opacity.
+    fn test_hillshade_alpha() {
+        let shade = GrayImage::from_raw(3, 1, vec![255, 0, 128]).unwrap();
+        let overlay = hillshade_alpha(&shade, 0.5);
+        assert_eq!(overlay.get_pixel(0, 0), &Rgba([0, 0, 0, 0]));
+        assert_eq!(overlay.get_pixel(1, 0), &Rgba([0, 0, 0, 128]));
+        assert_eq!(overlay.get_pixel(2, 0)[3], 64);
+        assert_eq!(hillshade_alpha(&shade, 1.0).get_pixel(1, 0)[3], 255);
+    }
+
+    #[test]
+    /// Every pixel of the multiscale shading lies between the detail and the overview shadings, built with
+    /// the same cell sizes, ambient light and derivatives.
+    fn test_multiscale_hillshade_between_scales() {
+        let (width, height) = (24, 20);
+        // broad ridge with a fine ripple on top
+        let data: Vec<f32> = (0..width * height)
+            .map(|i| {
+                let (x, y) = ((i % width) as f32, (i / width) as f32);
+                30.0 * (x / 6.0).sin() + 2.0 * (x * 1.7 + y * 2.3).sin()
+            })
+            .collect();
+        let (cellsize, algo) = ((1.0, 1.5), SlopeAlgo::ZevenbergenThorne);
+        let fine = hillshade_gray_with(&data, width, height, cellsize, 315.0, 45.0, 0.2, algo);
+        let coarse = hillshade_gray_with(&gaussian_smooth(&data, width, height, 3.0), width, height, cellsize, 315.0, 45.0, 0.2, algo);
+        let blended = multiscale_hillshade(&data, width, height, cellsize, 315.0, 45.0, 0.2, algo, 3.0, 0.4);
+        for (x, y, pixel) in blended.enumerate_pixels() {
+            let (a, b) = (fine.get_pixel(x, y)[0], coarse.get_pixel(x, y)[0]);
+            assert!((a.min(b)..=a.max(b)).contains(&pixel[0]), "pixel {},{}: {} not between {} and {}", x, y, pixel[0], a, b);
+        }
+        assert_eq!(multiscale_hillshade(&data, width, height, cellsize, 315.0, 45.0, 0.2, algo, 3.0, 0.0), fine);
+    }
+}
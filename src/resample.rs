@@ -0,0 +1,197 @@
+use clap::ValueEnum;
+
+use crate::grid::Grid;
+
+/// Interpolation used when resampling a grid to a new cellsize.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ResampleKind {
+    /// Value of the closest source cell.
+    Nearest,
+    /// Weighted average of the 2x2 surrounding cells.
+    Bilinear,
+    /// Catmull-Rom spline through the 4x4 surrounding cells.
+    Bicubic,
+}
+
+/// Resamples a grid to a new cellsize, keeping the top-left corner of its extent in place.
+/// # Arguments
+/// * `grid` - The source grid.
+/// * `new_cellsize` - The cellsize of the output grid.
+/// * `kind` - The interpolation used to sample the source grid.
+/// # Returns
+/// * A grid of `round(width * cellsize / new_cellsize)` x `round(height * cellsize_y / new_cellsize)` square cells.
+///
+/// Interpolation never mixes in NoData: a bicubic sample whose 4x4 window touches a NaN cell falls
+/// back to bilinear, and a bilinear sample touching one falls back to the nearest cell.
+pub fn resample_with(grid: &Grid, new_cellsize: f32, kind: ResampleKind) -> Grid {
+    let ratio = new_cellsize / grid.cellsize;
+    let ratio_y = new_cellsize / grid.cellsize_y;
+    let width = ((grid.width as f32 / ratio).round() as u32).max(1);
+    let height = ((grid.height as f32 / ratio_y).round() as u32).max(1);
+    let mut data = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            // center of the output cell, in source cell coordinates
+            let sx = (x as f32 + 0.5) * ratio - 0.5;
+            let sy = (y as f32 + 0.5) * ratio_y - 0.5;
+            data.push(match kind {
+                ResampleKind::Nearest => nearest(grid, sx, sy),
+                ResampleKind::Bilinear => bilinear(grid, sx, sy),
+                ResampleKind::Bicubic => bicubic(grid, sx, sy),
+            });
+        }
+    }
+    let top = grid.yllcorner + grid.height as f64 * grid.cellsize_y as f64;
+    Grid {
+        data,
+        width,
+        height,
+        cellsize: new_cellsize,
+        cellsize_y: new_cellsize,
+        xllcorner: grid.xllcorner,
+        yllcorner: top - height as f64 * new_cellsize as f64,
+    }
+}
+
+/// Downsamples elevation data by averaging blocks of cells, for overviews that keep every cell's
+/// contribution instead of sampling a few of them.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// * `factor` - The side of the averaged blocks, in cells; `1` returns a copy.
+/// # Returns
+/// * The averaged data and its `ceil(width / factor)` x `ceil(height / factor)` size. Blocks cut by
+///   the right or bottom edge average the cells they hold; NaN cells are left out, and a block is
+///   NaN only when all its cells are.
+pub fn downsample_mean(data: &Vec<f32>, width: u32, height: u32, factor: u32) -> (Vec<f32>, u32, u32) {
+    let factor = factor.max(1);
+    let (out_width, out_height) = (width.div_ceil(factor), height.div_ceil(factor));
+    let mut out = Vec::with_capacity((out_width * out_height) as usize);
+    for by in 0..out_height {
+        for bx in 0..out_width {
+            let (mut sum, mut count) = (0.0f64, 0);
+            for y in by * factor..((by + 1) * factor).min(height) {
+                for x in bx * factor..((bx + 1) * factor).min(width) {
+                    let value = data[(y * width + x) as usize];
+                    if !value.is_nan() {
+                        sum += value as f64;
+                        count += 1;
+                    }
+                }
+            }
+            out.push(if count == 0 { f32::NAN } else { (sum / count as f64) as f32 });
+        }
+    }
+    (out, out_width, out_height)
+}
+
+/// Reads a source cell, clamping the coordinates to the grid.
+fn cell(grid: &Grid, x: i64, y: i64) -> f32 {
+    let x = x.clamp(0, grid.width as i64 - 1);
+    let y = y.clamp(0, grid.height as i64 - 1);
+    grid.data[(y * grid.width as i64 + x) as usize]
+}
+
+fn nearest(grid: &Grid, sx: f32, sy: f32) -> f32 {
+    cell(grid, sx.round() as i64, sy.round() as i64)
+}
+
+fn bilinear(grid: &Grid, sx: f32, sy: f32) -> f32 {
+    let (x0, y0) = (sx.floor(), sy.floor());
+    let (fx, fy) = (sx - x0, sy - y0);
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let (z00, z10) = (cell(grid, x0, y0), cell(grid, x0 + 1, y0));
+    let (z01, z11) = (cell(grid, x0, y0 + 1), cell(grid, x0 + 1, y0 + 1));
+    if z00.is_nan() || z10.is_nan() || z01.is_nan() || z11.is_nan() {
+        return nearest(grid, sx, sy);
+    }
+    let top = z00 + (z10 - z00) * fx;
+    let bottom = z01 + (z11 - z01) * fx;
+    top + (bottom - top) * fy
+}
+
+fn bicubic(grid: &Grid, sx: f32, sy: f32) -> f32 {
+    let (x0, y0) = (sx.floor(), sy.floor());
+    let (fx, fy) = (sx - x0, sy - y0);
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let mut rows = [0.0f32; 4];
+    for (j, row) in rows.iter_mut().enumerate() {
+        let y = y0 - 1 + j as i64;
+        let p = [cell(grid, x0 - 1, y), cell(grid, x0, y), cell(grid, x0 + 1, y), cell(grid, x0 + 2, y)];
+        if p.iter().any(|v| v.is_nan()) {
+            return bilinear(grid, sx, sy);
+        }
+        *row = catmull_rom(p, fx);
+    }
+    catmull_rom(rows, fy)
+}
+
+/// Evaluates the Catmull-Rom spline between `p[1]` (t = 0) and `p[2]` (t = 1).
+fn catmull_rom(p: [f32; 4], t: f32) -> f32 {
+    0.5 * (2.0 * p[1]
+        + (p[2] - p[0]) * t
+        + (2.0 * p[0] - 5.0 * p[1] + 4.0 * p[2] - p[3]) * t * t
+        + (3.0 * p[1] - p[0] - 3.0 * p[2] + p[3]) * t * t * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Upsampling a smooth sine surface, bicubic sits closer to the true surface than bilinear.
+    fn test_bicubic_beats_bilinear_on_sine() {
+        let surface = |x: f32, y: f32| (0.4 * x).sin() + (0.3 * y).cos();
+        let (width, height) = (40, 40);
+        let data = (0..width * height).map(|i| surface((i % width) as f32, (i / width) as f32)).collect();
+        let grid = Grid { data, width, height, cellsize: 1.0, cellsize_y: 1.0, xllcorner: 0.0, yllcorner: 0.0 };
+
+        let error = |kind: ResampleKind| {
+            let fine = resample_with(&grid, 0.5, kind);
+            assert_eq!((fine.width, fine.height), (80, 80));
+            let mut total = 0.0;
+            // skip the border where the clamped window distorts both methods
+            for y in 8..72 {
+                for x in 8..72 {
+                    let (sx, sy) = ((x as f32 + 0.5) * 0.5 - 0.5, (y as f32 + 0.5) * 0.5 - 0.5);
+                    total += (fine.data[(y * 80 + x) as usize] - surface(sx, sy)).abs();
+                }
+            }
+            total
+        };
+        let (bilinear, bicubic) = (error(ResampleKind::Bilinear), error(ResampleKind::Bicubic));
+        assert!(bicubic < bilinear / 2.0, "bicubic {} vs bilinear {}", bicubic, bilinear);
+    }
+
+    #[test]
+    /// A hole in the grid stays confined instead of spreading NaN over its bicubic window.
+    fn test_bicubic_nan_fallback() {
+        let mut data = vec![1.0; 36];
+        data[14] = f32::NAN;
+        let grid = Grid { data, width: 6, height: 6, cellsize: 2.0, cellsize_y: 2.0, xllcorner: 0.0, yllcorner: 0.0 };
+        let fine = resample_with(&grid, 1.0, ResampleKind::Bicubic);
+        let holes = fine.data.iter().filter(|v| v.is_nan()).count();
+        assert_eq!(holes, 4);
+        assert_eq!((fine.width, fine.height, fine.yllcorner), (12, 12, 0.0));
+    }
+
+    #[test]
+    /// Each 2x2 block of a 4x4 grid becomes its mean, NaN cells aside, and an all-NaN block stays NaN.
+    fn test_downsample_mean_factor_two() {
+        let nan = f32::NAN;
+        let data = vec![
+            1.0, 2.0, 10.0, nan,
+            3.0, 4.0, 20.0, 30.0,
+            nan, nan, 5.0, 5.0,
+            nan, nan, 5.0, 9.0,
+        ];
+        let (coarse, width, height) = downsample_mean(&data, 4, 4, 2);
+        assert_eq!((width, height), (2, 2));
+        assert_eq!(coarse[0], 2.5);
+        assert_eq!(coarse[1], 20.0);
+        assert!(coarse[2].is_nan());
+        assert_eq!(coarse[3], 6.0);
+        assert_eq!(downsample_mean(&data, 4, 4, 3).1, 2);
+    }
+}
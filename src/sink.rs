@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, ImageFormat, Rgba, RgbImage, RgbaImage};
+
+/// File format of the written images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Png,
+    /// Lossy and without alpha: transparent pixels are flattened onto a background color.
+    Jpeg,
+    /// Lossless WebP.
+    Webp,
+    Tiff,
+}
+
+impl OutputFormat {
+    /// The `image` crate format used to encode the files.
+    pub fn image_format(self) -> ImageFormat {
+        match self {
+            OutputFormat::Png => ImageFormat::Png,
+            OutputFormat::Jpeg => ImageFormat::Jpeg,
+            OutputFormat::Webp => ImageFormat::WebP,
+            OutputFormat::Tiff => ImageFormat::Tiff,
+        }
+    }
+
+    /// File extension of the format, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Webp => "webp",
+            OutputFormat::Tiff => "tif",
+        }
+    }
+
+    /// Replaces the extension of an output file name with the one of this format.
+    pub fn rename(self, name: &str) -> String {
+        Path::new(name).with_extension(self.extension()).to_string_lossy().into_owned()
+    }
+
+    /// Converts an image into a color type the format can encode.
+    /// # Arguments
+    /// * `img` - The rendered image.
+    /// * `background` - JPEG only: the color transparent pixels are blended onto.
+    ///
+    /// JPEG and WebP only take 8-bit data, so 16-bit grayscale is reduced to 8 bits for them.
+    pub fn prepare(self, img: &DynamicImage, background: Rgba<u8>) -> DynamicImage {
+        match (self, img) {
+            (OutputFormat::Jpeg, DynamicImage::ImageLuma8(_)) => img.clone(),
+            (OutputFormat::Jpeg, _) if img.color().has_alpha() => DynamicImage::ImageRgb8(flatten(img, background)),
+            (OutputFormat::Jpeg, _) if img.color().has_color() => DynamicImage::ImageRgb8(img.to_rgb8()),
+            (OutputFormat::Jpeg | OutputFormat::Webp, DynamicImage::ImageLuma16(_)) => DynamicImage::ImageLuma8(img.to_luma8()),
+            _ => img.clone(),
+        }
+    }
+}
+
+/// Scales an image so its longest side is `max_dim` pixels, keeping its aspect ratio.
+/// # Arguments
+/// * `image` - The full-size image.
+/// * `max_dim` - Length of the longest side of the thumbnail.
+/// # Returns
+/// * The thumbnail, resampled with a Lanczos3 filter. The shorter side is at least one pixel.
+pub fn make_thumbnail(image: &RgbaImage, max_dim: u32) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let scale = max_dim as f32 / width.max(height).max(1) as f32;
+    let new_width = ((width as f32 * scale).round() as u32).clamp(1, max_dim);
+    let new_height = ((height as f32 * scale).round() as u32).clamp(1, max_dim);
+    imageops::resize(image, new_width, new_height, FilterType::Lanczos3)
+}
+
+/// Name of the thumbnail of an output file: `name.png` becomes `name_thumb.png`.
+pub fn thumbnail_name(name: &str) -> String {
+    let path = Path::new(name);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or(name);
+    let thumb = match path.extension().and_then(|e| e.to_str()) {
+        Some(extension) => format!("{}_thumb.{}", stem, extension),
+        None => format!("{}_thumb", stem),
+    };
+    path.with_file_name(thumb).to_string_lossy().into_owned()
+}
+
+/// Blends every pixel onto an opaque background according to its alpha.
+pub fn flatten(img: &DynamicImage, background: Rgba<u8>) -> RgbImage {
+    let rgba = img.to_rgba8();
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let pixel = rgba.get_pixel(x, y);
+        let alpha = pixel[3] as f32 / 255.0;
+        let mix = |c: usize| (pixel[c] as f32 * alpha + background[c] as f32 * (1.0 - alpha)).round() as u8;
+        image::Rgb([mix(0), mix(1), mix(2)])
+    })
+}
+
+/// Meters per inch, to convert a print resolution in dots per inch.
+const METERS_PER_INCH: f32 = 0.0254;
+
+/// Physical resolution declared in the written PNGs, in pixels per meter.
+/// # Arguments
+/// * `cellsize` - The ground size of a cell (one pixel), in meters.
+/// * `print_dpi` - The print resolution asked for with `--print-dpi`, which takes precedence.
+/// # Returns
+/// * The pixels per meter: `print_dpi / 0.0254`, otherwise the ground resolution `1 / cellsize`.
+///   `None` when the ground resolution is coarser than the 1 pixel per meter a PNG can declare.
+pub fn pixels_per_meter(cellsize: f32, print_dpi: Option<f32>) -> Option<u32> {
+    let pixels = match print_dpi {
+        Some(dpi) => dpi / METERS_PER_INCH,
+        None if cellsize > 0.0 => 1.0 / cellsize,
+        None => return None,
+    };
+    let pixels = pixels.round();
+    (1.0..=u32::MAX as f32).contains(&pixels).then_some(pixels as u32)
+}
+
+/// Saves an image as PNG with a `pHYs` chunk, so layout software imports it at the right size.
+/// # Arguments
+/// * `img` - The image to save; 16-bit grayscale stays 16-bit, other images are written as 8-bit.
+/// * `pixels_per_meter` - The resolution declared for both axes.
+/// * `path` - The path of the PNG file.
+pub fn save_png_with_resolution(img: &DynamicImage, pixels_per_meter: u32, path: &Path) -> Result<()> {
+    let (color, depth, bytes) = match img {
+        DynamicImage::ImageLuma8(gray) => (png::ColorType::Grayscale, png::BitDepth::Eight, gray.as_raw().clone()),
+        // PNG stores 16-bit samples big-endian
+        DynamicImage::ImageLuma16(gray) => (png::ColorType::Grayscale, png::BitDepth::Sixteen, gray.as_raw().iter().flat_map(|v| v.to_be_bytes()).collect()),
+        DynamicImage::ImageRgb8(rgb) => (png::ColorType::Rgb, png::BitDepth::Eight, rgb.as_raw().clone()),
+        other => (png::ColorType::Rgba, png::BitDepth::Eight, other.to_rgba8().into_raw()),
+    };
+    let file = File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), img.width(), img.height());
+    encoder.set_color(color);
+    encoder.set_depth(depth);
+    encoder.set_pixel_dims(Some(png::PixelDimensions { xppu: pixels_per_meter, yppu: pixels_per_meter, unit: png::Unit::Meter }));
+    let mut writer = encoder.write_header().with_context(|| format!("Failed to save image {}", path.display()))?;
+    writer.write_image_data(&bytes).with_context(|| format!("Failed to save image {}", path.display()))?;
+    writer.finish().with_context(|| format!("Failed to save image {}", path.display()))
+}
+
+/// Destination for the images produced by the pipeline.
+/// Implementations decide where a named image ends up (disk, memory, a callback, ...).
+pub trait OutputSink {
+    /// Writes one image under the given file name.
+    /// # Arguments
+    /// * `name` - The output file name, e.g. `output_rgb_20250402_153924_turbo.png`.
+    /// * `img` - The image to store.
+    fn write(&mut self, name: &str, img: &DynamicImage) -> Result<()>;
+
+    /// Writes one image declaring its physical resolution, where the format can carry it (PNG).
+    /// Sinks that don't produce files can keep this default, which ignores the resolution.
+    fn write_with_resolution(&mut self, name: &str, img: &DynamicImage, _pixels_per_meter: u32) -> Result<()> {
+        self.write(name, img)
+    }
+
+    /// Writes a text sidecar (world file, `.prj`, ...) under the given file name.
+    /// Sinks that only deal with images can keep this default, which drops the text.
+    fn write_text(&mut self, _name: &str, _contents: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Sink that saves every image as a file inside a directory.
+pub struct FileSink {
+    pub dir: PathBuf,
+}
+
+impl FileSink {
+    /// Creates a sink writing into `dir`.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        FileSink { dir: dir.into() }
+    }
+
+    /// Creates a sink writing into `dir`, creating the directory and its parents when missing.
+    pub fn create(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create the output directory {}", dir.display()))?;
+        Ok(FileSink { dir })
+    }
+}
+
+impl OutputSink for FileSink {
+    fn write(&mut self, name: &str, img: &DynamicImage) -> Result<()> {
+        let path = self.dir.join(name);
+        let format = ImageFormat::from_path(&path)
+            .with_context(|| format!("Unknown image format for {}", path.display()))?;
+        img.save_with_format(&path, format)
+            .with_context(|| format!("Failed to save image {}", path.display()))
+    }
+
+    fn write_with_resolution(&mut self, name: &str, img: &DynamicImage, pixels_per_meter: u32) -> Result<()> {
+        let path = self.dir.join(name);
+        match ImageFormat::from_path(&path) {
+            Ok(ImageFormat::Png) => save_png_with_resolution(img, pixels_per_meter, &path),
+            _ => self.write(name, img),
+        }
+    }
+
+    fn write_text(&mut self, name: &str, contents: &str) -> Result<()> {
+        let path = self.dir.join(name);
+        fs::write(&path, contents)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// Sink that keeps every image in memory, keyed by name.
+/// Useful for embedding the renderer and for tests.
+#[derive(Default)]
+pub struct MemorySink {
+    pub images: HashMap<String, DynamicImage>,
+    pub texts: HashMap<String, String>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl OutputSink for MemorySink {
+    fn write(&mut self, name: &str, img: &DynamicImage) -> Result<()> {
+        self.images.insert(name.to_string(), img.clone());
+        Ok(())
+    }
+
+    fn write_text(&mut self, name: &str, contents: &str) -> Result<()> {
+        self.texts.insert(name.to_string(), contents.to_string());
+        Ok(())
+    }
+}
+
+/// Sink that forwards every image to a user supplied callback.
+pub struct CallbackSink<F: FnMut(&str, &DynamicImage) -> Result<()>> {
+    pub callback: F,
+}
+
+impl<F: FnMut(&str, &DynamicImage) -> Result<()>> OutputSink for CallbackSink<F> {
+    fn write(&mut self, name: &str, img: &DynamicImage) -> Result<()> {
+        (self.callback)(name, img)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// A transparent RGBA image can be written in every format and read back with the same size.
+    fn test_output_formats_round_trip() {
+        let mut img = image::RgbaImage::from_pixel(37, 21, Rgba([200, 40, 10, 255]));
+        img.put_pixel(0, 0, Rgba([0, 0, 0, 0]));
+        let img = DynamicImage::ImageRgba8(img);
+        let dir = std::env::temp_dir().join("dem_output_formats");
+        let mut sink = FileSink::new(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for format in [OutputFormat::Png, OutputFormat::Jpeg, OutputFormat::Webp, OutputFormat::Tiff] {
+            let name = format.rename("output_rgb.png");
+            sink.write(&name, &format.prepare(&img, Rgba([255, 255, 255, 255]))).unwrap();
+            let reloaded = image::open(dir.join(&name)).unwrap();
+            assert_eq!((reloaded.width(), reloaded.height()), (37, 21), "{:?}", format);
+            if format == OutputFormat::Jpeg {
+                // the transparent corner becomes the white background
+                assert!(reloaded.to_rgb8().get_pixel(0, 0)[1] > 200);
+            }
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    /// A sink created on a missing nested directory creates it and writes the images into it.
+    fn test_create_missing_output_dir() {
+        let root = std::env::temp_dir().join("dem_missing_output_dir");
+        let _ = fs::remove_dir_all(&root);
+        let dir = root.join("nested").join("images");
+        let mut sink = FileSink::create(&dir).unwrap();
+        sink.write("output.png", &DynamicImage::ImageRgba8(RgbaImage::new(4, 3))).unwrap();
+        assert!(dir.join("output.png").is_file());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    /// The pHYs chunk of a written PNG declares the `--print-dpi` resolution, or the ground resolution by default.
+    fn test_png_physical_resolution() {
+        assert_eq!(pixels_per_meter(0.5, None), Some(2));
+        assert_eq!(pixels_per_meter(25.0, None), None);
+        assert_eq!(pixels_per_meter(25.0, Some(300.0)), Some(11811));
+
+        let dir = std::env::temp_dir().join("dem_png_phys");
+        let mut sink = FileSink::create(&dir).unwrap();
+        sink.write_with_resolution("output.png", &DynamicImage::ImageRgba8(RgbaImage::new(5, 4)), 11811).unwrap();
+        let decoder = png::Decoder::new(File::open(dir.join("output.png")).unwrap());
+        let reader = decoder.read_info().unwrap();
+        let dims = reader.info().pixel_dims.unwrap();
+        assert_eq!((dims.xppu, dims.yppu, dims.unit), (11811, 11811, png::Unit::Meter));
+        assert_eq!(image::open(dir.join("output.png")).unwrap().width(), 5);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    /// The longest side of the thumbnail is `max_dim` and the aspect ratio is kept.
+    fn test_make_thumbnail() {
+        let wide = make_thumbnail(&RgbaImage::new(400, 100), 64);
+        assert_eq!(wide.dimensions(), (64, 16));
+        let tall = make_thumbnail(&RgbaImage::new(30, 90), 64);
+        assert_eq!(tall.dimensions(), (21, 64));
+        assert_eq!(thumbnail_name("output_rgb_1_turbo.png"), "output_rgb_1_turbo_thumb.png");
+    }
+}
@@ -0,0 +1,69 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use image::{imageops, RgbaImage};
+
+/// Zoom level of a single-level pyramid: the smallest `z` whose `2^z` x `2^z` tile grid
+/// covers `tiles_x` x `tiles_y` tiles.
+pub fn native_zoom(tiles_x: u32, tiles_y: u32) -> u32 {
+    tiles_x.max(tiles_y).max(1).next_power_of_two().trailing_zeros()
+}
+
+/// Splits an image into a web map tile layer (`z/x/y.png`, as read by slippy maps).
+/// # Arguments
+/// * `image` - The full image, e.g. the colored hillshade.
+/// * `tile_size` - Side of the square tiles in pixels, usually 256.
+/// * `out_dir` - The directory the `z` folder is created in.
+///
+/// Only the native resolution is written, at the zoom level given by `native_zoom`; the image
+/// fills the tiles from the top-left one (x = 0, y = 0). Tiles on the right and bottom edges are
+/// padded with transparent pixels.
+pub fn write_tiles(image: &RgbaImage, tile_size: u32, out_dir: &str) -> Result<(), Box<dyn Error>> {
+    if tile_size == 0 {
+        return Err("tile size must be positive".into());
+    }
+    let tiles_x = image.width().div_ceil(tile_size);
+    let tiles_y = image.height().div_ceil(tile_size);
+    let zoom_dir = Path::new(out_dir).join(native_zoom(tiles_x, tiles_y).to_string());
+    for x in 0..tiles_x {
+        let column_dir = zoom_dir.join(x.to_string());
+        fs::create_dir_all(&column_dir).map_err(|e| format!("cannot create {}: {}", column_dir.display(), e))?;
+        for y in 0..tiles_y {
+            let (left, top) = (x * tile_size, y * tile_size);
+            let view = imageops::crop_imm(image, left, top, tile_size.min(image.width() - left), tile_size.min(image.height() - top));
+            let mut tile = RgbaImage::new(tile_size, tile_size);
+            imageops::replace(&mut tile, &*view, 0, 0);
+            let path = column_dir.join(format!("{}.png", y));
+            tile.save(&path).map_err(|e| format!("cannot write tile {}: {}", path.display(), e))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    /// A 513x257 image makes a 3x2 tile layer at zoom 2, with the overflow column padded transparent.
+    fn test_write_tiles_grid() {
+        let dir = std::env::temp_dir().join("dem_tiles_test");
+        let _ = fs::remove_dir_all(&dir);
+        let image = RgbaImage::from_pixel(513, 257, Rgba([10, 20, 30, 255]));
+        write_tiles(&image, 256, dir.to_str().unwrap()).unwrap();
+
+        let zoom_dir = dir.join("2");
+        let columns = fs::read_dir(&zoom_dir).unwrap().count();
+        assert_eq!(columns, 3);
+        for x in 0..3 {
+            assert_eq!(fs::read_dir(zoom_dir.join(x.to_string())).unwrap().count(), 2);
+        }
+        let corner = image::open(zoom_dir.join("2/1.png")).unwrap().to_rgba8();
+        assert_eq!(corner.dimensions(), (256, 256));
+        assert_eq!(corner.get_pixel(0, 0), &Rgba([10, 20, 30, 255]));
+        assert_eq!(corner.get_pixel(1, 0)[3], 0);
+        assert_eq!(corner.get_pixel(0, 1)[3], 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+
+/// Zone id of the cells that belong to no zone (NoData in the zone grid). They are left out of the statistics.
+pub const NO_ZONE: i32 = i32::MIN;
+
+/// Elevation statistics of one zone.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ZoneStat {
+    /// Number of cells of the zone with a valid elevation.
+    pub count: usize,
+    pub mean: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+/// Computes the elevation statistics of every zone of a zone grid.
+/// # Arguments
+/// * `data` - A vector of f32 representing the elevation data.
+/// * `zones` - The zone id of every cell, co-registered with `data`; `NO_ZONE` cells are skipped.
+/// * `width` - The width of the grid.
+/// * `height` - The height of the grid.
+/// # Returns
+/// * The count, mean, min and max elevation per zone id. NaN elevations are ignored, so a zone
+///   whose cells are all NaN is missing from the map.
+pub fn zonal_stats(data: &Vec<f32>, zones: &Vec<i32>, width: u32, height: u32) -> HashMap<i32, ZoneStat> {
+    // the mean is accumulated in f64 so large zones do not lose precision
+    let mut sums: HashMap<i32, (f64, ZoneStat)> = HashMap::new();
+    let cells = (width * height) as usize;
+    for (&value, &zone) in data.iter().zip(zones.iter()).take(cells) {
+        if value.is_nan() || zone == NO_ZONE {
+            continue;
+        }
+        let (sum, stat) = sums
+            .entry(zone)
+            .or_insert((0.0, ZoneStat { count: 0, mean: 0.0, min: f32::INFINITY, max: f32::NEG_INFINITY }));
+        *sum += value as f64;
+        stat.count += 1;
+        stat.min = stat.min.min(value);
+        stat.max = stat.max.max(value);
+    }
+    sums.into_iter()
+        .map(|(zone, (sum, stat))| (zone, ZoneStat { mean: (sum / stat.count as f64) as f32, ..stat }))
+        .collect()
+}
+
+/// Formats zone statistics as a text table, one zone per line in increasing id order.
+pub fn format_table(stats: &HashMap<i32, ZoneStat>) -> String {
+    let mut zones: Vec<&i32> = stats.keys().collect();
+    zones.sort();
+    let mut table = format!("{:>8} {:>10} {:>10} {:>10} {:>10}\n", "zone", "count", "mean", "min", "max");
+    for zone in zones {
+        let stat = &stats[zone];
+        table.push_str(&format!("{:>8} {:>10} {:>10.2} {:>10.2} {:>10.2}\n", zone, stat.count, stat.mean, stat.min, stat.max));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Two zones get their own count, mean and extremes; NaN elevations and unzoned cells are skipped.
+    fn test_zonal_stats_two_zones() {
+        let data = vec![
+            10.0, 20.0, 100.0,
+            30.0, f32::NAN, 200.0,
+            5.0, 7.0, 300.0,
+        ];
+        let zones = vec![
+            1, 1, 2,
+            1, 1, 2,
+            NO_ZONE, NO_ZONE, 2,
+        ];
+        let stats = zonal_stats(&data, &zones, 3, 3);
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[&1], ZoneStat { count: 3, mean: 20.0, min: 10.0, max: 30.0 });
+        assert_eq!(stats[&2], ZoneStat { count: 3, mean: 200.0, min: 100.0, max: 300.0 });
+
+        let table = format_table(&stats);
+        assert_eq!(table.lines().count(), 3);
+        assert!(table.lines().nth(1).unwrap().trim_start().starts_with("1 "));
+    }
+}
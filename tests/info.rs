@@ -0,0 +1,25 @@
+use std::fs;
+use std::process::Command;
+
+#[test]
+/// --info prints the parsed header fields and exits without rendering any image.
+fn test_info_prints_header() {
+    let work_dir = std::env::temp_dir().join("dem_info_test");
+    let _ = fs::remove_dir_all(&work_dir);
+    fs::create_dir_all(work_dir.join("src/output_img")).unwrap();
+    let content = "ncols 3\nnrows 2\nxllcenter 100\nyllcorner 200.5\ncellsize 2\nnodata_value -9999\n1 2 3\n4 5 -9999\n";
+    fs::write(work_dir.join("grid.asc"), content).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_dem"))
+        .args(["grid.asc", "--info"])
+        .current_dir(&work_dir)
+        .output()
+        .expect("Failed to start dem");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    for line in ["ncols: 3", "nrows: 2", "cellsize: 2", "nodata_value: -9999", "xllcorner: 99", "yllcorner: 200.5", "header_lines: 6"] {
+        assert!(stdout.lines().any(|l| l == line), "missing {:?} in:\n{}", line, stdout);
+    }
+    assert_eq!(fs::read_dir(work_dir.join("src/output_img")).unwrap().count(), 0);
+    fs::remove_dir_all(&work_dir).unwrap();
+}
@@ -0,0 +1,45 @@
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Builds an ASC grid large enough for every stage of the pipeline.
+fn ramp_asc(width: u32, height: u32) -> String {
+    let mut content = format!(
+        "ncols {}\nnrows {}\nxllcorner 0\nyllcorner 0\ncellsize 1\nnodata_value -9999\n",
+        width, height
+    );
+    for y in 0..height {
+        let row: Vec<String> = (0..width).map(|x| (x + y).to_string()).collect();
+        content.push_str(&row.join(" "));
+        content.push('\n');
+    }
+    content
+}
+
+#[test]
+/// It pipes an ASC grid through stdin using `-` and checks that the images are written.
+fn test_reads_dem_from_stdin() {
+    let work_dir = std::env::temp_dir().join("dem_stdin_test");
+    let _ = fs::remove_dir_all(&work_dir);
+    let output_dir = work_dir.join("src/output_img");
+    fs::create_dir_all(&output_dir).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_dem"))
+        .args(["-", "--quiet"])
+        .current_dir(&work_dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("Failed to start dem");
+    child.stdin.take().unwrap().write_all(ramp_asc(64, 64).as_bytes()).unwrap();
+    let status = child.wait().unwrap();
+    assert!(status.success());
+
+    let produced: Vec<String> = fs::read_dir(&output_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    assert!(produced.iter().any(|name| name.starts_with("output_rgb_")));
+    assert_eq!(produced.len(), 5);
+    fs::remove_dir_all(&work_dir).unwrap();
+}
@@ -0,0 +1,42 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Builds an ASC grid whose header has no `cellsize` line, which the QA report warns about.
+fn asc_without_cellsize(width: u32, height: u32) -> String {
+    let mut content = format!(
+        "ncols {}\nnrows {}\nxllcorner 0\nyllcorner 0\nunknown_key 1\nnodata_value -9999\n",
+        width, height
+    );
+    for y in 0..height {
+        let row: Vec<String> = (0..width).map(|x| (x + y).to_string()).collect();
+        content.push_str(&row.join(" "));
+        content.push('\n');
+    }
+    content
+}
+
+fn run_dem(work_dir: &Path, extra_args: &[&str]) -> bool {
+    Command::new(env!("CARGO_BIN_EXE_dem"))
+        .args(["grid.asc", "--quiet"])
+        .args(extra_args)
+        .current_dir(work_dir)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .expect("Failed to start dem")
+        .success()
+}
+
+#[test]
+/// A QA warning only fails the run when --strict is given.
+fn test_strict_exit_code_on_warnings() {
+    let work_dir = std::env::temp_dir().join("dem_strict_test");
+    let _ = fs::remove_dir_all(&work_dir);
+    fs::create_dir_all(work_dir.join("src/output_img")).unwrap();
+    fs::write(work_dir.join("grid.asc"), asc_without_cellsize(64, 64)).unwrap();
+
+    assert!(run_dem(&work_dir, &[]));
+    assert!(!run_dem(&work_dir, &["--strict"]));
+    fs::remove_dir_all(&work_dir).unwrap();
+}